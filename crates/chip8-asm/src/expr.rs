@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use crate::lexer::{Spanned, Token};
+use crate::{AsmError, Loc};
+
+/// The maximum depth of constants referring to other constants, deeper means a cycle
+const MAX_DEPTH: usize = 64;
+
+/// An arithmetic expression
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(i64),
+    /// A label or a constant
+    Symbol(String, Loc),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>, Loc),
+}
+
+/// A named value
+#[derive(Debug, Clone)]
+pub enum Symbol {
+    /// The address of a label
+    Label(u16),
+    /// A constant from `define`, evaluated when used
+    Const(Expr),
+}
+
+/// Binary operators from the lowest to the highest precedence
+const PRECEDENCE: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// A cursor over the tokens of a line
+pub struct Cursor<'a> {
+    tokens: &'a [Spanned],
+    pos: usize,
+    end: Loc,
+}
+
+impl<'a> Cursor<'a> {
+    /// Create a cursor, `end` is the location reported for errors at the end of the line
+    pub fn new(tokens: &'a [Spanned], end: Loc) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            end,
+        }
+    }
+
+    /// The next token without consuming it
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    /// The token after the next token
+    pub fn peek2(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos + 1).map(|t| &t.token)
+    }
+
+    /// The location of the next token, or the end of the line
+    pub fn loc(&self) -> Loc {
+        self.tokens
+            .get(self.pos)
+            .map_or_else(|| self.end.clone(), |t| t.loc.clone())
+    }
+
+    /// Consume the next token
+    pub fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        self.pos += token.is_some() as usize;
+        token
+    }
+
+    /// Consume the next token if it is the punctuation
+    pub fn eat(&mut self, punct: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Punct(p)) if *p == punct);
+        self.pos += found as usize;
+        found
+    }
+
+    /// Consume the punctuation or fail
+    pub fn expect(&mut self, punct: &str) -> Result<(), AsmError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.loc().error(format!("expected {}", punct)))
+        }
+    }
+
+    /// If all tokens are consumed
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// Parse an expression
+    pub fn expr(&mut self) -> Result<Expr, AsmError> {
+        self.binary(0)
+    }
+
+    /// Parse binary operators of the precedence level or higher
+    fn binary(&mut self, level: usize) -> Result<Expr, AsmError> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        loop {
+            let loc = self.loc();
+            let Some(op) = PRECEDENCE[level].iter().find(|op| self.eat(op)) else {
+                return Ok(lhs);
+            };
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), loc);
+        }
+    }
+
+    /// Parse unary operators, parentheses, numbers and symbols
+    fn unary(&mut self) -> Result<Expr, AsmError> {
+        let loc = self.loc();
+
+        for op in ["-", "~", "+"] {
+            if self.eat(op) {
+                return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+
+        match self.next() {
+            Some(Token::Punct("(")) => {
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Number(n)) => Ok(Expr::Number(*n)),
+            Some(Token::Ident(name)) => Ok(Expr::Symbol(name.clone(), loc)),
+            _ => Err(loc.error("expected an expression")),
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluate the expression
+    pub fn eval(&self, symbols: &HashMap<String, Symbol>) -> Result<i64, AsmError> {
+        self.eval_depth(symbols, 0)
+    }
+
+    fn eval_depth(&self, symbols: &HashMap<String, Symbol>, depth: usize) -> Result<i64, AsmError> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Symbol(name, loc) => match symbols.get(name) {
+                Some(Symbol::Label(addr)) => *addr as i64,
+                Some(Symbol::Const(_)) if depth >= MAX_DEPTH => {
+                    return Err(
+                        loc.error(format!("constant {} is defined in terms of itself", name))
+                    )
+                }
+                Some(Symbol::Const(expr)) => expr.eval_depth(symbols, depth + 1)?,
+                None => return Err(loc.error(format!("undefined symbol {}", name))),
+            },
+            Expr::Unary(op, expr) => {
+                let v = expr.eval_depth(symbols, depth)?;
+                match *op {
+                    "-" => v.wrapping_neg(),
+                    "~" => !v,
+                    _ => v,
+                }
+            }
+            Expr::Binary(op, lhs, rhs, loc) => {
+                let l = lhs.eval_depth(symbols, depth)?;
+                let r = rhs.eval_depth(symbols, depth)?;
+                match *op {
+                    "|" => l | r,
+                    "^" => l ^ r,
+                    "&" => l & r,
+                    "<<" | ">>" if !(0..64).contains(&r) => {
+                        return Err(loc.error(format!("invalid shift by {}", r)))
+                    }
+                    "<<" => l << r,
+                    ">>" => l >> r,
+                    "+" => l.wrapping_add(r),
+                    "-" => l.wrapping_sub(r),
+                    "*" => l.wrapping_mul(r),
+                    "/" | "%" if r == 0 => return Err(loc.error("division by zero")),
+                    "/" => l.wrapping_div(r),
+                    _ => l.wrapping_rem(r),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,127 @@
+use crate::Loc;
+
+/// A token of a source line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A mnemonic, directive, register, label or constant name
+    Ident(String),
+    /// A decimal, `0x` hexadecimal or `0b` binary number
+    Number(i64),
+    /// A string literal, only used by `db` and `include`
+    Str(Vec<u8>),
+    /// Punctuation or an operator
+    Punct(&'static str),
+}
+
+/// A token and the location it starts at
+#[derive(Debug, Clone)]
+pub struct Spanned {
+    pub token: Token,
+    pub loc: Loc,
+}
+
+/// Punctuation and operators, two character operators first so they are matched greedily
+const PUNCTUATION: [&str; 17] = [
+    "<<", ">>", ",", ":", "[", "]", "(", ")", "+", "-", "*", "/", "%", "&", "|", "^", "~",
+];
+
+/// Split a source line into tokens, ignoring whitespace and `;` comments
+///
+/// `loc` is the location of the start of the line.
+pub fn tokenize(line: &str, loc: &Loc) -> Result<Vec<Spanned>, crate::AsmError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let at = loc.at(pos + 1);
+
+        if c == ';' {
+            break;
+        } else if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+
+        let token = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let len = word_len(&chars[pos..]);
+            let ident = chars[pos..pos + len].iter().collect();
+            pos += len;
+            Token::Ident(ident)
+        } else if c.is_ascii_digit() {
+            let len = word_len(&chars[pos..]);
+            let word: String = chars[pos..pos + len]
+                .iter()
+                .filter(|c| **c != '_')
+                .collect();
+            pos += len;
+            Token::Number(
+                parse_number(&word).ok_or_else(|| at.error(format!("invalid number {}", word)))?,
+            )
+        } else if c == '"' {
+            let (string, len) =
+                parse_string(&chars[pos..]).ok_or_else(|| at.error("unterminated string"))?;
+            pos += len;
+            Token::Str(string)
+        } else {
+            let rest: String = chars[pos..(pos + 2).min(chars.len())].iter().collect();
+            let punct = PUNCTUATION
+                .iter()
+                .find(|p| rest.starts_with(**p))
+                .ok_or_else(|| at.error(format!("unexpected character {:?}", c)))?;
+            pos += punct.len();
+            Token::Punct(punct)
+        };
+
+        tokens.push(Spanned { token, loc: at });
+    }
+
+    Ok(tokens)
+}
+
+/// The length of the identifier or number at the start of the characters
+fn word_len(chars: &[char]) -> usize {
+    chars
+        .iter()
+        .take_while(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '.')
+        .count()
+}
+
+/// Parse a decimal, `0x` hexadecimal or `0b` binary number
+fn parse_number(word: &str) -> Option<i64> {
+    let lower = word.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+/// Parse a string literal starting with `"`, returns the bytes and the length of the literal
+///
+/// Supports the escapes `\"`, `\\`, `\n` and `\0`.
+fn parse_string(chars: &[char]) -> Option<(Vec<u8>, usize)> {
+    let mut out = String::new();
+    let mut pos = 1;
+
+    loop {
+        match *chars.get(pos)? {
+            '"' => return Some((out.into_bytes(), pos + 1)),
+            '\\' => {
+                out.push(match *chars.get(pos + 1)? {
+                    'n' => '\n',
+                    '0' => '\0',
+                    c => c,
+                });
+                pos += 2;
+            }
+            c => {
+                out.push(c);
+                pos += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,570 @@
+mod expr;
+mod lexer;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use chip8::Instruction;
+use expr::{Cursor, Expr, Symbol};
+use lexer::Token;
+
+/// The address roms are loaded at, the first assembled byte is placed here
+pub const ROM_START: u16 = 0x200;
+
+/// The maximum depth of nested includes, deeper means an include cycle
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The instruction mnemonics
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE", "SNE",
+    "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND", "DRW",
+    "SKP", "SKNP", "PLANE", "AUDIO", "PITCH",
+];
+
+/// Operand keywords and directives, reserved like the mnemonics and registers
+const RESERVED: [&str; 13] = [
+    "I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG", "DEFINE", "INCLUDE", "DB", "DW",
+];
+
+/// An error raised while assembling, with the location it was raised at
+///
+/// * `file`: The file the error is in, `None` for the source passed to [`assemble`] without a
+///   path
+/// * `line`: The line number, starting at 1
+/// * `col`: The column number, starting at 1
+/// * `message`: A description of the error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: Option<String>,
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// The source line an instruction was assembled from
+///
+/// * `addr`: The address of the instruction
+/// * `file`: The file the line is in, `None` for the source passed to [`assemble`] without a path
+/// * `line`: The line number, starting at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub file: Option<String>,
+    pub line: usize,
+}
+
+/// A location in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Loc {
+    file: Option<Rc<str>>,
+    line: usize,
+    col: usize,
+}
+
+impl Loc {
+    /// The location of a column on the same line
+    pub(crate) fn at(&self, col: usize) -> Loc {
+        Loc {
+            col,
+            ..self.clone()
+        }
+    }
+
+    /// Create an error at the location
+    pub(crate) fn error(&self, message: impl Into<String>) -> AsmError {
+        AsmError {
+            file: self.file.as_deref().map(String::from),
+            line: self.line,
+            col: self.col,
+            message: message.into(),
+        }
+    }
+}
+
+/// A register or a special operand of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    I,
+    /// `[I]`, the memory at I
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+}
+
+/// An operand of an instruction
+#[derive(Debug, Clone)]
+enum Operand {
+    /// A register v0-vF
+    Register(u8),
+    Keyword(Keyword),
+    /// `LONG expr`, the 16 bit address of XO-CHIP `F000 nnnn`
+    Long(Expr, Loc),
+    Expr(Expr, Loc),
+}
+
+/// An item of a `db` directive
+#[derive(Debug, Clone)]
+enum Data {
+    Expr(Expr, Loc),
+    Str(Vec<u8>),
+}
+
+/// Something that is assembled into bytes
+#[derive(Debug, Clone)]
+enum Item {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<Data>),
+    Words(Vec<(Expr, Loc)>),
+}
+
+/// An item and its location in the source
+struct Statement {
+    item: Item,
+    loc: Loc,
+}
+
+/// The state of the assembler between the two passes
+///
+/// The first pass parses the source, assigns addresses to every statement and collects the
+/// symbols. The second pass evaluates the expressions and emits the bytes.
+///
+/// * `statements`: The parsed statements in order
+/// * `symbols`: Labels and constants by name
+/// * `size`: The number of bytes assembled so far
+struct Assembler {
+    statements: Vec<Statement>,
+    symbols: HashMap<String, Symbol>,
+    size: usize,
+}
+
+/// Assemble source code into a rom that is loaded at [`ROM_START`]
+///
+/// `path` is the file the source was read from, used in errors and to resolve `include`s
+/// relative to it. Without a path includes are resolved relative to the working directory.
+///
+/// The source has one statement per line, optionally preceded by a `label:`. Comments start with
+/// `;`. Mnemonics, registers and directives are case insensitive, labels and constants are not.
+///
+/// * Instructions use the mnemonics of [`Instruction`], like `LD V1, 0x2a` or `DRW V0, V1, 5`
+/// * `define NAME expr` defines a constant
+/// * `db expr, "string", ...` emits bytes, `dw expr, ...` emits big endian words
+/// * `include "path"` assembles another file in place
+///
+/// Expressions are numbers, labels and constants combined with `+ - * / % & | ^ << >> ~` and
+/// parentheses, numbers are decimal, `0x` hexadecimal or `0b` binary.
+pub fn assemble(source: &str, path: Option<&Path>) -> Result<Vec<u8>, AsmError> {
+    assemble_with_map(source, path).map(|(rom, _)| rom)
+}
+
+/// Assemble like [`assemble`], also returning a source map with the line of every instruction in
+/// address order
+pub fn assemble_with_map(
+    source: &str,
+    path: Option<&Path>,
+) -> Result<(Vec<u8>, Vec<SourceLine>), AsmError> {
+    let mut asm = Assembler {
+        statements: Vec::new(),
+        symbols: HashMap::new(),
+        size: 0,
+    };
+
+    asm.parse(source, path, 0)?;
+    asm.emit()
+}
+
+impl Assembler {
+    /// Parse a source file into statements and symbols, the first pass
+    fn parse(&mut self, source: &str, path: Option<&Path>, depth: usize) -> Result<(), AsmError> {
+        let file: Option<Rc<str>> = path.map(|p| p.display().to_string().into());
+
+        for (n, line) in source.lines().enumerate() {
+            let loc = Loc {
+                file: file.clone(),
+                line: n + 1,
+                col: 1,
+            };
+            let tokens = lexer::tokenize(line, &loc)?;
+            let mut cursor = Cursor::new(&tokens, loc.at(line.chars().count() + 1));
+
+            // A label, optionally followed by a statement
+            if let (Some(Token::Ident(name)), Some(Token::Punct(":"))) =
+                (cursor.peek(), cursor.peek2())
+            {
+                let label_loc = cursor.loc();
+                cursor.next();
+                cursor.next();
+                let addr = self.next_addr(&label_loc)?;
+                self.define(name, Symbol::Label(addr), &label_loc)?;
+            }
+
+            let loc = cursor.loc();
+            let Some(token) = cursor.next() else {
+                continue;
+            };
+            let Token::Ident(word) = token else {
+                return Err(loc.error("expected an instruction or directive"));
+            };
+
+            let item = match word.to_ascii_lowercase().as_str() {
+                "define" => {
+                    let name_loc = cursor.loc();
+                    let Some(Token::Ident(name)) = cursor.next() else {
+                        return Err(name_loc.error("expected a constant name"));
+                    };
+                    let expr = cursor.expr()?;
+                    self.define(name, Symbol::Const(expr), &name_loc)?;
+                    None
+                }
+                "include" => {
+                    let path_loc = cursor.loc();
+                    let Some(Token::Str(include)) = cursor.next() else {
+                        return Err(path_loc.error("expected a quoted path"));
+                    };
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(loc.error("includes are nested too deeply"));
+                    }
+                    let include = String::from_utf8_lossy(include).into_owned();
+                    let include = match path.and_then(Path::parent) {
+                        Some(dir) => dir.join(include),
+                        None => PathBuf::from(include),
+                    };
+                    let source = std::fs::read_to_string(&include).map_err(|err| {
+                        path_loc.error(format!("could not read {}: {}", include.display(), err))
+                    })?;
+                    if !cursor.is_empty() {
+                        return Err(cursor.loc().error("expected the end of the line"));
+                    }
+                    self.parse(&source, Some(&include), depth + 1)?;
+                    None
+                }
+                "db" => {
+                    let mut data = Vec::new();
+                    loop {
+                        let item_loc = cursor.loc();
+                        data.push(match cursor.peek() {
+                            Some(Token::Str(string)) => {
+                                cursor.next();
+                                Data::Str(string.clone())
+                            }
+                            _ => Data::Expr(cursor.expr()?, item_loc),
+                        });
+                        if !cursor.eat(",") {
+                            break;
+                        }
+                    }
+                    Some(Item::Bytes(data))
+                }
+                "dw" => {
+                    let mut words = Vec::new();
+                    loop {
+                        let item_loc = cursor.loc();
+                        words.push((cursor.expr()?, item_loc));
+                        if !cursor.eat(",") {
+                            break;
+                        }
+                    }
+                    Some(Item::Words(words))
+                }
+                mnemonic => {
+                    let mnemonic = mnemonic.to_ascii_uppercase();
+                    if !MNEMONICS.contains(&mnemonic.as_str()) {
+                        return Err(loc.error(format!("unknown instruction {}", word)));
+                    }
+                    let mut operands = Vec::new();
+                    if !cursor.is_empty() {
+                        loop {
+                            operands.push(operand(&mut cursor)?);
+                            if !cursor.eat(",") {
+                                break;
+                            }
+                        }
+                    }
+                    Some(Item::Instruction { mnemonic, operands })
+                }
+            };
+
+            if !cursor.is_empty() {
+                return Err(cursor.loc().error("expected the end of the line"));
+            }
+
+            if let Some(item) = item {
+                self.next_addr(&loc)?;
+                self.size += item.size();
+                self.statements.push(Statement { item, loc });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The address of the next statement
+    fn next_addr(&self, loc: &Loc) -> Result<u16, AsmError> {
+        u16::try_from(ROM_START as usize + self.size)
+            .map_err(|_| loc.error("the program does not fit in memory"))
+    }
+
+    /// Define a label or constant, names can only be defined once
+    fn define(&mut self, name: &str, symbol: Symbol, loc: &Loc) -> Result<(), AsmError> {
+        if is_reserved(name) {
+            return Err(loc.error(format!("{} is a reserved name", name)));
+        }
+        if self.symbols.insert(name.to_string(), symbol).is_some() {
+            return Err(loc.error(format!("{} is already defined", name)));
+        }
+        Ok(())
+    }
+
+    /// Evaluate the expressions and emit the bytes of every statement and the source map, the
+    /// second pass
+    fn emit(&self) -> Result<(Vec<u8>, Vec<SourceLine>), AsmError> {
+        let mut rom = Vec::with_capacity(self.size);
+        let mut lines = Vec::new();
+
+        for statement in &self.statements {
+            match &statement.item {
+                Item::Instruction { mnemonic, operands } => {
+                    lines.push(SourceLine {
+                        addr: ROM_START + rom.len() as u16,
+                        file: statement.loc.file.as_deref().map(String::from),
+                        line: statement.loc.line,
+                    });
+                    let (instruction, long) =
+                        self.instruction(mnemonic, operands, &statement.loc)?;
+                    rom.extend_from_slice(&instruction.encode().to_be_bytes());
+                    if let Some(long) = long {
+                        rom.extend_from_slice(&long.to_be_bytes());
+                    }
+                }
+                Item::Bytes(data) => {
+                    for item in data {
+                        match item {
+                            Data::Expr(expr, loc) => rom.push(self.byte(expr, loc)?),
+                            Data::Str(string) => rom.extend_from_slice(string),
+                        }
+                    }
+                }
+                Item::Words(words) => {
+                    for (expr, loc) in words {
+                        let word = self.value(expr, loc, -0x8000, 0xffff, "a word")?;
+                        rom.extend_from_slice(&(word as u16).to_be_bytes());
+                    }
+                }
+            }
+        }
+
+        Ok((rom, lines))
+    }
+
+    /// Build the instruction from a mnemonic and its operands, and the address following it for
+    /// `LD I, LONG`
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[Operand],
+        loc: &Loc,
+    ) -> Result<(Instruction, Option<u16>), AsmError> {
+        use Instruction::*;
+        use Operand::{Expr as E, Keyword as K, Long, Register as V};
+
+        let instruction = match (mnemonic, operands) {
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("SCD", [E(n, at)]) => ScrollDown(self.nibble(n, at)?),
+            ("SCU", [E(n, at)]) => ScrollUp(self.nibble(n, at)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Low,
+            ("HIGH", []) => High,
+            ("JP", [E(addr, at)]) => Jp(self.addr(addr, at)?),
+            ("JP", [V(0), E(addr, at)]) => JpV0(self.addr(addr, at)?),
+            ("CALL", [E(addr, at)]) => Call(self.addr(addr, at)?),
+            ("SE", [V(x), V(y)]) => SeReg(*x, *y),
+            ("SE", [V(x), E(nn, at)]) => Se(*x, self.byte(nn, at)?),
+            ("SNE", [V(x), V(y)]) => SneReg(*x, *y),
+            ("SNE", [V(x), E(nn, at)]) => Sne(*x, self.byte(nn, at)?),
+            ("SAVE", [V(x), V(y)]) => SaveRange(*x, *y),
+            ("LOAD", [V(x), V(y)]) => LoadRange(*x, *y),
+            ("LD", [V(x), V(y)]) => LdReg(*x, *y),
+            ("LD", [V(x), E(nn, at)]) => Ld(*x, self.byte(nn, at)?),
+            ("LD", [K(Keyword::I), E(addr, at)]) => LdI(self.addr(addr, at)?),
+            ("LD", [K(Keyword::I), Long(addr, at)]) => {
+                let addr = self.value(addr, at, 0, 0xffff, "a 16 bit address")?;
+                return Ok((LdILong, Some(addr as u16)));
+            }
+            ("LD", [V(x), K(Keyword::Dt)]) => LdVxDt(*x),
+            ("LD", [V(x), K(Keyword::K)]) => LdKey(*x),
+            ("LD", [K(Keyword::Dt), V(x)]) => LdDt(*x),
+            ("LD", [K(Keyword::St), V(x)]) => LdSt(*x),
+            ("LD", [K(Keyword::F), V(x)]) => LdFont(*x),
+            ("LD", [K(Keyword::Hf), V(x)]) => LdBigFont(*x),
+            ("LD", [K(Keyword::B), V(x)]) => Bcd(*x),
+            ("LD", [K(Keyword::IndirectI), V(x)]) => Store(*x),
+            ("LD", [V(x), K(Keyword::IndirectI)]) => Load(*x),
+            ("LD", [K(Keyword::R), V(x)]) => StoreFlags(*x),
+            ("LD", [V(x), K(Keyword::R)]) => LoadFlags(*x),
+            ("ADD", [V(x), V(y)]) => AddReg(*x, *y),
+            ("ADD", [V(x), E(nn, at)]) => Add(*x, self.byte(nn, at)?),
+            ("ADD", [K(Keyword::I), V(x)]) => AddI(*x),
+            ("OR", [V(x), V(y)]) => Or(*x, *y),
+            ("AND", [V(x), V(y)]) => And(*x, *y),
+            ("XOR", [V(x), V(y)]) => Xor(*x, *y),
+            ("SUB", [V(x), V(y)]) => Sub(*x, *y),
+            ("SUBN", [V(x), V(y)]) => Subn(*x, *y),
+            ("SHR", [V(x), V(y)]) => Shr(*x, *y),
+            ("SHR", [V(x)]) => Shr(*x, *x),
+            ("SHL", [V(x), V(y)]) => Shl(*x, *y),
+            ("SHL", [V(x)]) => Shl(*x, *x),
+            ("RND", [V(x), E(nn, at)]) => Rnd(*x, self.byte(nn, at)?),
+            ("DRW", [V(x), V(y), E(n, at)]) => Drw {
+                x: *x,
+                y: *y,
+                n: self.nibble(n, at)?,
+            },
+            ("SKP", [V(x)]) => Skp(*x),
+            ("SKNP", [V(x)]) => Sknp(*x),
+            // Every nibble decodes as a plane mask, so accept them all to round trip any rom
+            ("PLANE", [E(n, at)]) => Plane(self.nibble(n, at)?),
+            ("AUDIO", []) => Audio,
+            ("PITCH", [V(x)]) => Pitch(*x),
+            _ => return Err(loc.error(format!("invalid operands for {}", mnemonic))),
+        };
+
+        Ok((instruction, None))
+    }
+
+    /// Evaluate an expression and check that it is in the range `min..=max`
+    fn value(
+        &self,
+        expr: &Expr,
+        loc: &Loc,
+        min: i64,
+        max: i64,
+        what: &str,
+    ) -> Result<i64, AsmError> {
+        let v = expr.eval(&self.symbols)?;
+        if (min..=max).contains(&v) {
+            Ok(v)
+        } else {
+            Err(loc.error(format!("{} does not fit in {}", v, what)))
+        }
+    }
+
+    /// Evaluate a byte, negative values are stored as two's complement
+    fn byte(&self, expr: &Expr, loc: &Loc) -> Result<u8, AsmError> {
+        Ok(self.value(expr, loc, -0x80, 0xff, "a byte")? as u8)
+    }
+
+    fn nibble(&self, expr: &Expr, loc: &Loc) -> Result<u8, AsmError> {
+        Ok(self.value(expr, loc, 0, 0xf, "a nibble")? as u8)
+    }
+
+    /// Evaluate a 12 bit address
+    fn addr(&self, expr: &Expr, loc: &Loc) -> Result<u16, AsmError> {
+        Ok(self.value(expr, loc, 0, 0xfff, "a 12 bit address")? as u16)
+    }
+}
+
+impl Item {
+    /// The number of bytes the item assembles to, known before evaluating expressions
+    fn size(&self) -> usize {
+        match self {
+            Item::Instruction { operands, .. } => match operands.as_slice() {
+                [Operand::Keyword(Keyword::I), Operand::Long(..)] => 4,
+                _ => 2,
+            },
+            Item::Bytes(data) => data
+                .iter()
+                .map(|item| match item {
+                    Data::Expr(..) => 1,
+                    Data::Str(string) => string.len(),
+                })
+                .sum(),
+            Item::Words(words) => 2 * words.len(),
+        }
+    }
+}
+
+/// Parse an operand of an instruction
+fn operand(cursor: &mut Cursor) -> Result<Operand, AsmError> {
+    let loc = cursor.loc();
+
+    if cursor.eat("[") {
+        match cursor.next() {
+            Some(Token::Ident(i)) if i.eq_ignore_ascii_case("i") => (),
+            _ => return Err(loc.error("expected [I]")),
+        }
+        cursor.expect("]")?;
+        return Ok(Operand::Keyword(Keyword::IndirectI));
+    }
+
+    if let Some(Token::Ident(word)) = cursor.peek() {
+        let upper = word.to_ascii_uppercase();
+
+        if upper == "LONG" {
+            cursor.next();
+            let loc = cursor.loc();
+            return Ok(Operand::Long(cursor.expr()?, loc));
+        }
+
+        if let Some(register) = register(&upper) {
+            cursor.next();
+            return Ok(Operand::Register(register));
+        }
+
+        let keyword = match upper.as_str() {
+            "I" => Some(Keyword::I),
+            "DT" => Some(Keyword::Dt),
+            "ST" => Some(Keyword::St),
+            "K" => Some(Keyword::K),
+            "F" => Some(Keyword::F),
+            "HF" => Some(Keyword::Hf),
+            "B" => Some(Keyword::B),
+            "R" => Some(Keyword::R),
+            _ => None,
+        };
+        if let Some(keyword) = keyword {
+            cursor.next();
+            return Ok(Operand::Keyword(keyword));
+        }
+    }
+
+    Ok(Operand::Expr(cursor.expr()?, loc))
+}
+
+/// The number of a register name like `V3` or `VA`, case insensitive
+fn register(name: &str) -> Option<u8> {
+    let digit = name.to_ascii_uppercase().strip_prefix('V')?.to_string();
+    (digit.len() == 1)
+        .then(|| u8::from_str_radix(&digit, 16).ok())
+        .flatten()
+}
+
+/// If a name is a register, keyword, mnemonic or directive and can't be a label or constant
+fn is_reserved(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    register(&upper).is_some()
+        || MNEMONICS.contains(&upper.as_str())
+        || RESERVED.contains(&upper.as_str())
+}
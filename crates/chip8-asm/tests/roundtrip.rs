@@ -0,0 +1,219 @@
+use std::collections::HashSet;
+
+use chip8::{Instruction, Variant};
+use chip8_asm::{assemble, assemble_with_map, SourceLine};
+use chip8_disasm::disassemble;
+
+/// Disassemble a rom and assemble it again
+fn roundtrip(rom: &[u8]) {
+    let source = disassemble(rom, Variant::XoChip).unwrap();
+    let assembled = assemble(&source, None).unwrap_or_else(|err| panic!("{}\n{}", err, source));
+    assert_eq!(assembled, rom, "\n{}", source);
+}
+
+/// One of every instruction that continues to the next, with the given operands
+fn instructions(x: u8, y: u8, nn: u8, n: u8, addr: u16) -> Vec<Instruction> {
+    use Instruction::*;
+
+    vec![
+        ScrollDown(n),
+        ScrollUp(n),
+        Cls,
+        ScrollRight,
+        ScrollLeft,
+        Low,
+        High,
+        Call(addr),
+        Se(x, nn),
+        Sne(x, nn),
+        SeReg(x, y),
+        SaveRange(x, y),
+        LoadRange(x, y),
+        Ld(x, nn),
+        Add(x, nn),
+        LdReg(x, y),
+        Or(x, y),
+        And(x, y),
+        Xor(x, y),
+        AddReg(x, y),
+        Sub(x, y),
+        Shr(x, y),
+        Subn(x, y),
+        Shl(x, y),
+        SneReg(x, y),
+        LdI(addr),
+        Rnd(x, nn),
+        Drw { x, y, n },
+        Skp(x),
+        Sknp(x),
+        LdILong,
+        Plane(n),
+        Audio,
+        LdVxDt(x),
+        LdVxDtAlias(x),
+        LdKey(x),
+        LdDt(x),
+        LdSt(x),
+        AddI(x),
+        LdFont(x),
+        LdBigFont(x),
+        Bcd(x),
+        Pitch(x),
+        Store(x),
+        Load(x),
+        StoreFlags(x),
+        LoadFlags(x),
+    ]
+}
+
+/// Encode instructions into a rom, long loads point at `0x1234`
+fn encode(instructions: &[Instruction]) -> Vec<u8> {
+    instructions
+        .iter()
+        .flat_map(|instruction| match instruction {
+            Instruction::LdILong => vec![0xf0, 0x00, 0x12, 0x34],
+            _ => instruction.encode().to_be_bytes().to_vec(),
+        })
+        .collect()
+}
+
+#[test]
+fn every_instruction() {
+    // Every instruction with low, mixed and high operands, followed by a jump back to the start
+    let mut code = instructions(0x0, 0x0, 0x00, 0x0, 0x000);
+    code.extend(instructions(0x3, 0xa, 0x5c, 0x7, 0x2ab));
+    code.extend(instructions(0xf, 0xf, 0xff, 0xf, 0xfff));
+    code.push(Instruction::Jp(0x200));
+    let rom = encode(&code);
+    let source = disassemble(&rom, Variant::XoChip).unwrap();
+    assert!(!source.contains("db "), "Not traced as code\n{}", source);
+    roundtrip(&rom);
+
+    // The instructions that end a path
+    for end in [
+        Instruction::Ret,
+        Instruction::Exit,
+        Instruction::Jp(0x000),
+        Instruction::Jp(0xfff),
+        Instruction::JpV0(0x000),
+        Instruction::JpV0(0xabc),
+    ] {
+        let rom = encode(&[Instruction::Cls, end]);
+        assert!(!disassemble(&rom, Variant::XoChip).unwrap().contains("db "));
+        roundtrip(&rom);
+    }
+
+    // Every instruction the decoder knows is covered
+    code.extend([Instruction::Ret, Instruction::Exit, Instruction::JpV0(0)]);
+    let covered: HashSet<_> = code.iter().map(std::mem::discriminant).collect();
+    for opcode in 0..=u16::MAX {
+        if let Ok(instruction) = Instruction::decode(opcode) {
+            assert!(
+                covered.contains(&std::mem::discriminant(&instruction)),
+                "{:?} is not round tripped",
+                instruction
+            );
+        }
+    }
+}
+
+#[test]
+fn code_and_data() {
+    let rom = [
+        0x00, 0xe0, // CLS
+        0xa2, 0x0e, // LD I, sprite
+        0x22, 0x0c, // CALL sub
+        0x30, 0x01, // SE V0, 1
+        0x12, 0x02, // JP 0x202
+        0x12, 0x0a, // JP 0x20a
+        0xd0, 0x15, // sub: DRW V0, V1, 5
+        0x00, 0xee, // RET
+        0xff, 0x81, 0x81, 0x81, 0xff, // sprite
+        0x12, 0x13, // Data that looks like a jump into the middle of itself
+    ];
+
+    roundtrip(&rom);
+    roundtrip(&(0..=255).collect::<Vec<u8>>());
+
+    // The duplicate of LD V3, DT has no mnemonic and is written as a word
+    roundtrip(&[0xf3, 0x11, 0x12, 0x02]);
+
+    // Plane masks above 3 decode like any other nibble
+    roundtrip(&[0xf6, 0x01, 0xff, 0x01, 0x12, 0x04]);
+}
+
+#[test]
+fn syntax() {
+    let source = r#"
+        define COUNT 3
+        define SPRITE_SIZE end - sprite
+
+        start:
+            ld v0, COUNT * 2 + 1     ; Comments are ignored
+            LD I, sprite
+            drw V0, v1, SPRITE_SIZE
+        loop: jp loop
+        sprite:
+            db 0b11110000, 0x90, -1, "ab"
+            dw 0x1234, start
+        end:
+    "#;
+
+    assert_eq!(
+        assemble(source, None).unwrap(),
+        [
+            0x60, 0x07, 0xa2, 0x08, 0xd0, 0x19, 0x12, 0x06, 0xf0, 0x90, 0xff, b'a', b'b', 0x12,
+            0x34, 0x02, 0x00
+        ]
+    );
+}
+
+#[test]
+fn errors() {
+    let error = |source: &str| {
+        let err = assemble(source, None).unwrap_err();
+        (err.line, err.col, err.message)
+    };
+
+    assert_eq!(
+        error("CLS\n  JP nowhere"),
+        (2, 6, "undefined symbol nowhere".to_string())
+    );
+    assert_eq!(
+        error("LD V0, 256"),
+        (1, 8, "256 does not fit in a byte".to_string())
+    );
+    assert_eq!(
+        error("  FOO V0"),
+        (1, 3, "unknown instruction FOO".to_string())
+    );
+    assert_eq!(
+        error("a:\na: CLS"),
+        (2, 1, "a is already defined".to_string())
+    );
+    assert_eq!(
+        error("LD V0, V1, V2"),
+        (1, 1, "invalid operands for LD".to_string())
+    );
+    assert_eq!(
+        error("define X Y\ndefine Y X\nLD V0, X"),
+        (
+            2,
+            10,
+            "constant X is defined in terms of itself".to_string()
+        )
+    );
+}
+
+#[test]
+fn source_map() {
+    let (_, lines) =
+        assemble_with_map("start:\n  CLS\n  db 1, 2\n  LD I, LONG start\n", None).unwrap();
+
+    let line = |addr, line| SourceLine {
+        addr,
+        file: None,
+        line,
+    };
+    assert_eq!(lines, [line(0x200, 2), line(0x204, 4)]);
+}
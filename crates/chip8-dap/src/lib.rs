@@ -0,0 +1,429 @@
+mod program;
+mod protocol;
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use chip8::{Chip8, FrameEnd, Instruction, Timing, Variant, DEFAULT_IPF};
+use serde_json::{json, Value};
+
+use program::Program;
+
+/// The time between frames, the timers tick once per frame
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// The only thread, CHIP-8 has a single one
+const THREAD_ID: u64 = 1;
+
+/// The variables reference of the registers scope
+const REGISTERS: u64 = 1;
+/// The variables reference of the timers scope
+const TIMERS: u64 = 2;
+
+/// How the emulator is running, stepping out of the outermost subroutine runs like continuing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Run until a breakpoint
+    Continue,
+    /// Run to the start of the next source line, entering subroutines
+    StepIn,
+    /// Run to the start of the next source line in the same or an outer subroutine
+    StepOver { depth: usize },
+    /// Run until returning from the current subroutine
+    StepOut { depth: usize },
+}
+
+/// A Debug Adapter Protocol session debugging a single program
+///
+/// * `output`: Where responses and events are written
+/// * `seq`: The sequence number of the last message sent
+/// * `target`: The launched program and emulator, `None` before `launch`
+/// * `stop_on_entry`: If the program stops before its first instruction once configured
+/// * `breakpoints`: The breakpoint addresses of each source file
+/// * `mode`: How the emulator is running, `None` while stopped
+/// * `resumed`: If the emulator was just resumed, so it runs the breakpoint it stopped at
+/// * `ipf`: The number of instructions run per frame, ignored with the VIP timing
+/// * `next_frame`: When the next frame of cycles runs while running
+struct Session<W: Write> {
+    output: W,
+    seq: u64,
+    target: Option<(Program, Chip8)>,
+    stop_on_entry: bool,
+    breakpoints: HashMap<PathBuf, Vec<u16>>,
+    mode: Option<Mode>,
+    resumed: bool,
+    ipf: u32,
+    next_frame: Instant,
+}
+
+/// Run a debug adapter, reading requests from `input` and writing responses and events to
+/// `output` until the client disconnects
+///
+/// Supports launching `.8o` Octo sources, `.asm` assembly sources and plain roms, breakpoints by
+/// source line, stack traces, registers and timers as variables, and stepping in, over and out of
+/// subroutines. The program runs 60 frames a second with [`Chip8::run_frame`], like the other
+/// frontends.
+pub fn run(input: impl Read + Send + 'static, output: impl Write) -> io::Result<()> {
+    // Read requests on a separate thread so they can arrive while the program runs
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        while let Ok(Some(message)) = protocol::read_message(&mut reader) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        output,
+        seq: 0,
+        target: None,
+        stop_on_entry: false,
+        breakpoints: HashMap::new(),
+        mode: None,
+        resumed: false,
+        ipf: DEFAULT_IPF,
+        next_frame: Instant::now(),
+    };
+
+    loop {
+        let message = if session.mode.is_some() {
+            let timeout = session.next_frame.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(timeout) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => {
+                    session.frame()?;
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        } else {
+            match rx.recv() {
+                Ok(message) => message,
+                Err(_) => return Ok(()),
+            }
+        };
+
+        if !session.request(&message)? {
+            return Ok(());
+        }
+    }
+}
+
+impl<W: Write> Session<W> {
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        protocol::write_message(&mut self.output, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// Handle a request, returns false once the client disconnects
+    fn request(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => Ok(json!({})),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS, "expensive": false },
+            ] })),
+            "variables" => self.variables(args),
+            "continue" => self
+                .resume(Mode::Continue)
+                .map(|_| json!({ "allThreadsContinued": true })),
+            "next" => self
+                .depth()
+                .and_then(|depth| self.resume(Mode::StepOver { depth })),
+            "stepIn" => self.resume(Mode::StepIn),
+            "stepOut" => self
+                .depth()
+                .and_then(|depth| self.resume(Mode::StepOut { depth })),
+            "pause" => Ok(json!({})),
+            "disconnect" => Ok(json!({})),
+            _ => Err(format!("Unsupported request {}", command)),
+        };
+
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)?;
+
+        // Events that follow the response
+        match command {
+            "launch" if self.target.is_some() => self.event("initialized", json!({}))?,
+            "configurationDone" if self.target.is_some() => {
+                if self.stop_on_entry {
+                    self.stop("entry", None)?;
+                } else {
+                    self.resume(Mode::Continue).ok();
+                }
+            }
+            "pause" if self.mode.is_some() => self.stop("pause", None)?,
+            "disconnect" => return Ok(false),
+            _ => (),
+        }
+
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["program"]
+            .as_str()
+            .ok_or("launch needs the path of a program")?;
+        let variant = match args["variant"].as_str() {
+            Some(name) => Variant::from_name(name).ok_or_else(|| {
+                format!("Unknown variant {name}, expected chip8, schip or xochip")
+            })?,
+            None => Variant::XoChip,
+        };
+
+        let program = Program::load(Path::new(path))?;
+        let mut builder = Chip8::builder(program.rom.clone()).variant(variant);
+        if let Some(seed) = args["seed"].as_u64() {
+            builder = builder.seed(seed);
+        }
+        if let Some(name) = args["timing"].as_str() {
+            let timing = Timing::from_name(name)
+                .ok_or_else(|| format!("Unknown timing {name}, expected fixed or vip"))?;
+            builder = builder.timing(timing);
+        }
+        self.ipf = match args["ipf"].as_u64() {
+            Some(ipf) => u32::try_from(ipf)
+                .ok()
+                .filter(|ipf| *ipf > 0)
+                .ok_or("ipf needs a positive number of instructions")?,
+            None => DEFAULT_IPF,
+        };
+
+        let chip8 = builder
+            .build()
+            .map_err(|err| format!("Could not load {path}: {err}"))?;
+
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.target = Some((program, chip8));
+        Ok(json!({}))
+    }
+
+    fn target(&self) -> Result<&(Program, Chip8), String> {
+        self.target
+            .as_ref()
+            .ok_or_else(|| "No program launched".to_string())
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let (program, _) = self.target()?;
+        let path = args["source"]["path"]
+            .as_str()
+            .ok_or("setBreakpoints needs a source path")?;
+        let lines: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|bp| bp["line"].as_u64())
+            .map(|line| line as usize)
+            .collect();
+
+        let mut addrs = Vec::new();
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|line| match program.addrs_of(Path::new(path), *line) {
+                Some((found, found_addrs)) => {
+                    addrs.extend(found_addrs);
+                    json!({ "verified": true, "line": found })
+                }
+                None => json!({
+                    "verified": false,
+                    "line": line,
+                    "message": "No instructions at or after this line",
+                }),
+            })
+            .collect();
+
+        self.breakpoints.insert(program::canonical(path), addrs);
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let (program, chip8) = self.target()?;
+
+        // The innermost frame is at pc, the callers at the calls they will return after
+        let pcs = std::iter::once(chip8.pc())
+            .chain(chip8.stack().iter().rev().map(|ret| ret.wrapping_sub(2)));
+
+        let frames: Vec<Value> = pcs
+            .enumerate()
+            .map(|(id, pc)| {
+                let mem = chip8.memory();
+                let opcode = mem
+                    .get(pc as usize..pc as usize + 2)
+                    .map(|word| u16::from_be_bytes([word[0], word[1]]));
+                let name = match opcode.map(Instruction::decode) {
+                    Some(Ok(instruction)) => format!("{:#06x} {}", pc, instruction),
+                    _ => format!("{:#06x}", pc),
+                };
+
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#06x}", pc),
+                });
+                if let Some(line) = program.line_at(pc) {
+                    frame["line"] = json!(line.line);
+                    frame["column"] = json!(1);
+                    frame["source"] = json!({
+                        "name": line.path.file_name().map(|name| name.to_string_lossy()),
+                        "path": line.path.display().to_string(),
+                    });
+                }
+                frame
+            })
+            .collect();
+
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let (_, chip8) = self.target()?;
+
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        let variables: Vec<Value> = match args["variablesReference"].as_u64() {
+            Some(REGISTERS) => chip8
+                .registers()
+                .iter()
+                .enumerate()
+                .map(|(n, v)| variable(format!("v{:X}", n), format!("{:#04x}", v)))
+                .chain([
+                    variable("I".to_string(), format!("{:#06x}", chip8.i())),
+                    variable("pc".to_string(), format!("{:#06x}", chip8.pc())),
+                ])
+                .collect(),
+            Some(TIMERS) => vec![
+                variable("delay".to_string(), chip8.delay_timer().to_string()),
+                variable("sound".to_string(), chip8.sound_timer().to_string()),
+            ],
+            _ => return Err("Unknown variables reference".to_string()),
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// The depth of the subroutine stack
+    fn depth(&self) -> Result<usize, String> {
+        Ok(self.target()?.1.stack().len())
+    }
+
+    /// Start running the emulator, the first frame runs right away
+    fn resume(&mut self, mode: Mode) -> Result<Value, String> {
+        self.target()?;
+        self.mode = Some(mode);
+        self.resumed = true;
+        self.next_frame = Instant::now();
+        Ok(json!({}))
+    }
+
+    /// Stop running and tell the client why
+    fn stop(&mut self, reason: &str, description: Option<String>) -> io::Result<()> {
+        self.mode = None;
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(description) = description {
+            body["description"] = json!(description.clone());
+            body["text"] = json!(description);
+        }
+        self.event("stopped", body)
+    }
+
+    /// Run a frame with [`Chip8::run_frame_with`], stopping early at breakpoints and finished
+    /// steps
+    fn frame(&mut self) -> io::Result<()> {
+        self.next_frame += FRAME;
+        // Don't try to catch up after falling behind
+        self.next_frame = self.next_frame.max(Instant::now());
+
+        let Some(mode) = self.mode else {
+            return Ok(());
+        };
+        let Some((program, chip8)) = &mut self.target else {
+            return Ok(());
+        };
+
+        let breakpoints = &self.breakpoints;
+        let resumed = &mut self.resumed;
+        let mut reason = "step";
+        let result = chip8.run_frame_with(self.ipf, |chip8| {
+            // The instruction the emulator resumed at runs, even with a breakpoint
+            if std::mem::take(resumed) {
+                return true;
+            }
+
+            // Steps stop at the start of a line, or after every instruction without a source
+            let at_line = !program.has_source() || program.is_line_start(chip8.pc());
+            let depth = chip8.stack().len();
+            let done = match mode {
+                Mode::Continue => false,
+                Mode::StepIn => at_line,
+                Mode::StepOver { depth: start } => at_line && depth <= start,
+                Mode::StepOut { depth: start } => depth < start,
+            };
+            if done {
+                return false;
+            }
+
+            let pc = chip8.pc();
+            if breakpoints.values().any(|addrs| addrs.contains(&pc)) {
+                reason = "breakpoint";
+                return false;
+            }
+            true
+        });
+
+        match result {
+            Ok(frame) => match frame.end {
+                FrameEnd::Interrupted => self.stop(reason, None),
+                FrameEnd::Exited => {
+                    self.mode = None;
+                    self.event("exited", json!({ "exitCode": 0 }))?;
+                    self.event("terminated", json!({}))
+                }
+                FrameEnd::Finished | FrameEnd::Watchpoint { .. } => Ok(()),
+            },
+            Err(err) => {
+                let message = err.to_string();
+                self.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("{}\n", message) }),
+                )?;
+                self.stop("exception", Some(message))
+            }
+        }
+    }
+}
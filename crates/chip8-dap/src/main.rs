@@ -0,0 +1,7 @@
+/// Run a debug adapter over stdin and stdout, started by an editor
+fn main() {
+    if let Err(err) = chip8_dap::run(std::io::stdin(), std::io::stdout().lock()) {
+        eprintln!("Debug adapter failed: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// The source line an instruction came from
+///
+/// * `addr`: The address of the instruction
+/// * `path`: The canonical path of the source file
+/// * `line`: The line number, starting at 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+/// A program loaded for debugging and the source map of its instructions
+///
+/// * `rom`: The rom to run
+/// * `lines`: The source line of every instruction in address order, empty for plain roms
+/// * `starts`: The addresses of the first instruction of every line, stepping stops at these
+pub struct Program {
+    pub rom: Vec<u8>,
+    lines: Vec<Line>,
+    starts: BTreeSet<u16>,
+}
+
+impl Program {
+    /// Load a program, `.8o` files are compiled as Octo, `.asm` files are assembled and anything
+    /// else is loaded as a rom
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let read_source = || {
+            std::fs::read_to_string(path)
+                .map_err(|err| format!("Could not read {}: {}", path.display(), err))
+        };
+
+        let (rom, lines) = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("8o") => {
+                let (rom, lines) = chip8_octo::compile_with_map(&read_source()?)
+                    .map_err(|err| format!("{}:{}", path.display(), err))?;
+                let path = canonical(path);
+                let lines = lines
+                    .into_iter()
+                    .map(|line| Line {
+                        addr: line.addr,
+                        path: path.clone(),
+                        line: line.line,
+                    })
+                    .collect();
+                (rom, lines)
+            }
+            Some("asm") => {
+                let (rom, lines) = chip8_asm::assemble_with_map(&read_source()?, Some(path))
+                    .map_err(|err| err.to_string())?;
+                let lines = lines
+                    .into_iter()
+                    .map(|line| Line {
+                        addr: line.addr,
+                        path: line.file.map_or_else(|| canonical(path), canonical),
+                        line: line.line,
+                    })
+                    .collect();
+                (rom, lines)
+            }
+            _ => {
+                let rom = std::fs::read(path)
+                    .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+                (rom, Vec::new())
+            }
+        };
+
+        Ok(Self::new(rom, lines))
+    }
+
+    fn new(rom: Vec<u8>, lines: Vec<Line>) -> Self {
+        let starts = lines
+            .iter()
+            .enumerate()
+            .filter(|(n, line)| {
+                // Lines compiling to several instructions only start at the first one
+                *n == 0 || {
+                    let prev = &lines[n - 1];
+                    prev.path != line.path || prev.line != line.line || prev.addr + 4 < line.addr
+                }
+            })
+            .map(|(_, line)| line.addr)
+            .collect();
+
+        Self { rom, lines, starts }
+    }
+
+    /// If the program has a source map
+    pub fn has_source(&self) -> bool {
+        !self.lines.is_empty()
+    }
+
+    /// The source line of the instruction at an address
+    pub fn line_at(&self, addr: u16) -> Option<&Line> {
+        let n = self.lines.partition_point(|line| line.addr < addr);
+        self.lines.get(n).filter(|line| line.addr == addr)
+    }
+
+    /// If an address is the first instruction of a source line
+    pub fn is_line_start(&self, addr: u16) -> bool {
+        self.starts.contains(&addr)
+    }
+
+    /// The addresses of a line in a source file, or of the next line after it with instructions
+    /// if it has none, and the line they are on
+    pub fn addrs_of(&self, path: &Path, line: usize) -> Option<(usize, Vec<u16>)> {
+        let path = canonical(path);
+        let found = self
+            .lines
+            .iter()
+            .filter(|l| l.path == path && l.line >= line)
+            .map(|l| l.line)
+            .min()?;
+
+        let addrs = self
+            .lines
+            .iter()
+            .filter(|l| l.path == path && l.line == found && self.starts.contains(&l.addr))
+            .map(|l| l.addr)
+            .collect();
+        Some((found, addrs))
+    }
+}
+
+/// The canonical form of a path so paths from the editor and the assembler can be compared
+pub fn canonical(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
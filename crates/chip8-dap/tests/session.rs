@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, PipeReader, PipeWriter, Read, Write};
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+
+use serde_json::{json, Value};
+
+/// A scripted editor talking to a debug adapter running on another thread
+struct Client {
+    input: PipeWriter,
+    output: BufReader<PipeReader>,
+    seq: u64,
+    events: VecDeque<Value>,
+    adapter: JoinHandle<()>,
+}
+
+impl Client {
+    fn start() -> Self {
+        let (input_reader, input) = std::io::pipe().unwrap();
+        let (output, output_writer) = std::io::pipe().unwrap();
+        let adapter = thread::spawn(move || chip8_dap::run(input_reader, output_writer).unwrap());
+
+        Self {
+            input,
+            output: BufReader::new(output),
+            seq: 0,
+            events: VecDeque::new(),
+            adapter,
+        }
+    }
+
+    fn read(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.output.read_line(&mut header).unwrap();
+            match header.trim_end() {
+                "" => break,
+                header => length = header["Content-Length: ".len()..].parse().unwrap(),
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.output.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Send a request and wait for its response, keeping the events sent before it
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let body = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(self.input, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+
+        loop {
+            let message = self.read();
+            if message["type"] == "response" {
+                assert_eq!(message["request_seq"], self.seq);
+                return message;
+            }
+            self.events.push_back(message);
+        }
+    }
+
+    /// Send a request that must succeed and get the body of the response
+    fn ok(&mut self, command: &str, arguments: Value) -> Value {
+        let response = self.request(command, arguments);
+        assert_eq!(response["success"], true, "{}", response);
+        response["body"].clone()
+    }
+
+    /// Wait for an event, skipping other events
+    fn event(&mut self, event: &str) -> Value {
+        loop {
+            let message = self.events.pop_front().unwrap_or_else(|| self.read());
+            if message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    /// Wait for the program to stop and check the reason and where it stopped
+    fn stopped(&mut self, reason: &str) -> Vec<(Value, Value)> {
+        assert_eq!(self.event("stopped")["reason"], reason);
+        self.ok("stackTrace", json!({ "threadId": 1 }))["stackFrames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| {
+                (
+                    frame["line"].clone(),
+                    frame["instructionPointerReference"].clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn disconnect(mut self) {
+        self.ok("disconnect", json!({}));
+        self.adapter.join().unwrap();
+    }
+}
+
+/// Write a source file to a temporary directory
+fn source(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chip8-dap-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn frame(line: u64, pc: &str) -> (Value, Value) {
+    (json!(line), json!(pc))
+}
+
+#[test]
+fn assembly() {
+    let path = source(
+        "program.asm",
+        b"; Counts in v0 and copies it to v1 in a subroutine
+start:
+    LD V0, 0
+loop:
+    ADD V0, 1
+    CALL copy
+    JP loop
+copy:
+    LD V1, V0
+    RET
+",
+    );
+    let source = json!({ "path": path });
+
+    let mut client = Client::start();
+    client.ok("initialize", json!({ "adapterID": "chip8" }));
+    client.ok("launch", json!({ "program": path, "stopOnEntry": true }));
+    client.event("initialized");
+
+    let breakpoints = client.ok(
+        "setBreakpoints",
+        json!({ "source": source, "breakpoints": [{ "line": 4 }, { "line": 9 }, { "line": 20 }] }),
+    );
+    assert_eq!(
+        breakpoints["breakpoints"],
+        json!([
+            { "verified": true, "line": 5 },
+            { "verified": true, "line": 9 },
+            { "verified": false, "line": 20, "message": "No instructions at or after this line" },
+        ])
+    );
+
+    client.ok("configurationDone", json!({}));
+    assert_eq!(client.stopped("entry"), [frame(3, "0x0200")]);
+
+    client.ok("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.stopped("breakpoint"), [frame(5, "0x0202")]);
+
+    client.ok(
+        "setBreakpoints",
+        json!({ "source": source, "breakpoints": [{ "line": 9 }] }),
+    );
+    client.ok("continue", json!({ "threadId": 1 }));
+    assert_eq!(
+        client.stopped("breakpoint"),
+        [frame(9, "0x0208"), frame(6, "0x0204")]
+    );
+
+    let registers = client.ok("variables", json!({ "variablesReference": 1 }));
+    assert_eq!(registers["variables"][0]["name"], "v0");
+    assert_eq!(registers["variables"][0]["value"], "0x01");
+    let timers = client.ok("variables", json!({ "variablesReference": 2 }));
+    assert_eq!(timers["variables"][0]["name"], "delay");
+
+    client.ok("stepOut", json!({ "threadId": 1 }));
+    assert_eq!(client.stopped("step"), [frame(7, "0x0206")]);
+
+    client.ok(
+        "setBreakpoints",
+        json!({ "source": source, "breakpoints": [] }),
+    );
+    client.ok("next", json!({ "threadId": 1 }));
+    assert_eq!(client.stopped("step"), [frame(5, "0x0202")]);
+    client.ok("next", json!({ "threadId": 1 }));
+    client.stopped("step");
+    client.ok("next", json!({ "threadId": 1 }));
+    assert_eq!(client.stopped("step"), [frame(7, "0x0206")]);
+
+    client.ok("stepIn", json!({ "threadId": 1 }));
+    client.stopped("step");
+    client.ok("stepIn", json!({ "threadId": 1 }));
+    client.stopped("step");
+    client.ok("stepIn", json!({ "threadId": 1 }));
+    assert_eq!(
+        client.stopped("step"),
+        [frame(9, "0x0208"), frame(6, "0x0204")]
+    );
+
+    client.disconnect();
+}
+
+#[test]
+fn octo_and_errors() {
+    let mut client = Client::start();
+    client.ok("initialize", json!({ "adapterID": "chip8" }));
+
+    let missing = client.request("launch", json!({ "program": "/nonexistent/game.8o" }));
+    assert_eq!(missing["success"], false);
+    assert_eq!(client.request("stackTrace", json!({}))["success"], false);
+
+    let path = source(
+        "program.8o",
+        b": main
+    v0 := 1
+    loop
+        v0 += 1
+        if v0 == 5 then 0xff 0xff
+    again
+",
+    );
+    for mut arguments in [json!({ "timing": "slow" }), json!({ "ipf": 0 })] {
+        arguments["program"] = json!(path);
+        assert_eq!(client.request("launch", arguments)["success"], false);
+    }
+
+    client.ok("launch", json!({ "program": path, "variant": "chip8" }));
+    client.event("initialized");
+    client.ok(
+        "setBreakpoints",
+        json!({ "source": { "path": path }, "breakpoints": [{ "line": 4 }] }),
+    );
+    client.ok("configurationDone", json!({}));
+    assert_eq!(client.stopped("breakpoint"), [frame(4, "0x0204")]);
+
+    // Runs into the unknown opcode 0xffff
+    client.ok(
+        "setBreakpoints",
+        json!({ "source": { "path": path }, "breakpoints": [] }),
+    );
+    client.ok("continue", json!({ "threadId": 1 }));
+    assert!(client.event("output")["output"]
+        .as_str()
+        .unwrap()
+        .contains("0xffff"));
+    assert_eq!(client.stopped("exception"), [frame(0, "0x0208")]);
+
+    client.disconnect();
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use chip8::{Chip8Error, Instruction, Variant};
+
+/// The address roms are loaded at
+pub const ROM_START: u16 = 0x200;
+
+/// The maximum number of data bytes on a single `db` line
+const DATA_PER_LINE: usize = 8;
+
+/// What a byte of the rom is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Byte {
+    /// Not reached by any code path, likely sprites or other data
+    Data,
+    /// The first byte of an instruction
+    Code,
+    /// A later byte of an instruction
+    Operand,
+}
+
+/// The result of tracing the code paths of a rom
+///
+/// * `bytes`: What each byte of the rom is used for
+/// * `targets`: Addresses that are jumped to, called or loaded into I
+struct Analysis {
+    bytes: Vec<Byte>,
+    targets: BTreeSet<u16>,
+}
+
+/// Disassemble a rom loaded at [`ROM_START`] into assembly
+///
+/// Code is separated from data by tracing every path from the start of the rom through jumps,
+/// calls and skips. Instructions that are not supported by the variant and indirect jumps with
+/// `Bnnn` end a path. Jump, call and I targets get labels like `L0200`, and bytes that are never
+/// reached are written as `db` data. Every line ends with a comment holding its address and raw
+/// bytes. Fails like loading the rom into the emulator if it does not fit in the memory of the
+/// variant.
+pub fn disassemble(rom: &[u8], variant: Variant) -> Result<String, Chip8Error> {
+    if rom.len() > variant.max_rom_size() {
+        return Err(Chip8Error::RomTooLarge {
+            size: rom.len(),
+            max: variant.max_rom_size(),
+        });
+    }
+
+    let analysis = analyze(rom, variant);
+
+    // Only label targets at the start of a line, a target inside an instruction keeps its address
+    let labels: BTreeSet<u16> = analysis
+        .targets
+        .iter()
+        .copied()
+        .filter(|addr| {
+            offset(rom, *addr).is_some_and(|offset| analysis.bytes[offset] != Byte::Operand)
+        })
+        .collect();
+
+    let mut out = String::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let addr = ROM_START + offset as u16;
+        if labels.contains(&addr) {
+            writeln!(out, "{}:", label(addr)).unwrap();
+        }
+
+        let (text, len) = match analysis.bytes[offset] {
+            Byte::Code => {
+                let instruction =
+                    decode(rom, addr, variant).expect("Traced an invalid instruction");
+                let size = instruction.size() as usize;
+                let long = match instruction {
+                    Instruction::LdILong => u16::from_be_bytes([rom[offset + 2], rom[offset + 3]]),
+                    _ => 0,
+                };
+                (format_instruction(instruction, long, &labels), size)
+            }
+            Byte::Data => {
+                let len = (offset..rom.len())
+                    .take(DATA_PER_LINE)
+                    .enumerate()
+                    .take_while(|(n, o)| {
+                        analysis.bytes[*o] == Byte::Data
+                            && (*n == 0 || !labels.contains(&(ROM_START + *o as u16)))
+                    })
+                    .count();
+                let bytes: Vec<String> = rom[offset..offset + len]
+                    .iter()
+                    .map(|b| format!("{:#04x}", b))
+                    .collect();
+                (format!("db {}", bytes.join(", ")), len)
+            }
+            Byte::Operand => unreachable!("Operand without an instruction at {:#06x}", addr),
+        };
+
+        let raw: Vec<String> = rom[offset..offset + len]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        writeln!(out, "    {:<23} ; {:04x}: {}", text, addr, raw.join(" ")).unwrap();
+
+        offset += len;
+    }
+
+    Ok(out)
+}
+
+/// Trace all code paths reachable from the start of the rom
+fn analyze(rom: &[u8], variant: Variant) -> Analysis {
+    let mut bytes = vec![Byte::Data; rom.len()];
+    let mut targets = BTreeSet::new();
+    let mut pending = vec![ROM_START];
+
+    while let Some(addr) = pending.pop() {
+        let Some(instruction) = decode(rom, addr, variant) else {
+            continue;
+        };
+
+        // Stop at visited instructions, and at instructions overlapping others
+        let start = offset(rom, addr).expect("Decoded outside of the rom");
+        let size = instruction.size();
+        let span = &mut bytes[start..start + size as usize];
+        if span.iter().any(|b| *b != Byte::Data) {
+            continue;
+        }
+        span[0] = Byte::Code;
+        span[1..].fill(Byte::Operand);
+
+        let next = addr.wrapping_add(size);
+        match instruction {
+            Instruction::Jp(target) => {
+                targets.insert(target);
+                pending.push(target);
+            }
+            Instruction::Call(target) => {
+                targets.insert(target);
+                pending.extend([target, next]);
+            }
+            // The target of `Bnnn` depends on a register, so it is not traced
+            Instruction::Ret | Instruction::Exit | Instruction::JpV0(_) => (),
+            Instruction::Se(..)
+            | Instruction::Sne(..)
+            | Instruction::SeReg(..)
+            | Instruction::SneReg(..)
+            | Instruction::Skp(..)
+            | Instruction::Sknp(..) => {
+                // Skipping over a long load skips both of its words
+                let skipped = decode(rom, next, variant).map_or(2, Instruction::size);
+                pending.extend([next, next.wrapping_add(skipped)]);
+            }
+            Instruction::LdI(target) => {
+                targets.insert(target);
+                pending.push(next);
+            }
+            Instruction::LdILong => {
+                targets.insert(u16::from_be_bytes([rom[start + 2], rom[start + 3]]));
+                pending.push(next);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    Analysis { bytes, targets }
+}
+
+/// The offset of an address into the rom, `None` if outside of the rom
+fn offset(rom: &[u8], addr: u16) -> Option<usize> {
+    let offset = (addr as usize).checked_sub(ROM_START as usize)?;
+    (offset < rom.len()).then_some(offset)
+}
+
+/// Decode the instruction at an address, `None` if it is invalid, unsupported by the variant or
+/// does not fit in the rom
+fn decode(rom: &[u8], addr: u16, variant: Variant) -> Option<Instruction> {
+    let offset = offset(rom, addr)?;
+    let opcode = u16::from_be_bytes([*rom.get(offset)?, *rom.get(offset + 1)?]);
+    let instruction = Instruction::decode(opcode).ok()?;
+    (instruction.variant() <= variant && offset + instruction.size() as usize <= rom.len())
+        .then_some(instruction)
+}
+
+/// The label for an address
+fn label(addr: u16) -> String {
+    format!("L{:04X}", addr)
+}
+
+/// Format an instruction, using labels for addresses that have one
+///
+/// `long` is the address following [`Instruction::LdILong`], ignored for other instructions.
+fn format_instruction(instruction: Instruction, long: u16, labels: &BTreeSet<u16>) -> String {
+    let target = |addr: u16, width: usize| {
+        if labels.contains(&addr) {
+            label(addr)
+        } else {
+            format!("{:#0width$x}", addr, width = width)
+        }
+    };
+
+    match instruction {
+        Instruction::Jp(addr) => format!("JP {}", target(addr, 5)),
+        Instruction::Call(addr) => format!("CALL {}", target(addr, 5)),
+        Instruction::LdI(addr) => format!("LD I, {}", target(addr, 5)),
+        Instruction::JpV0(addr) => format!("JP V0, {}", target(addr, 5)),
+        Instruction::LdILong => format!("LD I, LONG {}", target(long, 6)),
+        _ => instruction.to_string(),
+    }
+}
@@ -0,0 +1,30 @@
+use chip8::Variant;
+
+/// Disassemble a rom to stdout
+///
+/// Takes the rom path and optionally `--variant <chip8|schip|xochip>`, the instruction set to
+/// decode. Defaults to `xochip`, which decodes every instruction.
+fn main() {
+    let mut rom = None;
+    let mut variant = Variant::XoChip;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => {
+                let name = args.next().expect("--variant needs a variant name");
+                variant = Variant::from_name(&name).unwrap_or_else(|| {
+                    panic!("Unknown variant {name}, expected chip8, schip or xochip")
+                });
+            }
+            _ => rom = Some(arg),
+        }
+    }
+
+    let path = rom.expect("No rom passed, needs a chip-8 rom path as an argument");
+    let data = std::fs::read(&path).unwrap_or_else(|err| panic!("Could not read {path}: {err}"));
+
+    let source = chip8_disasm::disassemble(&data, variant)
+        .unwrap_or_else(|err| panic!("Could not disassemble {path}: {err}"));
+    print!("{}", source);
+}
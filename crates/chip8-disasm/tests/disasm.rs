@@ -0,0 +1,170 @@
+use chip8::{Chip8Error, Variant};
+use chip8_disasm::disassemble;
+
+/// Disassemble a rom and return its lines without the address comments
+fn lines(rom: &[u8], variant: Variant) -> Vec<String> {
+    disassemble(rom, variant)
+        .unwrap()
+        .lines()
+        .map(|line| match line.split_once(';') {
+            Some((code, _)) => code.trim().to_string(),
+            None => line.trim().to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn traces_code_paths() {
+    let rom = [
+        0x22, 0x08, // CALL sub
+        0x30, 0x01, // SE V0, 1
+        0x12, 0x0c, // JP end
+        0x00, 0xe0, // Skipped to: CLS
+        0x60, 0x05, // sub: LD V0, 5
+        0x00, 0xee, // RET
+        0x12, 0x0c, // end: JP end
+        0xff, 0xff, // Never reached
+    ];
+
+    assert_eq!(
+        lines(&rom, Variant::Chip8),
+        [
+            "CALL L0208",
+            "SE V0, 0x01",
+            "JP L020C",
+            "CLS",
+            "L0208:",
+            "LD V0, 0x05",
+            "RET",
+            "L020C:",
+            "JP L020C",
+            "db 0xff, 0xff",
+        ]
+    );
+}
+
+#[test]
+fn skips_over_long_loads() {
+    let rom = [
+        0x30, 0x00, // SE V0, 0
+        0xf0, 0x00, 0x02, 0x0a, // LD I, LONG data
+        0x12, 0x08, // JP 0x208
+        0x12, 0x08, // Only reached by skipping the long load
+        0xaa, // data
+    ];
+
+    assert_eq!(
+        lines(&rom, Variant::XoChip),
+        [
+            "SE V0, 0x00",
+            "LD I, LONG L020A",
+            "JP L0208",
+            "L0208:",
+            "JP L0208",
+            "L020A:",
+            "db 0xaa",
+        ]
+    );
+}
+
+#[test]
+fn separates_data() {
+    let rom = [
+        0xa2, 0x04, // LD I, sprite
+        0x12, 0x02, // JP 0x202
+        0xff, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xff, // sprite
+    ];
+
+    // Data lines hold at most 8 bytes
+    assert_eq!(
+        lines(&rom, Variant::Chip8),
+        [
+            "LD I, L0204",
+            "L0202:",
+            "JP L0202",
+            "L0204:",
+            "db 0xff, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81",
+            "db 0x81, 0xff",
+        ]
+    );
+
+    // A label in the middle of data starts a new line
+    let rom = [
+        0xa2, 0x07, // LD I, 0x207
+        0x12, 0x05, // JP 0x205, which is not a valid instruction
+        0x01, 0x02, 0x03, 0x04, 0x05,
+    ];
+    assert_eq!(
+        lines(&rom, Variant::Chip8),
+        [
+            "LD I, L0207",
+            "JP L0205",
+            "db 0x01",
+            "L0205:",
+            "db 0x02, 0x03",
+            "L0207:",
+            "db 0x04, 0x05",
+        ]
+    );
+
+    // Targets inside an instruction keep their address
+    let rom = [
+        0xa2, 0x07, // LD I, 0x207
+        0x12, 0x03, // JP 0x203 into its own operand
+        0x01, 0x02, 0x03, 0x04, 0x05,
+    ];
+    assert_eq!(
+        lines(&rom, Variant::Chip8),
+        [
+            "LD I, L0207",
+            "JP 0x203",
+            "db 0x01, 0x02, 0x03",
+            "L0207:",
+            "db 0x04, 0x05",
+        ]
+    );
+}
+
+#[test]
+fn indirect_jumps_end_paths() {
+    let rom = [
+        0xb2, 0x04, // JP V0, table
+        0x00, 0xe0, // Never reached
+        0x12, 0x04, // table: only reached through v0
+    ];
+
+    assert_eq!(
+        lines(&rom, Variant::Chip8),
+        ["JP V0, 0x204", "db 0x00, 0xe0, 0x12, 0x04"]
+    );
+}
+
+#[test]
+fn unsupported_instructions_end_paths() {
+    // HIGH is SUPER-CHIP only, so it is data for CHIP-8
+    let rom = [0x00, 0xe0, 0x00, 0xff, 0x00, 0xe0];
+    assert_eq!(
+        lines(&rom, Variant::Chip8),
+        ["CLS", "db 0x00, 0xff, 0x00, 0xe0"]
+    );
+    assert_eq!(lines(&rom, Variant::SuperChip), ["CLS", "HIGH", "CLS"]);
+}
+
+#[test]
+fn rom_sizes() {
+    // The largest XO-CHIP rom reaches the end of the 64KB address space
+    let mut rom = vec![0; Variant::XoChip.max_rom_size()];
+    rom[..4].copy_from_slice(&[0xf0, 0x00, 0xff, 0xfe]);
+    let source = disassemble(&rom, Variant::XoChip).unwrap();
+    assert!(source.contains("LD I, LONG LFFFE"));
+    assert!(source.ends_with("; fffe: 00 00\n"));
+
+    assert_eq!(
+        disassemble(&[0; 0xe01], Variant::Chip8),
+        Err(Chip8Error::RomTooLarge {
+            size: 0xe01,
+            max: 0xe00
+        })
+    );
+    assert!(disassemble(&vec![0; 0xfe01], Variant::XoChip).is_err());
+}
@@ -0,0 +1,353 @@
+mod packet;
+mod target;
+
+use std::collections::BTreeSet;
+use std::io;
+use std::net::TcpStream;
+use std::ops::RangeInclusive;
+
+use chip8::{Access, Chip8, Chip8Error, FrameEnd};
+
+use packet::{Connection, Message};
+
+/// The number of cycles to run between checks for an interrupt from the debugger
+const INTERRUPT_INTERVAL: u32 = 1024;
+
+/// The largest packet accepted from the debugger
+const PACKET_SIZE: usize = 0x1000;
+
+/// Why the emulator stopped running
+enum Stop {
+    /// A single step finished
+    Step,
+    /// A software breakpoint was reached
+    Breakpoint,
+    /// A watched address was accessed, the kind is `watch`, `rwatch` or `awatch`
+    Watchpoint { kind: &'static str, addr: u16 },
+    /// The debugger interrupted the emulator
+    Interrupt,
+    /// The emulator raised an error
+    Error(Chip8Error),
+    /// The program exited with `00FD`
+    Exited,
+}
+
+impl Stop {
+    /// The stop reply packet, with a signal like a process on a unix system would get
+    fn reply(&self) -> String {
+        match self {
+            Stop::Step => "S05".to_string(),
+            Stop::Breakpoint => "T05swbreak:;".to_string(),
+            Stop::Watchpoint { kind, addr } => format!("T05{}:{:x};", kind, addr),
+            Stop::Interrupt => "S02".to_string(),
+            Stop::Error(Chip8Error::UnknownOpcode { .. }) => "S04".to_string(),
+            Stop::Error(_) => "S0b".to_string(),
+            Stop::Exited => "W00".to_string(),
+        }
+    }
+}
+
+/// A stub for the GDB remote serial protocol, debugging a [`Chip8`] over TCP
+///
+/// The registers are `v0`-`vf`, `i`, `pc`, `dt` and `st`, described to the debugger with a target
+/// description. Software breakpoints, watchpoints, stepping, continuing and memory access are
+/// supported. The emulator runs frames of `ipf` instructions like the other frontends, ticking the
+/// timers after each, see [`Chip8::run_frame`]. There is no input so programs waiting for a key
+/// block until interrupted.
+///
+/// * `chip8`: The emulator being debugged
+/// * `breakpoints`: The addresses to stop at before executing them
+/// * `watchpoints`: The watched ranges with the kind of the watchpoint packet that set them
+/// * `ipf`: The number of instructions run per frame, ignored with the VIP timing
+/// * `stop`: The reason the emulator last stopped, reported again on `?`
+pub struct GdbStub {
+    chip8: Chip8,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<(RangeInclusive<u16>, u8)>,
+    ipf: u32,
+    stop: Stop,
+}
+
+impl GdbStub {
+    /// Create a stub running `ipf` instructions per frame
+    pub fn new(chip8: Chip8, ipf: u32) -> Self {
+        Self {
+            chip8,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            ipf,
+            stop: Stop::Step,
+        }
+    }
+
+    /// Get the emulator being debugged
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    /// Serve a debugger until it detaches, kills the target or disconnects
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut conn = Connection::new(stream)?;
+
+        while let Some(message) = conn.recv()? {
+            let Message::Packet(packet) = message else {
+                // Interrupts while stopped are answered with the current stop
+                conn.send(&self.stop.reply())?;
+                continue;
+            };
+
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') | Some(b's') => {
+                    let step = packet.starts_with('s');
+                    match parse_hex(&packet[1..]) {
+                        Some(addr) => self.chip8.set_pc(addr as u16),
+                        None if packet.len() > 1 => {
+                            conn.send("E01")?;
+                            continue;
+                        }
+                        None => (),
+                    }
+
+                    self.stop = self.run(&mut conn, step)?;
+                    if let Stop::Error(err) = &self.stop {
+                        conn.send(&format!("O{}", hex(format!("{}\n", err).as_bytes())))?;
+                    }
+                    self.stop.reply()
+                }
+                Some(b'D') => {
+                    conn.send("OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                Some(b'Q') if packet == "QStartNoAckMode" => {
+                    conn.send("OK")?;
+                    conn.disable_acks();
+                    continue;
+                }
+                _ => self.handle(&packet).unwrap_or_else(|| "E01".to_string()),
+            };
+            conn.send(&reply)?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle a packet that does not run the emulator, `None` for malformed packets
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let Some(command) = packet.get(..1) else {
+            return Some(String::new());
+        };
+        let args = &packet[1..];
+
+        Some(match command {
+            "?" => self.stop.reply(),
+            "g" => {
+                let bytes: Vec<u8> = (0..target::REGISTERS.len())
+                    .flat_map(|n| target::read_register(&self.chip8, n).unwrap_or_default())
+                    .collect();
+                hex(&bytes)
+            }
+            "G" => {
+                let mut bytes = &unhex(args)?[..];
+                for (n, register) in target::REGISTERS.iter().enumerate() {
+                    let (value, rest) = bytes.split_at_checked(register.size)?;
+                    target::write_register(&mut self.chip8, n, value)?;
+                    bytes = rest;
+                }
+                "OK".to_string()
+            }
+            "p" => hex(&target::read_register(&self.chip8, parse_hex(args)?)?),
+            "P" => {
+                let (n, value) = args.split_once('=')?;
+                target::write_register(&mut self.chip8, parse_hex(n)?, &unhex(value)?)?;
+                "OK".to_string()
+            }
+            "m" => {
+                let (addr, len) = parse_pair(args)?;
+                hex(self.chip8.memory().get(addr..addr.checked_add(len)?)?)
+            }
+            "M" => {
+                let (range, data) = args.split_once(':')?;
+                let (addr, len) = parse_pair(range)?;
+                let data = unhex(data).filter(|data| data.len() == len)?;
+                self.chip8
+                    .memory_mut()
+                    .get_mut(addr..addr.checked_add(len)?)?
+                    .copy_from_slice(&data);
+                "OK".to_string()
+            }
+            "Z" | "z" => self.set_point(command == "Z", args)?,
+            "H" | "T" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        })
+    }
+
+    /// Handle a general query, empty for unsupported queries
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;qXfer:memory-map:read+;\
+                 QStartNoAckMode+;swbreak+",
+                PACKET_SIZE
+            );
+        }
+
+        if let Some(args) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            return transfer(&target::description(), args);
+        }
+        if let Some(args) = query.strip_prefix("Xfer:memory-map:read::") {
+            return transfer(&target::memory_map(&self.chip8), args);
+        }
+
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Insert or remove a breakpoint or watchpoint, `type,addr,kind`
+    fn set_point(&mut self, insert: bool, args: &str) -> Option<String> {
+        let (point, rest) = args.split_once(',')?;
+        let (addr, len) = parse_pair(rest)?;
+        let addr = u16::try_from(addr).ok()?;
+
+        match point {
+            // Software and hardware breakpoints are the same to the emulator
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+            }
+            "2" | "3" | "4" => {
+                let end = u16::try_from((addr as usize).checked_add(len.max(1) - 1)?).ok()?;
+                let watchpoint = (addr..=end, point.as_bytes()[0] - b'0');
+                if insert {
+                    self.watchpoints.push(watchpoint);
+                } else {
+                    self.watchpoints.retain(|w| *w != watchpoint);
+                }
+
+                self.chip8.clear_watchpoints();
+                for (range, point) in &self.watchpoints {
+                    let access = match point {
+                        2 => Access::Write,
+                        3 => Access::Read,
+                        _ => Access::ReadWrite,
+                    };
+                    self.chip8.add_watchpoint(range.clone(), access);
+                }
+            }
+            _ => return Some(String::new()),
+        }
+
+        Some("OK".to_string())
+    }
+
+    /// Run a single cycle when stepping, otherwise until something stops the emulator
+    ///
+    /// Runs whole frames with [`Chip8::run_frame_with`], so the timers tick at the same frame
+    /// boundaries as in the other frontends.
+    fn run(&mut self, conn: &mut Connection, step: bool) -> io::Result<Stop> {
+        // Resuming from a breakpoint executes it instead of stopping again
+        let mut first = true;
+        let mut since_poll = 0;
+
+        loop {
+            let breakpoints = &self.breakpoints;
+            let mut stop = Stop::Step;
+            let frame = self.chip8.run_frame_with(self.ipf, |chip8| {
+                if std::mem::take(&mut first) {
+                    return true;
+                }
+                if step {
+                    return false;
+                }
+                if breakpoints.contains(&chip8.pc()) {
+                    stop = Stop::Breakpoint;
+                    return false;
+                }
+                true
+            });
+
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(err) => return Ok(Stop::Error(err)),
+            };
+            match frame.end {
+                FrameEnd::Finished => (),
+                FrameEnd::Interrupted => return Ok(stop),
+                FrameEnd::Exited => return Ok(Stop::Exited),
+                FrameEnd::Watchpoint { addr, .. } => {
+                    let kind = match self.watch_kind(addr) {
+                        2 => "watch",
+                        3 => "rwatch",
+                        _ => "awatch",
+                    };
+                    return Ok(Stop::Watchpoint { kind, addr });
+                }
+            }
+
+            since_poll += frame.cycles;
+            if since_poll >= INTERRUPT_INTERVAL {
+                since_poll = 0;
+                if conn.poll_interrupt()? {
+                    return Ok(Stop::Interrupt);
+                }
+            }
+        }
+    }
+
+    /// The type of the first watchpoint over an address
+    fn watch_kind(&self, addr: u16) -> u8 {
+        self.watchpoints
+            .iter()
+            .find(|(range, _)| range.contains(&addr))
+            .map_or(4, |(_, point)| *point)
+    }
+}
+
+/// Reply to a `qXfer` read of `offset,length` from a document
+fn transfer(document: &str, args: &str) -> String {
+    let Some((offset, len)) = parse_pair(args) else {
+        return "E01".to_string();
+    };
+
+    let bytes = document.as_bytes();
+    let start = offset.min(bytes.len());
+    let end = start.saturating_add(len).min(bytes.len());
+    let more = if end < bytes.len() { "m" } else { "l" };
+    format!("{}{}", more, String::from_utf8_lossy(&bytes[start..end]))
+}
+
+/// Parse a hexadecimal number
+fn parse_hex(hex: &str) -> Option<usize> {
+    usize::from_str_radix(hex, 16).ok()
+}
+
+/// Parse two comma separated hexadecimal numbers, like `addr,length`
+fn parse_pair(pair: &str) -> Option<(usize, usize)> {
+    let (a, b) = pair.split_once(',')?;
+    Some((parse_hex(a)?, parse_hex(b)?))
+}
+
+/// Encode bytes as lower case hex
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode hex into bytes
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|n| u8::from_str_radix(hex.get(n..n + 2)?, 16).ok())
+        .collect()
+}
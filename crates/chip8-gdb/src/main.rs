@@ -0,0 +1,69 @@
+use std::net::TcpListener;
+
+use chip8::{Chip8, Timing, Variant, DEFAULT_IPF};
+use chip8_gdb::GdbStub;
+
+/// Serve a rom to a single GDB session on a local port
+///
+/// Takes the rom path, optionally `--variant <chip8|schip|xochip>`, `--ipf <n>`,
+/// `--timing <fixed|vip>` and `--port <port>`, which defaults to 1234. Connect with
+/// `target remote localhost:1234`.
+fn main() {
+    let mut rom = None;
+    let mut variant = Variant::Chip8;
+    let mut ipf = DEFAULT_IPF;
+    let mut timing = Timing::Fixed;
+    let mut port = 1234;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => {
+                let name = args.next().expect("--variant needs a variant name");
+                variant = Variant::from_name(&name).unwrap_or_else(|| {
+                    panic!("Unknown variant {name}, expected chip8, schip or xochip")
+                });
+            }
+            "--ipf" => {
+                ipf = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .filter(|v| *v > 0)
+                    .expect("--ipf needs a positive number of instructions");
+            }
+            "--timing" => {
+                let name = args.next().expect("--timing needs a timing name");
+                timing = Timing::from_name(&name)
+                    .unwrap_or_else(|| panic!("Unknown timing {name}, expected fixed or vip"));
+            }
+            "--port" => {
+                port = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .expect("--port needs a port number");
+            }
+            _ => rom = Some(arg),
+        }
+    }
+
+    let path = rom.expect("No rom passed, needs a chip-8 rom path as an argument");
+    let data = std::fs::read(&path).unwrap_or_else(|err| panic!("Could not read {path}: {err}"));
+    let chip8 = Chip8::builder(data)
+        .variant(variant)
+        .timing(timing)
+        .build()
+        .unwrap_or_else(|err| panic!("Could not load {path}: {err}"));
+
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .unwrap_or_else(|err| panic!("Could not listen on port {port}: {err}"));
+    println!("Waiting for a debugger on 127.0.0.1:{port}");
+
+    let (stream, addr) = listener.accept().expect("Could not accept a debugger");
+    println!("Debugger connected from {addr}");
+
+    let mut stub = GdbStub::new(chip8, ipf);
+    if let Err(err) = stub.serve(stream) {
+        eprintln!("Connection to the debugger failed: {err}");
+        std::process::exit(1);
+    }
+}
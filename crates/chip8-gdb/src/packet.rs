@@ -0,0 +1,154 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+/// The byte sent by the debugger to interrupt a running target
+const INTERRUPT: u8 = 0x03;
+
+/// A message from the debugger
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A packet with a valid checksum, without the framing
+    Packet(String),
+    /// A request to stop the running target
+    Interrupt,
+}
+
+/// A connection to a debugger speaking the remote serial protocol
+///
+/// * `reader`: The buffered incoming side of the stream
+/// * `writer`: The outgoing side of the stream
+/// * `last`: The last packet sent, resent when the debugger asks for a retransmission
+/// * `no_ack`: If acknowledgements are turned off with `QStartNoAckMode`
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    last: Vec<u8>,
+    no_ack: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        // Packets are small and answered one at a time, so don't wait to batch them
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            writer: stream.try_clone()?,
+            reader: BufReader::new(stream),
+            last: Vec::new(),
+            no_ack: false,
+        })
+    }
+
+    /// Stop sending and expecting acknowledgements
+    pub fn disable_acks(&mut self) {
+        self.no_ack = true;
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// Read the next message, `None` once the debugger has disconnected
+    ///
+    /// Packets with a bad checksum are rejected and read again.
+    pub fn recv(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+
+            match byte {
+                INTERRUPT => return Ok(Some(Message::Interrupt)),
+                b'-' if !self.no_ack => self.writer.write_all(&self.last)?,
+                b'$' => {
+                    let mut data = Vec::new();
+                    if self.reader.read_until(b'#', &mut data)? == 0 || data.pop() != Some(b'#') {
+                        return Ok(None);
+                    }
+                    let mut checksum = [0; 2];
+                    self.reader.read_exact(&mut checksum)?;
+
+                    let valid = std::str::from_utf8(&checksum)
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        == Some(checksum_of(&data));
+                    if !self.no_ack {
+                        self.writer.write_all(if valid { b"+" } else { b"-" })?;
+                    }
+                    if valid {
+                        return Ok(Some(Message::Packet(
+                            String::from_utf8_lossy(&data).into_owned(),
+                        )));
+                    }
+                }
+                // Acknowledgements and noise between packets
+                _ => (),
+            }
+        }
+    }
+
+    /// Send a packet, escaping the characters that are part of the framing
+    pub fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut body = Vec::with_capacity(data.len());
+        for byte in data.bytes() {
+            match byte {
+                b'#' | b'$' | b'}' | b'*' => body.extend([b'}', byte ^ 0x20]),
+                _ => body.push(byte),
+            }
+        }
+
+        let mut packet = Vec::with_capacity(body.len() + 4);
+        packet.push(b'$');
+        packet.extend(&body);
+        packet.extend(format!("#{:02x}", checksum_of(&body)).bytes());
+
+        self.writer.write_all(&packet)?;
+        self.last = packet;
+        Ok(())
+    }
+
+    /// Check for an interrupt without blocking, a disconnect also counts as an interrupt
+    ///
+    /// Anything else that is waiting is left to be read by [`Connection::recv`].
+    pub fn poll_interrupt(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(self.take_interrupt());
+        }
+
+        self.reader.get_ref().set_nonblocking(true)?;
+        let filled = self.reader.fill_buf().map(|buf| buf.is_empty());
+        self.reader.get_ref().set_nonblocking(false)?;
+
+        match filled {
+            Ok(true) => Ok(true),
+            Ok(false) => Ok(self.take_interrupt()),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Consume an interrupt at the start of the buffer, after any acknowledgements
+    fn take_interrupt(&mut self) -> bool {
+        let acks = self
+            .reader
+            .buffer()
+            .iter()
+            .take_while(|b| **b == b'+')
+            .count();
+        self.reader.consume(acks);
+
+        let interrupt = self.reader.buffer().first() == Some(&INTERRUPT);
+        if interrupt {
+            self.reader.consume(1);
+        }
+        interrupt
+    }
+}
+
+/// The checksum of a packet, the sum of its bytes modulo 256
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
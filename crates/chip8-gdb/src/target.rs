@@ -0,0 +1,109 @@
+use chip8::Chip8;
+
+/// A register exposed to the debugger
+///
+/// * `name`: The name shown by the debugger
+/// * `size`: The size in bytes
+/// * `kind`: The type in the target description
+pub struct Register {
+    pub name: &'static str,
+    pub size: usize,
+    pub kind: &'static str,
+}
+
+const fn register(name: &'static str, size: usize, kind: &'static str) -> Register {
+    Register { name, size, kind }
+}
+
+/// The registers in the order they are numbered and sent in `g` packets
+pub const REGISTERS: [Register; 20] = [
+    register("v0", 1, "uint8"),
+    register("v1", 1, "uint8"),
+    register("v2", 1, "uint8"),
+    register("v3", 1, "uint8"),
+    register("v4", 1, "uint8"),
+    register("v5", 1, "uint8"),
+    register("v6", 1, "uint8"),
+    register("v7", 1, "uint8"),
+    register("v8", 1, "uint8"),
+    register("v9", 1, "uint8"),
+    register("va", 1, "uint8"),
+    register("vb", 1, "uint8"),
+    register("vc", 1, "uint8"),
+    register("vd", 1, "uint8"),
+    register("ve", 1, "uint8"),
+    register("vf", 1, "uint8"),
+    register("i", 2, "data_ptr"),
+    register("pc", 2, "code_ptr"),
+    register("dt", 1, "uint8"),
+    register("st", 1, "uint8"),
+];
+
+/// The number of the I register, the ones before it are v0-vF
+const I: usize = 16;
+const PC: usize = 17;
+const DT: usize = 18;
+const ST: usize = 19;
+
+/// Read a register, multi-byte registers are little endian like the debugger expects
+pub fn read_register(chip8: &Chip8, n: usize) -> Option<Vec<u8>> {
+    Some(match n {
+        0..=15 => vec![chip8.registers()[n]],
+        I => chip8.i().to_le_bytes().to_vec(),
+        PC => chip8.pc().to_le_bytes().to_vec(),
+        DT => vec![chip8.delay_timer()],
+        ST => vec![chip8.sound_timer()],
+        _ => return None,
+    })
+}
+
+/// Write a register, `bytes` must be exactly the size of the register
+pub fn write_register(chip8: &mut Chip8, n: usize, bytes: &[u8]) -> Option<()> {
+    if REGISTERS.get(n)?.size != bytes.len() {
+        return None;
+    }
+
+    let word = || u16::from_le_bytes([bytes[0], bytes[1]]);
+    match n {
+        0..=15 => chip8.set_register(n as u8, bytes[0]),
+        I => chip8.set_i(word()),
+        PC => chip8.set_pc(word()),
+        DT => chip8.set_delay_timer(bytes[0]),
+        _ => chip8.set_sound_timer(bytes[0]),
+    }
+    Some(())
+}
+
+/// The target description, the registers and their types
+pub fn description() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n\
+         <feature name=\"org.chip8.core\">\n",
+    );
+    for (n, register) in REGISTERS.iter().enumerate() {
+        xml += &format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+            register.name,
+            register.size * 8,
+            register.kind,
+            n
+        );
+    }
+    xml += "</feature>\n</target>\n";
+    xml
+}
+
+/// The memory map, a single block of ram the size of the emulator memory
+pub fn memory_map(chip8: &Chip8) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\n\
+         <!DOCTYPE memory-map PUBLIC \"+//IDN gnu.org//DTD GDB Memory Map V1.0//EN\" \
+         \"http://sourceware.org/gdb/gdb-memory-map.dtd\">\n\
+         <memory-map>\n\
+         <memory type=\"ram\" start=\"0x0\" length=\"{:#x}\"/>\n\
+         </memory-map>\n",
+        chip8.memory().len()
+    )
+}
@@ -0,0 +1,232 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+use chip8::{Chip8, Variant, DEFAULT_IPF};
+use chip8_gdb::GdbStub;
+
+/// A scripted debugger connected to a stub running on another thread
+struct Client {
+    stream: TcpStream,
+    stub: JoinHandle<GdbStub>,
+}
+
+impl Client {
+    fn connect(rom: &[u8], variant: Variant) -> Self {
+        let chip8 = Chip8::builder(rom.to_vec())
+            .variant(variant)
+            .seed(0)
+            .build()
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stub = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = GdbStub::new(chip8, DEFAULT_IPF);
+            stub.serve(stream).unwrap();
+            stub
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        Self { stream, stub }
+    }
+
+    fn send(&mut self, packet: &str) {
+        let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Read a reply packet, skipping acknowledgements
+    fn recv(&mut self) -> String {
+        while self.byte() != b'$' {}
+
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b'}' => data.push(self.byte() ^ 0x20),
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let expected = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(format!("{:02x}", expected).as_bytes(), checksum);
+
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    /// Send a packet and read the reply
+    fn request(&mut self, packet: &str) -> String {
+        self.send(packet);
+        self.recv()
+    }
+
+    /// Detach and get the stub back
+    fn detach(mut self) -> GdbStub {
+        assert_eq!(self.request("D"), "OK");
+        self.stub.join().unwrap()
+    }
+}
+
+#[test]
+fn registers_and_memory() {
+    // LD V0, 0x2a; LD I, 0x345
+    let mut client = Client::connect(&[0x60, 0x2a, 0xa3, 0x45], Variant::Chip8);
+
+    assert!(client
+        .request("qSupported:swbreak+")
+        .contains("qXfer:features:read+"));
+    assert_eq!(client.request("?"), "S05");
+    assert!(client
+        .request("qXfer:features:read:target.xml:0,fff")
+        .contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\" regnum=\"17\"/>"));
+    assert!(client
+        .request("qXfer:memory-map:read::0,fff")
+        .contains("length=\"0x1000\""));
+
+    // v0-vF, I, pc, dt and st, the 16 bit registers little endian
+    let initial = format!("{}{}{}{}", "00".repeat(16), "0000", "0002", "0000");
+    assert_eq!(client.request("g"), initial);
+    assert_eq!(client.request("m200,4"), "602aa345");
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p0"), "2a");
+    assert_eq!(client.request("p10"), "4503");
+    assert_eq!(client.request("p11"), "0402");
+
+    assert_eq!(client.request("P3=7f"), "OK");
+    assert_eq!(client.request("P12=3c"), "OK");
+    assert_eq!(client.request("M300,3:010203"), "OK");
+    assert_eq!(client.request("m2ff,5"), "0001020300");
+    assert_eq!(client.request("m1000,1"), "E01");
+    assert_eq!(client.request("M300,2:01"), "E01");
+    assert_eq!(client.request("vMustReplyEmpty"), "");
+
+    let stub = client.detach();
+    let chip8 = stub.chip8();
+    assert_eq!(chip8.registers()[3], 0x7f);
+    assert_eq!(chip8.delay_timer(), 0x3c);
+    assert_eq!(&chip8.memory()[0x300..0x303], &[1, 2, 3]);
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    let rom = [
+        0x60, 0x00, // LD V0, 0
+        0x70, 0x01, // loop: ADD V0, 1
+        0x22, 0x08, // CALL sub
+        0x12, 0x02, // JP loop
+        0x00, 0xee, // sub: RET
+    ];
+    let mut client = Client::connect(&rom, Variant::Chip8);
+
+    assert_eq!(client.request("Z0,208,2"), "OK");
+    assert_eq!(client.request("c"), "T05swbreak:;");
+    assert_eq!(client.request("p11"), "0802");
+    assert_eq!(client.request("p0"), "01");
+
+    // Continuing runs the breakpoint instead of stopping at it again
+    assert_eq!(client.request("c"), "T05swbreak:;");
+    assert_eq!(client.request("p0"), "02");
+
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p11"), "0602");
+
+    assert_eq!(client.request("z0,208,2"), "OK");
+    assert_eq!(client.request("Z0,202,2"), "OK");
+    assert_eq!(client.request("c"), "T05swbreak:;");
+    assert_eq!(client.request("p0"), "02");
+
+    // Continuing at an address
+    assert_eq!(client.request("c200"), "T05swbreak:;");
+    assert_eq!(client.request("p0"), "00");
+
+    client.detach();
+}
+
+#[test]
+fn timers_tick_per_frame() {
+    let rom = [
+        0x60, 0x05, // LD V0, 5
+        0xf0, 0x15, // LD DT, V0
+        0x12, 0x04, // JP 0x204
+    ];
+    let mut client = Client::connect(&rom, Variant::Chip8);
+
+    // The timers tick once the frame of DEFAULT_IPF instructions is done
+    for _ in 0..DEFAULT_IPF - 1 {
+        assert_eq!(client.request("s"), "S05");
+    }
+    assert_eq!(client.request("p12"), "05");
+    assert_eq!(client.request("s"), "S05");
+    assert_eq!(client.request("p12"), "04");
+
+    client.detach();
+}
+
+#[test]
+fn interrupts_and_watchpoints() {
+    let rom = [
+        0xa3, 0x00, // LD I, 0x300
+        0x60, 0x7b, // LD V0, 123
+        0xf0, 0x33, // LD B, V0
+        0xf2, 0x65, // LD V2, [I]
+        0x12, 0x08, // JP 0x208
+    ];
+    let mut client = Client::connect(&rom, Variant::Chip8);
+
+    assert_eq!(client.request("Z2,301,1"), "OK");
+    assert_eq!(client.request("c"), "T05watch:301;");
+    assert_eq!(client.request("p11"), "0602");
+
+    assert_eq!(client.request("z2,301,1"), "OK");
+    assert_eq!(client.request("Z3,300,2"), "OK");
+    assert_eq!(client.request("c"), "T05rwatch:300;");
+    assert_eq!(client.request("z3,300,2"), "OK");
+
+    // Ranges past the end of the address space are rejected
+    assert_eq!(client.request("Z2,fff0,11"), "E01");
+    assert_eq!(client.request("Z2,200,ffffffffffffffff"), "E01");
+
+    // Running forever until interrupted
+    client.send("c");
+    thread::sleep(std::time::Duration::from_millis(50));
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!(client.recv(), "S02");
+    assert_eq!(client.request("p11"), "0802");
+
+    client.detach();
+}
+
+#[test]
+fn errors_and_exit() {
+    let rom = [
+        0xff, 0xff, // Unknown opcode
+        0x00, 0xfd, // EXIT
+    ];
+    let mut client = Client::connect(&rom, Variant::SuperChip);
+
+    assert_eq!(client.request("QStartNoAckMode"), "OK");
+
+    // The error message is printed to the debugger console before the stop
+    let message = client.request("c");
+    assert!(message.starts_with('O'), "{}", message);
+    assert_eq!(client.recv(), "S04");
+    assert_eq!(client.request("p11"), "0002");
+
+    assert_eq!(client.request("P11=0202"), "OK");
+    assert_eq!(client.request("c"), "W00");
+
+    client.send("k");
+    client.stub.join().unwrap();
+}
@@ -0,0 +1,221 @@
+use std::ops::RangeInclusive;
+
+use chip8::{Quirks, Timing, TraceFormat, Variant, DEFAULT_IPF};
+
+/// The default palette, indexed by the bit planes set for a pixel
+const DEFAULT_PALETTE: [[u8; 4]; 4] = [
+    [0x1E, 0x1E, 0x2E, 0xFF],
+    [0x89, 0xB4, 0xFA, 0xFF],
+    [0xF3, 0x8B, 0xA8, 0xFF],
+    [0xA6, 0xE3, 0xA1, 0xFF],
+];
+
+/// Command line arguments
+///
+/// * `rom`: The path to the rom
+/// * `variant`: The instruction set to emulate, chosen with `--variant <chip8|schip|xochip>`
+/// * `quirks`: The quirks to emulate, chosen with `--quirks <vip|vip-wait|chip48|schip|xochip>`, defaults
+///   to the quirks of the variant
+/// * `palette`: The colors of the bit planes, chosen with `--palette <rrggbb,rrggbb,rrggbb,rrggbb>`
+/// * `volume`: The volume of the sound between 0 and 1, chosen with `--volume <0-100>`
+/// * `mute`: Start with the sound muted, chosen with `--mute`
+/// * `ipf`: The number of instructions run per 60Hz frame, chosen with `--ipf <n>`
+/// * `timing`: How long instructions take, chosen with `--timing <fixed|vip>`, `vip` ignores `ipf`
+/// * `rewind_interval`: The number of frames between rewind snapshots, chosen with
+///   `--rewind-interval <frames>`
+/// * `rewind_memory`: The memory budget for rewind snapshots in bytes, chosen with
+///   `--rewind-memory <MiB>`
+/// * `seed`: The seed for the random number generator, chosen with `--seed <n>`, random if unset
+/// * `debug`: Start with the emulator paused in the debugger, chosen with `--debug`
+/// * `breakpoints`: Addresses to pause the emulator at, chosen with `--break <addr>` which can be
+///   repeated
+/// * `watchpoints`: Address ranges to pause the emulator after writing to, chosen with
+///   `--watch <addr[-addr]>` which can be repeated
+/// * `trace`: The file to write an execution trace to, chosen with `--trace <file>`
+/// * `trace_format`: The format of the trace, chosen with `--trace-format <text|binary>`
+pub struct Args {
+    pub rom: String,
+    pub variant: Variant,
+    pub quirks: Option<Quirks>,
+    pub palette: [[u8; 4]; 4],
+    pub volume: f32,
+    pub mute: bool,
+    pub ipf: u32,
+    pub timing: Timing,
+    pub rewind_interval: u32,
+    pub rewind_memory: usize,
+    pub seed: Option<u64>,
+    pub debug: bool,
+    pub breakpoints: Vec<u16>,
+    pub watchpoints: Vec<RangeInclusive<u16>>,
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+}
+
+impl Args {
+    /// Parse the command line arguments, panics with a message on invalid arguments
+    pub fn parse() -> Self {
+        let mut rom = None;
+        let mut variant = Variant::Chip8;
+        let mut quirks = None;
+        let mut palette = DEFAULT_PALETTE;
+        let mut volume = 0.5;
+        let mut mute = false;
+        let mut ipf = DEFAULT_IPF;
+        let mut timing = Timing::Fixed;
+        let mut rewind_interval = 2;
+        let mut rewind_memory = 16 << 20;
+        let mut seed = None;
+        let mut debug = false;
+        let mut breakpoints = Vec::new();
+        let mut watchpoints = Vec::new();
+        let mut trace = None;
+        let mut trace_format = TraceFormat::Text;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--variant" => {
+                    let name = args.next().expect("--variant needs a variant name");
+                    variant = Variant::from_name(&name).unwrap_or_else(|| {
+                        panic!("Unknown variant {name}, expected chip8, schip or xochip")
+                    });
+                }
+                "--quirks" => {
+                    let name = args.next().expect("--quirks needs a preset name");
+                    quirks = Some(Quirks::preset(&name).unwrap_or_else(|| {
+                        panic!(
+                            "Unknown quirks preset {name}, expected vip, vip-wait, chip48, schip or xochip"
+                        )
+                    }));
+                }
+                "--palette" => {
+                    let colors = args.next().expect("--palette needs 4 colors");
+                    palette = parse_palette(&colors).unwrap_or_else(|| {
+                        panic!("Invalid palette {colors}, expected 4 comma separated rrggbb colors")
+                    });
+                }
+                "--volume" => {
+                    let percent: u8 = args
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v| *v <= 100)
+                        .expect("--volume needs a volume between 0 and 100");
+                    volume = percent as f32 / 100.;
+                }
+                "--mute" => mute = true,
+                "--ipf" => {
+                    ipf = args
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v| *v > 0)
+                        .expect("--ipf needs a positive number of instructions");
+                }
+                "--timing" => {
+                    let name = args.next().expect("--timing needs a timing name");
+                    timing = Timing::from_name(&name)
+                        .unwrap_or_else(|| panic!("Unknown timing {name}, expected fixed or vip"));
+                }
+                "--rewind-interval" => {
+                    rewind_interval = args
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v| *v > 0)
+                        .expect("--rewind-interval needs a positive number of frames");
+                }
+                "--rewind-memory" => {
+                    let mib: usize = args
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .expect("--rewind-memory needs a size in MiB");
+                    rewind_memory = mib << 20;
+                }
+                "--seed" => {
+                    seed = Some(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .expect("--seed needs a number"),
+                    );
+                }
+                "--debug" => debug = true,
+                "--break" => {
+                    breakpoints.push(
+                        args.next()
+                            .and_then(|v| parse_addr(&v))
+                            .expect("--break needs an address like 0x200"),
+                    );
+                }
+                "--watch" => {
+                    watchpoints.push(
+                        args.next()
+                            .and_then(|v| parse_range(&v))
+                            .expect("--watch needs an address or a range like 0x300-0x302"),
+                    );
+                }
+                "--trace" => trace = Some(args.next().expect("--trace needs a file path")),
+                "--trace-format" => {
+                    let name = args.next().expect("--trace-format needs a format name");
+                    trace_format = TraceFormat::from_name(&name).unwrap_or_else(|| {
+                        panic!("Unknown trace format {name}, expected text or binary")
+                    });
+                }
+                _ => rom = Some(arg),
+            }
+        }
+
+        Self {
+            rom: rom.expect("No rom passed, needs a chip-8 rom path as an argument"),
+            variant,
+            quirks,
+            palette,
+            volume,
+            mute,
+            ipf,
+            timing,
+            rewind_interval,
+            rewind_memory,
+            seed,
+            debug,
+            breakpoints,
+            watchpoints,
+            trace,
+            trace_format,
+        }
+    }
+}
+
+/// Parse an address, hexadecimal with a `0x` prefix or decimal
+fn parse_addr(addr: &str) -> Option<u16> {
+    match addr.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => addr.parse().ok(),
+    }
+}
+
+/// Parse an inclusive address range like `0x300-0x302`, or a single address
+fn parse_range(range: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = match range.split_once('-') {
+        Some((start, end)) => (parse_addr(start)?, parse_addr(end)?),
+        None => (parse_addr(range)?, parse_addr(range)?),
+    };
+    (start <= end).then_some(start..=end)
+}
+
+/// Parse a palette of 4 comma separated hex colors, like `1e1e2e,89b4fa,f38ba8,a6e3a1`
+fn parse_palette(colors: &str) -> Option<[[u8; 4]; 4]> {
+    let mut palette = [[0xFF; 4]; 4];
+    let mut colors = colors.split(',');
+
+    for color in palette.iter_mut() {
+        let hex = colors.next()?.trim().trim_start_matches('#');
+        let rgb = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 6)?;
+        color[..3].copy_from_slice(&rgb.to_be_bytes()[1..]);
+    }
+
+    match colors.next() {
+        Some(_) => None,
+        None => Some(palette),
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex};
+
+use chip8::Buzzer;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SampleFormat, SizedSample, Stream, StreamConfig,
+};
+
+/// Open the default output device and play the buzzer on it
+///
+/// Returns the buzzer shared with the audio thread and the stream, which stops playing when
+/// dropped. Without a usable output device the buzzer is still returned but nothing is played.
+pub fn start(volume: f32, muted: bool) -> (Arc<Mutex<Buzzer>>, Option<Stream>) {
+    let device = cpal::default_host().default_output_device();
+    let config = device
+        .as_ref()
+        .and_then(|device| device.default_output_config().ok());
+
+    let sample_rate = config
+        .as_ref()
+        .map_or(44100, |config| config.sample_rate().0);
+    let mut buzzer = Buzzer::new(sample_rate);
+    buzzer.set_volume(volume);
+    buzzer.set_muted(muted);
+    let buzzer = Arc::new(Mutex::new(buzzer));
+
+    let (Some(device), Some(config)) = (device, config) else {
+        eprintln!("No audio output device found, playing without sound");
+        return (buzzer, None);
+    };
+
+    let stream_config = config.config();
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => build_stream::<f32>(&device, &stream_config, buzzer.clone()),
+        SampleFormat::I16 => build_stream::<i16>(&device, &stream_config, buzzer.clone()),
+        SampleFormat::U16 => build_stream::<u16>(&device, &stream_config, buzzer.clone()),
+        format => Err(format!("unsupported sample format {format}")),
+    };
+
+    match stream {
+        Ok(stream) => (buzzer, Some(stream)),
+        Err(err) => {
+            eprintln!("Could not start audio, playing without sound: {}", err);
+            (buzzer, None)
+        }
+    }
+}
+
+/// Build and start an output stream that renders the buzzer to every channel
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    buzzer: Arc<Mutex<Buzzer>>,
+) -> Result<Stream, String>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+    let mut mono = Vec::new();
+
+    let stream = device
+        .build_output_stream(
+            config,
+            move |data: &mut [T], _| {
+                mono.resize(data.len() / channels, 0.);
+                buzzer.lock().expect("Buzzer lock poisoned").fill(&mut mono);
+
+                for (frame, sample) in data.chunks_mut(channels).zip(&mono) {
+                    frame.fill(T::from_sample(*sample));
+                }
+            },
+            |err| eprintln!("Audio error: {}", err),
+            None,
+        )
+        .map_err(|err| err.to_string())?;
+
+    stream.play().map_err(|err| err.to_string())?;
+
+    Ok(stream)
+}
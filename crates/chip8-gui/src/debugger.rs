@@ -0,0 +1,205 @@
+use std::collections::BTreeSet;
+
+use chip8::Chip8;
+
+/// How the debugger lets the emulator run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Running freely until a breakpoint or watchpoint
+    Running,
+    /// Not running any instructions
+    Paused,
+    /// Running the given number of instructions before pausing
+    Steps(u32),
+    /// Running until the current frame ends, with the same boundary as running freely
+    Frame,
+}
+
+/// A step debugger that pauses the game loop, steps it and stops at breakpoints
+///
+/// The state of the emulator is shown in the window overlay whenever it pauses.
+///
+/// * `breakpoints`: The addresses to pause at before executing them
+/// * `mode`: If the emulator runs, is paused or runs a step
+/// * `resumed`: If the emulator just resumed, so it runs the breakpoint it was paused at instead
+///   of stopping there again
+/// * `status`: The last thing the debugger did, shown in the overlay
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    mode: Mode,
+    resumed: bool,
+    status: String,
+}
+
+impl Debugger {
+    /// Create a debugger, optionally starting paused
+    pub fn new(paused: bool, breakpoints: impl IntoIterator<Item = u16>) -> Self {
+        Self {
+            breakpoints: breakpoints.into_iter().collect(),
+            mode: if paused { Mode::Paused } else { Mode::Running },
+            resumed: false,
+            status: String::new(),
+        }
+    }
+
+    /// If the emulator is paused
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    /// If the emulator is paused or running a step, rather than running freely
+    pub fn is_debugging(&self) -> bool {
+        self.mode != Mode::Running
+    }
+
+    /// The breakpoints set, in address order
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// The last thing the debugger did, empty if nothing happened since the last pause
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    /// Pause or resume the emulator
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.resume(Mode::Running);
+        } else {
+            self.pause();
+            self.status.clear();
+        }
+    }
+
+    /// Run `instructions` more instructions and pause again, only while paused
+    pub fn step(&mut self, instructions: u32) {
+        if self.is_paused() {
+            self.resume(Mode::Steps(instructions));
+            self.status = match instructions {
+                1 => "Stepped an instruction".to_string(),
+                n => format!("Stepped {} instructions", n),
+            };
+        }
+    }
+
+    /// Run until the current frame ends and pause again, only while paused
+    pub fn step_frame(&mut self) {
+        if self.is_paused() {
+            self.resume(Mode::Frame);
+            self.status = "Stepped to the end of the frame".to_string();
+        }
+    }
+
+    /// Add or remove a breakpoint at the program counter
+    pub fn toggle_breakpoint(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc();
+        if self.breakpoints.remove(&pc) {
+            self.status = format!("Removed breakpoint at {:#06x}", pc);
+        } else {
+            self.breakpoints.insert(pc);
+            self.status = format!("Added breakpoint at {:#06x}", pc);
+        }
+    }
+
+    fn resume(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.resumed = true;
+    }
+
+    /// Call before each instruction, returns if the emulator should run it
+    ///
+    /// Pauses if the instruction has a breakpoint, and counts the instruction against the steps.
+    pub fn before_instruction(&mut self, chip8: &Chip8) -> bool {
+        if self.is_paused() {
+            return false;
+        }
+
+        if !std::mem::take(&mut self.resumed) && self.breakpoints.contains(&chip8.pc()) {
+            self.pause();
+            self.status = format!("Breakpoint at {:#06x}", chip8.pc());
+            return false;
+        }
+
+        if let Mode::Steps(steps) = self.mode {
+            self.mode = match steps {
+                0 | 1 => Mode::Paused,
+                n => Mode::Steps(n - 1),
+            };
+        }
+        true
+    }
+
+    /// Call when a frame finished, pauses at the end of a frame step
+    pub fn frame_finished(&mut self) {
+        if self.mode == Mode::Frame {
+            self.pause();
+        }
+    }
+
+    /// Pause after an instruction accessed a watched address
+    pub fn watchpoint(&mut self, addr: u16, pc: u16, old: u8, new: u8) {
+        self.pause();
+        self.status = format!(
+            "Watchpoint {:#06x} accessed at {:#06x}, {:#04x} -> {:#04x}",
+            addr, pc, old, new
+        );
+    }
+
+    /// Pause and cancel a running step
+    fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::{FrameEnd, Timing};
+
+    use super::*;
+
+    /// Run a frame with the debugger like the game loop does
+    fn run_frame(chip8: &mut Chip8, debugger: &mut Debugger) -> (u32, FrameEnd) {
+        let frame = chip8
+            .run_frame_with(8, |chip8| debugger.before_instruction(chip8))
+            .unwrap();
+        if frame.end == FrameEnd::Finished {
+            debugger.frame_finished();
+        }
+        (frame.cycles, frame.end)
+    }
+
+    #[test]
+    fn steps_frames_at_the_frame_boundary() {
+        // JP 0x200 takes 80 of the 1836 cycles of a VIP frame
+        let mut chip8 = Chip8::builder(vec![0x12, 0x00])
+            .timing(Timing::CosmacVip)
+            .build()
+            .unwrap();
+        chip8.set_delay_timer(5);
+        let mut debugger = Debugger::new(true, []);
+
+        debugger.step(1);
+        assert_eq!(
+            run_frame(&mut chip8, &mut debugger),
+            (1, FrameEnd::Interrupted)
+        );
+        assert!(debugger.is_paused());
+
+        // The rest of the frame runs and the timers tick once
+        debugger.step_frame();
+        assert_eq!(
+            run_frame(&mut chip8, &mut debugger),
+            (22, FrameEnd::Finished)
+        );
+        assert!(debugger.is_paused());
+        assert_eq!(chip8.delay_timer(), 4);
+
+        debugger.step_frame();
+        assert_eq!(
+            run_frame(&mut chip8, &mut debugger),
+            (23, FrameEnd::Finished)
+        );
+        assert_eq!(chip8.delay_timer(), 3);
+    }
+}
@@ -0,0 +1,97 @@
+/// The width of a character cell in pixels, a glyph and a column of spacing
+pub const CHAR_WIDTH: usize = 4;
+/// The height of a line in pixels, a glyph and a row of spacing
+pub const LINE_HEIGHT: usize = 6;
+
+/// 3x5 glyphs for the characters from ` ` to `_`, each row is 3 bits with the left pixel highest
+const GLYPHS: [[u8; 5]; 64] = [
+    [0, 0, 0, 0, 0], // ' '
+    [2, 2, 2, 0, 2], // '!'
+    [5, 5, 0, 0, 0], // '"'
+    [5, 7, 5, 7, 5], // '#'
+    [3, 6, 7, 3, 6], // '$'
+    [5, 1, 2, 4, 5], // '%'
+    [2, 5, 2, 5, 3], // '&'
+    [2, 2, 0, 0, 0], // '\''
+    [1, 2, 2, 2, 1], // '('
+    [4, 2, 2, 2, 4], // ')'
+    [0, 5, 2, 5, 0], // '*'
+    [0, 2, 7, 2, 0], // '+'
+    [0, 0, 0, 2, 4], // ','
+    [0, 0, 7, 0, 0], // '-'
+    [0, 0, 0, 0, 2], // '.'
+    [1, 1, 2, 4, 4], // '/'
+    [7, 5, 5, 5, 7], // '0'
+    [2, 6, 2, 2, 7], // '1'
+    [7, 1, 7, 4, 7], // '2'
+    [7, 1, 3, 1, 7], // '3'
+    [5, 5, 7, 1, 1], // '4'
+    [7, 4, 7, 1, 7], // '5'
+    [7, 4, 7, 5, 7], // '6'
+    [7, 1, 1, 2, 2], // '7'
+    [7, 5, 7, 5, 7], // '8'
+    [7, 5, 7, 1, 7], // '9'
+    [0, 2, 0, 2, 0], // ':'
+    [0, 2, 0, 2, 4], // ';'
+    [1, 2, 4, 2, 1], // '<'
+    [0, 7, 0, 7, 0], // '='
+    [4, 2, 1, 2, 4], // '>'
+    [7, 1, 3, 0, 2], // '?'
+    [2, 5, 7, 4, 3], // '@'
+    [2, 5, 7, 5, 5], // 'A'
+    [6, 5, 6, 5, 6], // 'B'
+    [3, 4, 4, 4, 3], // 'C'
+    [6, 5, 5, 5, 6], // 'D'
+    [7, 4, 6, 4, 7], // 'E'
+    [7, 4, 6, 4, 4], // 'F'
+    [3, 4, 5, 5, 3], // 'G'
+    [5, 5, 7, 5, 5], // 'H'
+    [7, 2, 2, 2, 7], // 'I'
+    [1, 1, 1, 5, 2], // 'J'
+    [5, 5, 6, 5, 5], // 'K'
+    [4, 4, 4, 4, 7], // 'L'
+    [5, 7, 7, 5, 5], // 'M'
+    [6, 5, 5, 5, 5], // 'N'
+    [2, 5, 5, 5, 2], // 'O'
+    [6, 5, 6, 4, 4], // 'P'
+    [2, 5, 5, 6, 3], // 'Q'
+    [6, 5, 6, 5, 5], // 'R'
+    [3, 4, 2, 1, 6], // 'S'
+    [7, 2, 2, 2, 2], // 'T'
+    [5, 5, 5, 5, 7], // 'U'
+    [5, 5, 5, 5, 2], // 'V'
+    [5, 5, 7, 7, 5], // 'W'
+    [5, 5, 2, 5, 5], // 'X'
+    [5, 5, 2, 2, 2], // 'Y'
+    [7, 1, 2, 4, 7], // 'Z'
+    [3, 2, 2, 2, 3], // '['
+    [4, 4, 2, 1, 1], // '\\'
+    [6, 2, 2, 2, 6], // ']'
+    [2, 5, 0, 0, 0], // '^'
+    [0, 0, 0, 0, 7], // '_'
+];
+
+/// Draw text into an RGBA frame `width` pixels wide, with the top left corner at x, y
+///
+/// Lowercase letters are drawn as uppercase, other characters without a glyph as `?`. Text past
+/// the edges of the frame is cut off.
+pub fn draw_text(frame: &mut [u8], width: usize, x: usize, y: usize, text: &str, color: [u8; 4]) {
+    let height = frame.len() / 4 / width;
+
+    for (n, c) in text.chars().enumerate() {
+        let glyph = match c.to_ascii_uppercase() {
+            c @ ' '..='_' => &GLYPHS[c as usize - ' ' as usize],
+            _ => &GLYPHS['?' as usize - ' ' as usize],
+        };
+
+        for (row, bits) in glyph.iter().enumerate() {
+            for col in 0..3 {
+                let (px, py) = (x + n * CHAR_WIDTH + col, y + row);
+                if bits & (0b100 >> col) != 0 && px < width && py < height {
+                    let i = (py * width + px) * 4;
+                    frame[i..i + 4].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+}
@@ -1,29 +1,65 @@
-use chip8::Chip8;
+mod args;
+mod audio;
+mod debugger;
+mod font;
+mod overlay;
+mod rewind;
+mod saves;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::{Arc, Mutex};
+
+use args::Args;
+use chip8::{Access, Buzzer, Chip8, Chip8Error, FrameEnd, Tracer};
+use debugger::Debugger;
 use game_loop::{
     game_loop,
     winit::{
         dpi::{LogicalSize, PhysicalSize, Size},
-        event::{ElementState, Event, VirtualKeyCode, WindowEvent},
+        event::{ElementState, Event, ModifiersState, VirtualKeyCode, WindowEvent},
         event_loop::EventLoop,
         window::WindowBuilder,
     },
 };
 use pixels::{Pixels, SurfaceTexture};
+use rewind::Rewind;
 
 /// The struct used for the game loop, needs data for the emulator and display
 struct Game {
     chip8: Chip8,
+    /// The path to the rom, save slots are stored next to it
+    rom_path: String,
+    /// The currently held keyboard modifiers
+    modifiers: ModifiersState,
     pixels: Pixels,
+    palette: [[u8; 4]; 4],
+    /// The error that halted the emulator, if any
+    error: Option<Chip8Error>,
+    /// The size of the pixel buffer, follows the resolution of the emulator or the debugger overlay
+    resolution: (usize, usize),
+    /// If the display changed since it was last drawn
+    redraw: bool,
+    /// The number of instructions run per frame
+    ipf: u32,
+    /// The buzzer shared with the audio thread
+    buzzer: Arc<Mutex<Buzzer>>,
+    /// The audio stream, kept alive for as long as the game runs
+    _audio: Option<cpal::Stream>,
+    /// The recent history of the emulator
+    rewind: Rewind,
+    /// If the rewind key is held
+    rewinding: bool,
+    /// Pauses, steps and breaks the emulator
+    debugger: Debugger,
+    /// Writes the execution trace, if enabled
+    tracer: Option<Tracer<BufWriter<File>>>,
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let args = Args::parse();
 
-    let rom = std::fs::read(
-        args.get(1)
-            .expect("No rom passed, needs a chip-8 rom path as a second argument"),
-    )
-    .expect("Could not read the rom passed, maybe you passed a wrong path?");
+    let rom = load_rom(&args.rom);
 
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
@@ -39,43 +75,174 @@ fn main() {
         .build(&event_loop)
         .expect("Could not create a window");
 
-    let chip8 = Chip8::new(rom);
+    let mut builder = Chip8::builder(rom)
+        .variant(args.variant)
+        .timing(args.timing);
+    if let Some(quirks) = args.quirks {
+        builder = builder.quirks(quirks);
+    }
+    if let Some(seed) = args.seed {
+        builder = builder.seed(seed);
+    }
+    let mut chip8 = builder
+        .build()
+        .unwrap_or_else(|err| panic!("Could not load {}: {}", args.rom, err));
+    for range in args.watchpoints {
+        chip8.add_watchpoint(range, Access::Write);
+    }
+    let resolution = (chip8.width(), chip8.height());
+
+    let tracer = args.trace.map(|path| {
+        let file = File::create(&path)
+            .unwrap_or_else(|err| panic!("Could not create the trace {}: {}", path, err));
+        Tracer::new(BufWriter::new(file), args.trace_format)
+    });
 
     let surface_texture = SurfaceTexture::new(640, 320, &window);
-    let pixels =
-        Pixels::new(64, 32, surface_texture).expect("Could not instantiate Pixels library");
+    let pixels = Pixels::new(resolution.0 as u32, resolution.1 as u32, surface_texture)
+        .expect("Could not instantiate Pixels library");
 
-    let game = Game { chip8, pixels };
+    let (buzzer, audio) = audio::start(args.volume, args.mute);
+
+    let game = Game {
+        chip8,
+        rom_path: args.rom,
+        modifiers: ModifiersState::empty(),
+        pixels,
+        palette: args.palette,
+        error: None,
+        resolution,
+        redraw: true,
+        ipf: args.ipf,
+        buzzer,
+        _audio: audio,
+        rewind: Rewind::new(args.rewind_interval, args.rewind_memory),
+        rewinding: false,
+        debugger: Debugger::new(args.debug, args.breakpoints),
+        tracer,
+    };
 
     game_loop(
         event_loop,
         window,
         game,
-        500,
+        60,
         0.1,
         |g| {
-            if g.number_of_updates() % 8 == 0 {
-                g.game.chip8.decrease_timers();
-            };
+            // Stop emulating once the emulator has errored, but keep the window open
+            let running = g.game.error.is_none();
+
+            if g.game.rewinding {
+                g.game.rewind.rewind(&mut g.game.chip8);
+                g.game.error = None;
+                g.game.redraw = true;
+            } else if running && !g.game.debugger.is_paused() {
+                let Game {
+                    chip8,
+                    debugger,
+                    tracer,
+                    ipf,
+                    ..
+                } = &mut g.game;
+
+                let result = chip8.run_frame_with(*ipf, |chip8| {
+                    if !debugger.before_instruction(chip8) {
+                        return false;
+                    }
+                    if let Some(writer) = tracer {
+                        if let Err(err) = writer.trace(chip8) {
+                            eprintln!("Could not write the trace: {}", err);
+                            *tracer = None;
+                        }
+                    }
+                    true
+                });
+
+                match result {
+                    Ok(frame) => {
+                        g.game.redraw |= frame.display_changed;
+                        match frame.end {
+                            FrameEnd::Finished => {
+                                g.game.debugger.frame_finished();
+                                g.game.rewind.record(&g.game.chip8);
+
+                                // Flush once a frame so the trace is complete up to the last frame
+                                // if the emulator is killed
+                                if let Some(Err(err)) = g.game.tracer.as_mut().map(Tracer::flush) {
+                                    eprintln!("Could not write the trace: {}", err);
+                                    g.game.tracer = None;
+                                }
+                            }
+                            FrameEnd::Exited => g.exit(),
+                            FrameEnd::Watchpoint { addr, pc, old, new } => {
+                                g.game.debugger.watchpoint(addr, pc, old, new)
+                            }
+                            FrameEnd::Interrupted => (),
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("Emulator error: {}", err);
+                        g.window.set_title(&format!("CHIP8 Emulator - {}", err));
+                        g.game.error = Some(err);
+                    }
+                }
+            }
 
-            g.game.chip8.cycle();
+            g.game
+                .buzzer
+                .lock()
+                .expect("Buzzer lock poisoned")
+                .update(&g.game.chip8);
         },
         |g| {
-            let frame = g.game.pixels.frame_mut();
+            // Follow the emulator when it switches between low and high resolution, and switch to
+            // the overlay while debugging
+            let debugging = g.game.debugger.is_debugging();
+            let (width, height) = (g.game.chip8.width(), g.game.chip8.height());
+            let size = if debugging {
+                (overlay::WIDTH, overlay::HEIGHT)
+            } else {
+                (width, height)
+            };
+            if g.game.resolution != size {
+                g.game
+                    .pixels
+                    .resize_buffer(size.0 as u32, size.1 as u32)
+                    .expect("Could not resize pixel buffer");
+                g.game.resolution = size;
+                g.game.redraw = true;
+
+                // Grow the window so the overlay text is at least doubled
+                let inner = g.window.inner_size();
+                let min = PhysicalSize::new(2 * size.0 as u32, 2 * size.1 as u32);
+                if debugging && (inner.width < min.width || inner.height < min.height) {
+                    g.window.set_inner_size(PhysicalSize::new(
+                        inner.width.max(min.width),
+                        inner.height.max(min.height),
+                    ));
+                }
+            }
 
-            for x in 0..64 {
-                for y in 0..32 {
-                    let set = g.game.chip8.display()[y * 64 + x];
+            if debugging {
+                // The debugger state changes without the display, so the overlay is always drawn
+                overlay::draw(
+                    g.game.pixels.frame_mut(),
+                    &g.game.chip8,
+                    &g.game.debugger,
+                    &g.game.palette,
+                );
+            } else if std::mem::take(&mut g.game.redraw) {
+                // Only copy the display when it changed, the pixel buffer keeps the last frame
+                let frame = g.game.pixels.frame_mut();
 
-                    let color_on: [u8; 4] = [0x89, 0xB4, 0xFA, 0xFF];
-                    let color_off: [u8; 4] = [0x1E, 0x1E, 0x2E, 0xFF];
+                for x in 0..width {
+                    for y in 0..height {
+                        let planes = g.game.chip8.display()[y * width + x];
+                        let color = &g.game.palette[planes as usize & 0b11];
 
-                    let pixel_i = (y * 64 + x) * 4;
-                    frame[pixel_i..pixel_i + 4].copy_from_slice(if set {
-                        &color_on
-                    } else {
-                        &color_off
-                    });
+                        let pixel_i = (y * width + x) * 4;
+                        frame[pixel_i..pixel_i + 4].copy_from_slice(color);
+                    }
                 }
             }
 
@@ -96,6 +263,7 @@ fn main() {
                         .expect("Could not resize surface");
                 }
                 WindowEvent::CloseRequested => g.exit(),
+                WindowEvent::ModifiersChanged(modifiers) => g.game.modifiers = *modifiers,
                 WindowEvent::KeyboardInput { input, .. } => {
                     if let Some(k) = input.virtual_keycode {
                         if let Some(hex) = key_to_hex(k) {
@@ -103,6 +271,10 @@ fn main() {
                                 ElementState::Pressed => g.game.chip8.down(hex),
                                 ElementState::Released => g.game.chip8.up(hex),
                             }
+                        } else if k == VirtualKeyCode::Back {
+                            g.game.rewinding = input.state == ElementState::Pressed;
+                        } else if input.state == ElementState::Pressed {
+                            hotkey(&mut g.game, k);
                         }
                     }
                 }
@@ -114,6 +286,85 @@ fn main() {
     );
 }
 
+/// Read the rom at a path, Octo `.8o` sources are compiled first
+fn load_rom(path: &str) -> Vec<u8> {
+    if path.ends_with(".8o") {
+        let source = std::fs::read_to_string(path)
+            .expect("Could not read the source passed, maybe you passed a wrong path?");
+        chip8_octo::compile(&source)
+            .unwrap_or_else(|err| panic!("Could not compile {}:{}", path, err))
+    } else {
+        std::fs::read(path).expect("Could not read the rom passed, maybe you passed a wrong path?")
+    }
+}
+
+/// Handle the emulator hotkeys outside of the keypad
+///
+/// * `M`: Mute or unmute the sound
+/// * `-` and `=`: Decrease or increase the volume
+/// * `F1` to `F9`: Load the save slot with the same number
+/// * `Shift` + `F1` to `F9`: Save to the slot with the same number
+/// * `P`: Pause or resume the emulator
+/// * `F10`: Step a single instruction while paused
+/// * `F11`: Run to the end of the current frame while paused
+/// * `B`: Add or remove a breakpoint at the program counter
+fn hotkey(game: &mut Game, key: VirtualKeyCode) {
+    if let Some(slot) = key_to_slot(key) {
+        let result = if game.modifiers.shift() {
+            saves::save(&game.chip8, &game.rom_path, slot)
+        } else {
+            saves::load(&mut game.chip8, &game.rom_path, slot).map(|()| {
+                // A loaded state is valid, so resume if the emulator was halted by an error
+                game.error = None;
+                game.redraw = true;
+            })
+        };
+
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+        return;
+    }
+
+    let mut buzzer = game.buzzer.lock().expect("Buzzer lock poisoned");
+
+    match key {
+        VirtualKeyCode::P => game.debugger.toggle_pause(),
+        VirtualKeyCode::F10 => game.debugger.step(1),
+        VirtualKeyCode::F11 => game.debugger.step_frame(),
+        VirtualKeyCode::B => game.debugger.toggle_breakpoint(&game.chip8),
+        VirtualKeyCode::M => {
+            let muted = !buzzer.muted();
+            buzzer.set_muted(muted);
+        }
+        VirtualKeyCode::Minus => {
+            let volume = buzzer.volume() - 0.1;
+            buzzer.set_volume(volume);
+        }
+        VirtualKeyCode::Equals => {
+            let volume = buzzer.volume() + 0.1;
+            buzzer.set_volume(volume);
+        }
+        _ => (),
+    }
+}
+
+/// Map the function keys F1 to F9 to save slots
+fn key_to_slot(virtual_key_code: VirtualKeyCode) -> Option<u8> {
+    match virtual_key_code {
+        VirtualKeyCode::F1 => Some(1),
+        VirtualKeyCode::F2 => Some(2),
+        VirtualKeyCode::F3 => Some(3),
+        VirtualKeyCode::F4 => Some(4),
+        VirtualKeyCode::F5 => Some(5),
+        VirtualKeyCode::F6 => Some(6),
+        VirtualKeyCode::F7 => Some(7),
+        VirtualKeyCode::F8 => Some(8),
+        VirtualKeyCode::F9 => Some(9),
+        _ => None,
+    }
+}
+
 /// Map keys to numbers, uses the same layout as chip8 but around rows 1234, qwer, asdf, and zxcv.
 fn key_to_hex(virtual_key_code: VirtualKeyCode) -> Option<u8> {
     match virtual_key_code {
@@ -0,0 +1,160 @@
+use chip8::{Chip8, Instruction, HIRES_HEIGHT, HIRES_WIDTH};
+
+use crate::debugger::Debugger;
+use crate::font::{self, CHAR_WIDTH, LINE_HEIGHT};
+
+/// The number of instructions shown before the program counter
+const CONTEXT_BEFORE: u16 = 4;
+/// The number of instructions shown after the program counter
+const CONTEXT_AFTER: u16 = 6;
+
+/// The number of stack entries shown on a line
+const STACK_PER_LINE: usize = 8;
+
+/// The size of the display at the top of the overlay, the high resolution display doubled
+const DISPLAY_WIDTH: usize = 2 * HIRES_WIDTH;
+const DISPLAY_HEIGHT: usize = 2 * HIRES_HEIGHT;
+
+/// The number of text lines below the display
+const LINES: usize = 23;
+
+/// The size of the pixel buffer while the overlay is shown
+pub const WIDTH: usize = DISPLAY_WIDTH;
+pub const HEIGHT: usize = DISPLAY_HEIGHT + 2 + LINES * LINE_HEIGHT;
+
+/// Draw the display with the debugger state below it into a frame of the overlay size
+///
+/// Shows the registers, timers, stack, the instructions around the program counter, the
+/// breakpoints and the last thing the debugger did.
+pub fn draw(frame: &mut [u8], chip8: &Chip8, debugger: &Debugger, palette: &[[u8; 4]; 4]) {
+    let (width, height) = (chip8.width(), chip8.height());
+    let scale = DISPLAY_WIDTH / width;
+
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let color = if y < height * scale {
+                let planes = chip8.display()[y / scale * width + x / scale];
+                &palette[planes as usize & 0b11]
+            } else if y == DISPLAY_HEIGHT {
+                // Separate the display from the text
+                &palette[1]
+            } else {
+                &palette[0]
+            };
+
+            let pixel_i = (y * WIDTH + x) * 4;
+            frame[pixel_i..pixel_i + 4].copy_from_slice(color);
+        }
+    }
+
+    for (n, line) in lines(chip8, debugger).iter().take(LINES).enumerate() {
+        let y = DISPLAY_HEIGHT + 2 + n * LINE_HEIGHT;
+        font::draw_text(frame, WIDTH, CHAR_WIDTH, y, line, palette[1]);
+    }
+}
+
+/// The text lines of the debugger state
+fn lines(chip8: &Chip8, debugger: &Debugger) -> Vec<String> {
+    let pc = chip8.pc();
+    let mut lines = vec![format!(
+        "PC {:04X}  I {:04X}  DT {:3}  ST {:3}  {:?}",
+        pc,
+        chip8.i(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        chip8.state()
+    )];
+
+    for (row, vs) in chip8.registers().chunks(4).enumerate() {
+        let registers: Vec<String> = vs
+            .iter()
+            .enumerate()
+            .map(|(n, v)| format!("V{:X} {:02X}", row * 4 + n, v))
+            .collect();
+        lines.push(registers.join("  "));
+    }
+
+    // Always two lines, so the instructions below do not move as the stack grows
+    let stack: Vec<String> = chip8
+        .stack()
+        .iter()
+        .map(|addr| format!("{:04X}", addr))
+        .collect();
+    let mut chunks = stack.chunks(STACK_PER_LINE);
+    lines.push(format!(
+        "STACK {}",
+        chunks.next().unwrap_or_default().join(" ")
+    ));
+    lines.push(format!(
+        "      {}",
+        chunks.next().unwrap_or_default().join(" ")
+    ));
+    lines.push(String::new());
+
+    let mem = chip8.memory();
+    let start = pc.saturating_sub(2 * CONTEXT_BEFORE);
+    for addr in (start..pc.saturating_add(2 * CONTEXT_AFTER)).step_by(2) {
+        let (Some(hi), Some(lo)) = (mem.get(addr as usize), mem.get(addr as usize + 1)) else {
+            break;
+        };
+        let opcode = u16::from_be_bytes([*hi, *lo]);
+        let text = match Instruction::decode(opcode) {
+            Ok(instruction) => instruction.to_string(),
+            Err(_) => "??".to_string(),
+        };
+        let marker = if addr == pc { '>' } else { ' ' };
+        let breakpoint = if debugger.breakpoints().contains(&addr) {
+            '*'
+        } else {
+            ' '
+        };
+        lines.push(format!(
+            "{}{} {:04X}  {:02X} {:02X}  {}",
+            marker, breakpoint, addr, hi, lo, text
+        ));
+    }
+    lines.resize(LINES - 4, String::new());
+
+    let breakpoints: Vec<String> = debugger
+        .breakpoints()
+        .iter()
+        .map(|addr| format!("{:04X}", addr))
+        .collect();
+    lines.push(format!("BREAK {}", breakpoints.join(" ")));
+    lines.push(debugger.status().to_string());
+    lines.push(String::new());
+    lines.push("P RESUME  F10 STEP  F11 FRAME  B BREAKPOINT".to_string());
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_every_line() {
+        // CALL 0x204; JP 0x202; RET
+        let mut chip8 = Chip8::new(vec![0x22, 0x04, 0x12, 0x02, 0x00, 0xee]).unwrap();
+        chip8.cycle().unwrap();
+        let mut debugger = Debugger::new(true, [0x206]);
+        debugger.toggle_breakpoint(&chip8);
+
+        let lines = lines(&chip8, &debugger);
+        assert_eq!(lines.len(), LINES);
+        assert_eq!(lines[5], "STACK 0202");
+        assert!(lines.contains(&">* 0204  00 EE  RET".to_string()));
+        assert!(lines.contains(&" * 0206  00 00  ??".to_string()));
+        assert_eq!(lines[LINES - 4], "BREAK 0204 0206");
+        assert_eq!(lines[LINES - 3], "Added breakpoint at 0x0204");
+
+        let mut frame = vec![0; WIDTH * HEIGHT * 4];
+        draw(
+            &mut frame,
+            &chip8,
+            &debugger,
+            &[[0; 4], [0xff; 4], [0; 4], [0; 4]],
+        );
+        assert!(frame[DISPLAY_HEIGHT * WIDTH * 4..].contains(&0xff));
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::VecDeque;
+
+use chip8::Chip8;
+
+/// The number of snapshots stored as deltas after each keyframe
+const DELTAS_PER_KEYFRAME: usize = 60;
+
+/// A keyframe snapshot followed by snapshots stored as deltas against it
+///
+/// * `keyframe`: The full save state
+/// * `deltas`: Encoded deltas against the keyframe, oldest first
+/// * `size`: The number of bytes used by the keyframe and the deltas
+struct Group {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+    size: usize,
+}
+
+/// A ring buffer of recent save states for rewinding
+///
+/// Snapshots are recorded every `interval` frames. Most snapshots are stored as the difference to
+/// the latest keyframe, since only a few bytes of the machine change between frames. When the
+/// buffer grows past its memory budget the oldest keyframe and its deltas are dropped.
+///
+/// * `groups`: The recorded snapshots, oldest first
+/// * `interval`: The number of frames between snapshots
+/// * `budget`: The maximum number of bytes to use for snapshots
+/// * `size`: The number of bytes currently used for snapshots
+/// * `frame`: The number of frames since the last snapshot was recorded or restored
+pub struct Rewind {
+    groups: VecDeque<Group>,
+    interval: u32,
+    budget: usize,
+    size: usize,
+    frame: u32,
+}
+
+impl Rewind {
+    /// Create an empty rewind buffer that records every `interval` frames within a budget of
+    /// `budget` bytes
+    pub fn new(interval: u32, budget: usize) -> Self {
+        Self {
+            groups: VecDeque::new(),
+            interval: interval.max(1),
+            budget,
+            size: 0,
+            frame: 0,
+        }
+    }
+
+    /// Call once per frame while playing, records a snapshot every `interval` frames
+    pub fn record(&mut self, chip8: &Chip8) {
+        self.frame += 1;
+        if self.frame < self.interval {
+            return;
+        }
+        self.frame = 0;
+
+        let state = chip8.save_state();
+        let delta = self
+            .groups
+            .back()
+            .filter(|group| group.deltas.len() < DELTAS_PER_KEYFRAME)
+            .and_then(|group| encode_delta(&group.keyframe, &state));
+
+        match delta {
+            Some(delta) => {
+                let group = self.groups.back_mut().expect("Delta without a keyframe");
+                group.size += delta.len();
+                self.size += delta.len();
+                group.deltas.push(delta);
+            }
+            None => {
+                self.size += state.len();
+                self.groups.push_back(Group {
+                    size: state.len(),
+                    keyframe: state,
+                    deltas: Vec::new(),
+                });
+            }
+        }
+
+        // Always keep the newest group, even if it alone is over budget
+        while self.size > self.budget && self.groups.len() > 1 {
+            let group = self.groups.pop_front().expect("No group to drop");
+            self.size -= group.size;
+        }
+    }
+
+    /// Call once per frame while rewinding, restores the previous snapshot every `interval` frames
+    /// so history plays backwards at the speed it was recorded
+    pub fn rewind(&mut self, chip8: &mut Chip8) {
+        self.frame += 1;
+        if self.frame < self.interval {
+            return;
+        }
+        self.frame = 0;
+
+        let Some(group) = self.groups.back_mut() else {
+            return;
+        };
+
+        let state = match group.deltas.pop() {
+            Some(delta) => {
+                group.size -= delta.len();
+                self.size -= delta.len();
+                decode_delta(&group.keyframe, &delta)
+            }
+            None => {
+                let group = self.groups.pop_back().expect("No group to rewind");
+                self.size -= group.size;
+                group.keyframe
+            }
+        };
+
+        chip8
+            .load_state(&state)
+            .expect("Rewind recorded an invalid save state");
+    }
+}
+
+/// Append a variable length integer, 7 bits at a time
+fn push_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8 & 0x7f) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+/// Read a variable length integer written by [`push_varint`]
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        v |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+/// Encode the difference between a state and a keyframe of the same length
+///
+/// The delta is a list of runs, each a count of unchanged bytes followed by a count of changed
+/// bytes and the changed bytes themselves. Returns `None` if the lengths differ, which happens
+/// when the resolution changes.
+fn encode_delta(keyframe: &[u8], state: &[u8]) -> Option<Vec<u8>> {
+    if keyframe.len() != state.len() {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < state.len() {
+        let unchanged = keyframe[pos..]
+            .iter()
+            .zip(&state[pos..])
+            .take_while(|(k, s)| k == s)
+            .count();
+        let start = pos + unchanged;
+        let changed = keyframe[start..]
+            .iter()
+            .zip(&state[start..])
+            .take_while(|(k, s)| k != s)
+            .count();
+
+        push_varint(&mut out, unchanged);
+        push_varint(&mut out, changed);
+        out.extend_from_slice(&state[start..start + changed]);
+        pos = start + changed;
+    }
+
+    Some(out)
+}
+
+/// Rebuild a state from a keyframe and a delta made by [`encode_delta`]
+fn decode_delta(keyframe: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut state = keyframe.to_vec();
+    let mut pos = 0;
+    let mut data = 0;
+
+    while data < delta.len() {
+        pos += read_varint(delta, &mut data);
+        let changed = read_varint(delta, &mut data);
+        state[pos..pos + changed].copy_from_slice(&delta[data..data + changed]);
+        pos += changed;
+        data += changed;
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An emulator whose save state differs in a register for every frame
+    fn chip8() -> Chip8 {
+        Chip8::builder(vec![0x12, 0x00]).seed(0).build().unwrap()
+    }
+
+    /// Record `frames` frames, changing v0 and v1 before each, and return the recorded states
+    fn record(rewind: &mut Rewind, chip8: &mut Chip8, frames: usize) -> Vec<Vec<u8>> {
+        (0..frames)
+            .map(|frame| {
+                chip8.set_register(0, frame as u8);
+                chip8.set_register(1, (frame >> 8) as u8);
+                rewind.record(chip8);
+                chip8.save_state()
+            })
+            .collect()
+    }
+
+    /// Rewind until the history is empty and return the restored states, newest first
+    fn rewind_all(rewind: &mut Rewind, chip8: &mut Chip8) -> Vec<Vec<u8>> {
+        let mut states = Vec::new();
+        while !rewind.groups.is_empty() {
+            rewind.rewind(chip8);
+            states.push(chip8.save_state());
+        }
+        states
+    }
+
+    #[test]
+    fn varints() {
+        for v in [
+            0,
+            1,
+            0x7f,
+            0x80,
+            0x3fff,
+            0x4000,
+            0x1f_ffff,
+            0x20_0000,
+            usize::MAX,
+        ] {
+            let mut out = vec![0xaa];
+            push_varint(&mut out, v);
+            let mut pos = 1;
+            assert_eq!(read_varint(&out, &mut pos), v);
+            assert_eq!(pos, out.len());
+        }
+
+        let mut out = Vec::new();
+        push_varint(&mut out, 0x7f);
+        push_varint(&mut out, 0x80);
+        assert_eq!(out, [0x7f, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn deltas() {
+        let keyframe: Vec<u8> = (0..=255).collect();
+        let edit = |changes: &[(usize, u8)]| {
+            let mut state = keyframe.clone();
+            for (pos, v) in changes {
+                state[*pos] = *v;
+            }
+            state
+        };
+
+        let states = [
+            keyframe.clone(),
+            edit(&[(0, 0xff)]),
+            edit(&[(255, 0)]),
+            edit(&[(254, 0), (255, 0)]),
+            edit(&[(0, 1), (1, 0), (100, 0), (200, 0), (255, 1)]),
+            keyframe.iter().map(|v| !v).collect(),
+            // A run of unchanged bytes longer than a single varint byte
+            edit(&[(0, 0xff), (200, 0)]),
+        ];
+        for state in states {
+            let delta = encode_delta(&keyframe, &state).unwrap();
+            assert_eq!(decode_delta(&keyframe, &delta), state);
+        }
+
+        assert_eq!(encode_delta(&keyframe, &[0; 255]), None);
+    }
+
+    #[test]
+    fn rewinds_in_reverse_order() {
+        let mut chip8 = chip8();
+        let mut rewind = Rewind::new(1, usize::MAX);
+
+        // Enough frames to span several keyframes
+        let recorded = record(&mut rewind, &mut chip8, 3 * DELTAS_PER_KEYFRAME + 5);
+        assert_eq!(rewind.groups.len(), 4);
+
+        let restored = rewind_all(&mut rewind, &mut chip8);
+        assert!(restored.iter().eq(recorded.iter().rev()));
+        assert_eq!(rewind.size, 0);
+
+        // Rewinding an empty history leaves the emulator alone
+        rewind.rewind(&mut chip8);
+        assert_eq!(chip8.save_state(), recorded[0]);
+    }
+
+    #[test]
+    fn records_every_interval() {
+        let mut chip8 = chip8();
+        let mut rewind = Rewind::new(3, usize::MAX);
+        let recorded = record(&mut rewind, &mut chip8, 9);
+
+        let mut restored = Vec::new();
+        for _ in 0..9 {
+            rewind.rewind(&mut chip8);
+            restored.push(chip8.save_state());
+        }
+        assert_eq!(restored[2], recorded[8]);
+        assert_eq!(restored[5], recorded[5]);
+        assert_eq!(restored[8], recorded[2]);
+    }
+
+    #[test]
+    fn evicts_the_oldest_keyframes() {
+        let mut chip8 = chip8();
+        let keyframe = chip8.save_state().len();
+
+        // Room for about two and a half groups
+        let mut rewind = Rewind::new(1, keyframe * 5 / 2);
+        let recorded = record(&mut rewind, &mut chip8, 10 * DELTAS_PER_KEYFRAME);
+        assert!(rewind.size <= rewind.budget);
+        assert_eq!(
+            rewind.size,
+            rewind.groups.iter().map(|group| group.size).sum::<usize>()
+        );
+
+        // The newest history survives, older groups are gone
+        let restored = rewind_all(&mut rewind, &mut chip8);
+        assert!(restored.len() < recorded.len());
+        assert!(restored.len() >= DELTAS_PER_KEYFRAME);
+        assert!(restored
+            .iter()
+            .eq(recorded.iter().rev().take(restored.len())));
+    }
+
+    #[test]
+    fn keeps_the_newest_group_over_budget() {
+        let mut chip8 = chip8();
+        let mut rewind = Rewind::new(1, 0);
+        let recorded = record(&mut rewind, &mut chip8, 3);
+
+        assert_eq!(rewind.groups.len(), 1);
+        let restored = rewind_all(&mut rewind, &mut chip8);
+        assert!(restored.iter().eq(recorded.iter().rev()));
+    }
+}
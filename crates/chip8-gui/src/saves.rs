@@ -0,0 +1,27 @@
+use std::path::PathBuf;
+
+use chip8::Chip8;
+
+/// The path of a numbered save slot, stored next to the rom as `<rom>.state<slot>`
+fn slot_path(rom: &str, slot: u8) -> PathBuf {
+    PathBuf::from(format!("{}.state{}", rom, slot))
+}
+
+/// Save the emulator to a numbered slot on disk
+pub fn save(chip8: &Chip8, rom: &str, slot: u8) -> Result<(), String> {
+    let path = slot_path(rom, slot);
+
+    std::fs::write(&path, chip8.save_state())
+        .map_err(|err| format!("Could not write {}: {}", path.display(), err))
+}
+
+/// Load the emulator from a numbered slot on disk
+pub fn load(chip8: &mut Chip8, rom: &str, slot: u8) -> Result<(), String> {
+    let path = slot_path(rom, slot);
+
+    let data = std::fs::read(&path)
+        .map_err(|err| format!("Could not read {}: {}", path.display(), err))?;
+    chip8
+        .load_state(&data)
+        .map_err(|err| format!("Could not load {}: {}", path.display(), err))
+}
@@ -0,0 +1,169 @@
+use chip8::{Quirks, Timing, TraceFormat, Variant, DEFAULT_IPF};
+
+/// How long to run the rom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Cycles(u64),
+    Frames(u64),
+}
+
+/// A key held down for a number of frames
+///
+/// * `key`: The key, 0-F
+/// * `frame`: The frame it is pressed at
+/// * `frames`: The number of frames it is held for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyPress {
+    pub key: u8,
+    pub frame: u64,
+    pub frames: u64,
+}
+
+/// Command line arguments
+///
+/// * `rom`: The path to the rom, `.8o` sources are compiled first
+/// * `variant`: The instruction set to emulate, chosen with `--variant <chip8|schip|xochip>`
+/// * `quirks`: The quirks to emulate, chosen with `--quirks <vip|vip-wait|chip48|schip|xochip>`, defaults
+///   to the quirks of the variant
+/// * `seed`: The seed for the random number generator, chosen with `--seed <n>`, random if unset
+/// * `ipf`: The number of instructions run per 60Hz frame, chosen with `--ipf <n>`, defaults to 8
+///   like the GUI
+/// * `timing`: How long instructions take, chosen with `--timing <fixed|vip>`, `vip` ignores `ipf`
+/// * `limit`: How long to run, chosen with `--cycles <n>` or `--frames <n>`, defaults to 600 frames
+/// * `keys`: Keys to press, chosen with `--key <key>@<frame>[:<frames>]` which can be repeated,
+///   held for a single frame unless the number of frames is given
+/// * `ascii`: Print the display as ASCII art once done, chosen with `--ascii`
+/// * `png`: The path to save the display to as a PNG once done, chosen with `--png <file>`
+/// * `trace`: The file to write an execution trace to, chosen with `--trace <file>`
+/// * `trace_format`: The format of the trace, chosen with `--trace-format <text|binary>`
+pub struct Args {
+    pub rom: String,
+    pub variant: Variant,
+    pub quirks: Option<Quirks>,
+    pub seed: Option<u64>,
+    pub ipf: u32,
+    pub timing: Timing,
+    pub limit: Limit,
+    pub keys: Vec<KeyPress>,
+    pub ascii: bool,
+    pub png: Option<String>,
+    pub trace: Option<String>,
+    pub trace_format: TraceFormat,
+}
+
+impl Args {
+    /// Parse the command line arguments, panics with a message on invalid arguments
+    pub fn parse() -> Self {
+        let mut rom = None;
+        let mut variant = Variant::Chip8;
+        let mut quirks = None;
+        let mut seed = None;
+        let mut ipf = DEFAULT_IPF;
+        let mut timing = Timing::Fixed;
+        let mut limit = Limit::Frames(600);
+        let mut keys = Vec::new();
+        let mut ascii = false;
+        let mut png = None;
+        let mut trace = None;
+        let mut trace_format = TraceFormat::Text;
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--variant" => {
+                    let name = args.next().expect("--variant needs a variant name");
+                    variant = Variant::from_name(&name).unwrap_or_else(|| {
+                        panic!("Unknown variant {name}, expected chip8, schip or xochip")
+                    });
+                }
+                "--quirks" => {
+                    let name = args.next().expect("--quirks needs a preset name");
+                    quirks = Some(Quirks::preset(&name).unwrap_or_else(|| {
+                        panic!(
+                            "Unknown quirks preset {name}, expected vip, vip-wait, chip48, schip or xochip"
+                        )
+                    }));
+                }
+                "--seed" => {
+                    seed = Some(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .expect("--seed needs a number"),
+                    );
+                }
+                "--ipf" => {
+                    ipf = args
+                        .next()
+                        .and_then(|v| v.parse().ok())
+                        .filter(|v| *v > 0)
+                        .expect("--ipf needs a positive number of instructions");
+                }
+                "--timing" => {
+                    let name = args.next().expect("--timing needs a timing name");
+                    timing = Timing::from_name(&name)
+                        .unwrap_or_else(|| panic!("Unknown timing {name}, expected fixed or vip"));
+                }
+                "--cycles" => {
+                    limit = Limit::Cycles(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .expect("--cycles needs a number of cycles"),
+                    );
+                }
+                "--frames" => {
+                    limit = Limit::Frames(
+                        args.next()
+                            .and_then(|v| v.parse().ok())
+                            .expect("--frames needs a number of frames"),
+                    );
+                }
+                "--key" => {
+                    let press = args.next().expect("--key needs a key press");
+                    keys.push(parse_key(&press).unwrap_or_else(|| {
+                        panic!("Invalid key press {press}, expected <key>@<frame>[:<frames>]")
+                    }));
+                }
+                "--ascii" => ascii = true,
+                "--png" => png = Some(args.next().expect("--png needs a file path")),
+                "--trace" => trace = Some(args.next().expect("--trace needs a file path")),
+                "--trace-format" => {
+                    let name = args.next().expect("--trace-format needs a format name");
+                    trace_format = TraceFormat::from_name(&name).unwrap_or_else(|| {
+                        panic!("Unknown trace format {name}, expected text or binary")
+                    });
+                }
+                _ => rom = Some(arg),
+            }
+        }
+
+        Self {
+            rom: rom.expect("No rom passed, needs a chip-8 rom path as an argument"),
+            variant,
+            quirks,
+            seed,
+            ipf,
+            timing,
+            limit,
+            keys,
+            ascii,
+            png,
+            trace,
+            trace_format,
+        }
+    }
+}
+
+/// Parse a key press like `5@60:10`, key 5 held for 10 frames from frame 60
+fn parse_key(press: &str) -> Option<KeyPress> {
+    let (key, time) = press.split_once('@')?;
+    let (frame, frames) = match time.split_once(':') {
+        Some((frame, frames)) => (frame, frames.parse().ok()?),
+        None => (time, 1),
+    };
+
+    Some(KeyPress {
+        key: u8::from_str_radix(key, 16).ok().filter(|key| *key < 16)?,
+        frame: frame.parse().ok()?,
+        frames,
+    })
+}
@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use chip8::Chip8;
+
+/// The characters for the pixels in ASCII art, indexed by the bit planes set for a pixel
+const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+/// The colors for the pixels in PNGs, indexed by the bit planes set for a pixel
+const PALETTE: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0xFF, 0xFF],
+    [0xAA, 0xAA, 0xAA],
+    [0x55, 0x55, 0x55],
+];
+
+/// Draw the display as ASCII art, a line per row
+pub fn ascii(chip8: &Chip8) -> String {
+    let mut art = String::with_capacity((chip8.width() + 1) * chip8.height());
+    for row in chip8.display().chunks(chip8.width()) {
+        art.extend(row.iter().map(|pixel| ASCII[*pixel as usize & 0b11]));
+        art.push('\n');
+    }
+    art
+}
+
+/// Save the display as a PNG, one image pixel per display pixel
+pub fn save_png(chip8: &Chip8, path: &str) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        chip8.width() as u32,
+        chip8.height() as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = chip8
+        .display()
+        .iter()
+        .flat_map(|pixel| PALETTE[*pixel as usize & 0b11])
+        .collect();
+    encoder.write_header()?.write_image_data(&data)
+}
@@ -0,0 +1,115 @@
+mod args;
+mod display;
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::ExitCode;
+
+use args::{Args, Limit};
+use chip8::{Chip8, FrameEnd, Tracer};
+
+/// Run a rom without a window, for CI and batch testing
+///
+/// Runs for a number of cycles or frames timed like the GUI, presses the scripted keys and finally
+/// prints or saves the display. Exits with 1 if the emulator raised an error, after showing the
+/// display it had at the error. Stops early if the rom exits.
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let rom = load_rom(&args.rom);
+    let mut builder = Chip8::builder(rom)
+        .variant(args.variant)
+        .timing(args.timing);
+    if let Some(quirks) = args.quirks {
+        builder = builder.quirks(quirks);
+    }
+    if let Some(seed) = args.seed {
+        builder = builder.seed(seed);
+    }
+    let mut chip8 = builder
+        .build()
+        .unwrap_or_else(|err| panic!("Could not load {}: {}", args.rom, err));
+
+    let mut tracer = args.trace.as_ref().map(|path| {
+        let file = File::create(path)
+            .unwrap_or_else(|err| panic!("Could not create the trace {}: {}", path, err));
+        Tracer::new(BufWriter::new(file), args.trace_format)
+    });
+
+    let (mut cycles, frames) = match args.limit {
+        Limit::Cycles(cycles) => (cycles, u64::MAX),
+        Limit::Frames(frames) => (u64::MAX, frames),
+    };
+
+    let mut error = None;
+    let mut frame = 0;
+    while cycles > 0 && frame < frames {
+        for press in &args.keys {
+            if press.frame == frame {
+                chip8.down(press.key);
+            }
+            if press.frame + press.frames == frame {
+                chip8.up(press.key);
+            }
+        }
+
+        // Stop in the middle of the frame once the cycles run out
+        let result = chip8.run_frame_with(args.ipf, |chip8| {
+            if cycles == 0 {
+                return false;
+            }
+            cycles -= 1;
+
+            if let Some(tracer) = &mut tracer {
+                tracer
+                    .trace(chip8)
+                    .unwrap_or_else(|err| panic!("Could not write the trace: {}", err));
+            }
+            true
+        });
+
+        match result.map(|result| result.end) {
+            Ok(FrameEnd::Finished) => frame += 1,
+            Ok(FrameEnd::Exited) => break,
+            Ok(_) => (),
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        }
+    }
+
+    if let Some(tracer) = &mut tracer {
+        tracer
+            .flush()
+            .unwrap_or_else(|err| panic!("Could not write the trace: {}", err));
+    }
+
+    if args.ascii {
+        print!("{}", display::ascii(&chip8));
+    }
+    if let Some(path) = &args.png {
+        display::save_png(&chip8, path)
+            .unwrap_or_else(|err| panic!("Could not save {}: {}", path, err));
+    }
+
+    match error {
+        Some(err) => {
+            eprintln!("Emulator error: {}", err);
+            ExitCode::FAILURE
+        }
+        None => ExitCode::SUCCESS,
+    }
+}
+
+/// Read the rom at a path, Octo `.8o` sources are compiled first
+fn load_rom(path: &str) -> Vec<u8> {
+    if path.ends_with(".8o") {
+        let source = std::fs::read_to_string(path)
+            .unwrap_or_else(|err| panic!("Could not read {}: {}", path, err));
+        chip8_octo::compile(&source)
+            .unwrap_or_else(|err| panic!("Could not compile {}:{}", path, err))
+    } else {
+        std::fs::read(path).unwrap_or_else(|err| panic!("Could not read {}: {}", path, err))
+    }
+}
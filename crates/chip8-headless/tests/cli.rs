@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Write a rom to a temporary file
+fn rom(name: &str, bytes: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chip8-headless-{}-{}", std::process::id(), name));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+fn run(rom: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8-headless"))
+        .arg(rom)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// The top left corner of the display in the ASCII output
+fn corner(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .take(5)
+        .map(|line| line[..4].to_string())
+        .collect()
+}
+
+#[test]
+fn keys_and_display() {
+    let rom = rom(
+        "keys.ch8",
+        &[
+            0xf0, 0x0a, // LD V0, K
+            0xf0, 0x29, // LD F, V0
+            0xd1, 0x15, // DRW V1, V1, 5
+            0x12, 0x06, // JP 0x206
+        ],
+    );
+
+    // Nothing is drawn while waiting for a key
+    let output = run(&rom, &["--frames", "10", "--ascii"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), 32);
+    assert!(stdout.lines().all(|line| line == ".".repeat(64)));
+
+    let png = std::env::temp_dir().join(format!("chip8-headless-{}.png", std::process::id()));
+    let output = run(
+        &rom,
+        &[
+            "--frames",
+            "10",
+            "--key",
+            "7@2:3",
+            "--ascii",
+            "--png",
+            png.to_str().unwrap(),
+        ],
+    );
+    assert!(output.status.success());
+    assert_eq!(corner(&output), ["####", "...#", "..#.", ".#..", ".#.."]);
+    assert!(std::fs::read(&png).unwrap().starts_with(b"\x89PNG"));
+}
+
+#[test]
+fn errors_and_exit() {
+    let failing = rom("error.ch8", &[0x60, 0x00, 0xff, 0xff]);
+    let output = run(&failing, &["--cycles", "100"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown opcode 0xffff at 0x0202"));
+
+    let exiting = rom("exit.ch8", &[0x00, 0xfd]);
+    let trace = std::env::temp_dir().join(format!("chip8-headless-{}.trace", std::process::id()));
+    let output = run(
+        &exiting,
+        &["--variant", "schip", "--trace", trace.to_str().unwrap()],
+    );
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_to_string(&trace).unwrap().lines().count(), 1);
+}
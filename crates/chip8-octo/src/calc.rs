@@ -0,0 +1,151 @@
+use crate::{OctoError, Token};
+
+/// The binary operators of `:calc` expressions
+const BINARY: [&str; 19] = [
+    "-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=",
+    ">=", ">",
+];
+
+/// The unary operators of `:calc` expressions
+const UNARY: [&str; 13] = [
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor",
+];
+
+/// Parse a number literal, a decimal, `0x` hexadecimal or `0b` binary number with an optional `-`
+pub fn number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+/// Evaluate the tokens of a `:calc` expression
+///
+/// Like Octo, binary operators have no precedence and are evaluated from right to left, so
+/// `2 * 3 + 1` is `8`. `lookup` resolves constants and labels, `end` is the token reported for
+/// errors at the end of the expression.
+pub fn eval(
+    tokens: &[Token],
+    lookup: &dyn Fn(&str) -> Option<f64>,
+    end: &Token,
+) -> Result<f64, OctoError> {
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        lookup,
+        end,
+    };
+    let value = parser.expr()?;
+
+    match parser.tokens.get(parser.pos) {
+        Some(token) => Err(token.error(format!("unexpected {} in expression", token.text))),
+        None => Ok(value),
+    }
+}
+
+/// A recursive descent parser that evaluates while parsing
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<f64>,
+    end: &'a Token,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Result<&Token, OctoError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| self.end.error("expression ends too early"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// A term, optionally followed by an operator and the rest of the expression
+    fn expr(&mut self) -> Result<f64, OctoError> {
+        let lhs = self.term()?;
+
+        let Some(op) = self
+            .tokens
+            .get(self.pos)
+            .map(|t| t.text.as_str())
+            .filter(|op| BINARY.contains(op))
+        else {
+            return Ok(lhs);
+        };
+        self.pos += 1;
+        let rhs = self.expr()?;
+
+        let (l, r) = (lhs as i64, rhs as i64);
+        Ok(match op {
+            "-" => lhs - rhs,
+            "+" => lhs + rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "&" => (l & r) as f64,
+            "|" => (l | r) as f64,
+            "^" => (l ^ r) as f64,
+            "<<" => l.wrapping_shl(r as u32) as f64,
+            ">>" => l.wrapping_shr(r as u32) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "<" => (lhs < rhs) as u8 as f64,
+            "<=" => (lhs <= rhs) as u8 as f64,
+            "==" => (lhs == rhs) as u8 as f64,
+            "!=" => (lhs != rhs) as u8 as f64,
+            ">=" => (lhs >= rhs) as u8 as f64,
+            _ => (lhs > rhs) as u8 as f64,
+        })
+    }
+
+    /// A number, name, parenthesized expression or unary operator applied to a term
+    fn term(&mut self) -> Result<f64, OctoError> {
+        let token = self.next()?.clone();
+
+        if UNARY.contains(&token.text.as_str()) {
+            let v = self.term()?;
+            return Ok(match token.text.as_str() {
+                "-" => -v,
+                "~" => !(v as i64) as f64,
+                "!" => (v == 0.) as u8 as f64,
+                "sin" => v.sin(),
+                "cos" => v.cos(),
+                "tan" => v.tan(),
+                "exp" => v.exp(),
+                "log" => v.ln(),
+                "abs" => v.abs(),
+                "sqrt" => v.sqrt(),
+                "sign" => v.signum(),
+                "ceil" => v.ceil(),
+                _ => v.floor(),
+            });
+        }
+
+        if token.text == "(" {
+            let v = self.expr()?;
+            let close = self.next()?;
+            if close.text != ")" {
+                return Err(close.error("expected )"));
+            }
+            return Ok(v);
+        }
+
+        number(&token.text)
+            .or_else(|| (self.lookup)(&token.text))
+            .ok_or_else(|| token.error(format!("undefined name {}", token.text)))
+    }
+}
@@ -0,0 +1,826 @@
+use std::collections::{HashMap, VecDeque};
+
+use chip8::Instruction;
+
+use crate::{calc, OctoError, SourceLine, Token, ROM_START};
+
+/// The maximum number of macro expansions, more means a macro expands itself forever
+const MAX_EXPANSIONS: usize = 100_000;
+
+/// How a forward reference is patched once its label is defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Patch {
+    /// The 12 bit address of a jump, call or `i :=`
+    Addr,
+    /// The 16 bit word after `i := long`
+    Long,
+    /// The two loads of `:unpack`, the high byte starts with the nibble unless `None` for
+    /// `:unpack long`
+    Unpack(Option<u8>),
+}
+
+/// A reference to a label that was not defined yet
+///
+/// * `addr`: The address of the instruction to patch
+/// * `patch`: How to patch the instruction
+/// * `name`: The name of the label
+struct Fixup {
+    addr: u16,
+    patch: Patch,
+    name: Token,
+}
+
+/// A macro defined with `:macro name args { body }`
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An open structured statement
+#[derive(Debug)]
+enum Block {
+    /// `if ... begin`, jumps past the block if the condition is false
+    If { jump: u16 },
+    /// `else`, jumps past the else branch after the if branch
+    Else { jump: u16 },
+    /// `loop`, and the jumps out of the loop from `while`
+    Loop { start: u16, whiles: Vec<u16> },
+}
+
+/// A compiled condition
+///
+/// * `prelude`: Instructions that compute the condition into vF
+/// * `skip_if_true`: Skips the next instruction if the condition holds
+/// * `skip_if_false`: Skips the next instruction if the condition does not hold
+struct Condition {
+    prelude: Vec<Instruction>,
+    skip_if_true: Instruction,
+    skip_if_false: Instruction,
+}
+
+/// A single pass compiler, forward references are patched at the end
+///
+/// * `tokens`: The tokens left to compile, macro expansions are pushed to the front
+/// * `end`: A token at the end of the source, used for errors at the end of the file
+/// * `rom`: The compiled bytes, starting at [`ROM_START`]
+/// * `here`: The address the next byte is compiled to
+/// * `labels`: Label addresses by name
+/// * `constants`: Values of `:const` and `:calc` by name
+/// * `aliases`: Register aliases by name
+/// * `macros`: Macros by name
+/// * `fixups`: References to labels that were not defined when used
+/// * `blocks`: The open structured statements, innermost last
+/// * `expansions`: The number of macro expansions so far
+/// * `lines`: The source line of every compiled instruction
+pub struct Compiler {
+    tokens: VecDeque<Token>,
+    end: Token,
+    rom: Vec<u8>,
+    here: u16,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+    expansions: usize,
+    lines: Vec<SourceLine>,
+}
+
+impl Compiler {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        let end = tokens.last().map_or(
+            Token {
+                text: String::new(),
+                line: 1,
+                col: 1,
+            },
+            |last| Token {
+                text: String::new(),
+                line: last.line,
+                col: last.col + last.text.chars().count(),
+            },
+        );
+
+        Self {
+            tokens: tokens.into(),
+            end,
+            rom: Vec::new(),
+            here: ROM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            expansions: 0,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Compile all tokens and resolve the forward references, returns the rom and the source map
+    /// in address order
+    pub fn compile(mut self) -> Result<(Vec<u8>, Vec<SourceLine>), OctoError> {
+        // Start with a jump to main, patched like any other forward reference
+        let main = Token {
+            text: "main".to_string(),
+            line: 1,
+            col: 1,
+        };
+        self.fixups.push(Fixup {
+            addr: self.here,
+            patch: Patch::Addr,
+            name: main,
+        });
+        self.instruction(&self.end.clone(), Instruction::Jp(0))?;
+        // The jump to main is not part of any line
+        self.lines.clear();
+
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if let Some((_, token)) = self.blocks.last() {
+            return Err(token.error(format!("{} is never closed", token.text)));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let target = self.labels.get(&fixup.name.text).copied().ok_or_else(|| {
+                match fixup.name.text.as_str() {
+                    "main" if fixup.addr == ROM_START => {
+                        fixup.name.error("the program has no main label")
+                    }
+                    name => fixup.name.error(format!("undefined name {}", name)),
+                }
+            })?;
+            self.patch(&fixup.name, fixup.addr, fixup.patch, target)?;
+        }
+
+        self.lines.sort_by_key(|line| line.addr);
+        Ok((self.rom, self.lines))
+    }
+
+    /// Compile a single statement
+    fn statement(&mut self) -> Result<(), OctoError> {
+        let token = self.next()?;
+
+        if let Some(x) = self.register(&token) {
+            return self.register_statement(&token, x);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(&name, self.here)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = self.register_operand()?;
+                self.aliases.insert(name.text, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.next()?;
+                let value = self.value(&value)?;
+                self.define_constant(&name, value)?;
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc()?;
+                self.define_constant(&name, value)?;
+            }
+            ":byte" => {
+                let (value, at) = match self.peek_is("{") {
+                    true => (self.calc()?, token.clone()),
+                    false => {
+                        let at = self.next()?;
+                        (self.value(&at)?, at)
+                    }
+                };
+                let byte = range(&at, value, -0x80, 0xff, "a byte")?;
+                self.emit(&token, byte as u8)?;
+            }
+            ":org" => {
+                let value = match self.peek_is("{") {
+                    true => self.calc()?,
+                    false => {
+                        let value = self.next()?;
+                        self.value(&value)?
+                    }
+                };
+                self.here = range(&token, value, ROM_START as i64, 0xffff, "memory")? as u16;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(&name, self.here.wrapping_add(1))?;
+            }
+            ":unpack" => {
+                let kind = self.next()?;
+                let nibble = match kind.text.as_str() {
+                    "long" => None,
+                    _ => Some(range(&kind, self.value(&kind)?, 0, 0xf, "a nibble")? as u8),
+                };
+                let name = self.next()?;
+                let target = self.address(&name, Patch::Unpack(nibble), 0xffff)?;
+                let hi = self.aliases.get("unpack-hi").copied().unwrap_or(0x0);
+                let lo = self.aliases.get("unpack-lo").copied().unwrap_or(0x1);
+                self.instruction(&token, Instruction::Ld(hi, 0))?;
+                self.instruction(&token, Instruction::Ld(lo, 0))?;
+                self.patch(&name, self.here - 4, Patch::Unpack(nibble), target)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":call" => {
+                let target = self.next()?;
+                let addr = self.address(&target, Patch::Addr, 0xfff)?;
+                self.instruction(&token, Instruction::Call(addr))?;
+            }
+            ":breakpoint" => {
+                self.name()?;
+            }
+            "clear" => self.instruction(&token, Instruction::Cls)?,
+            "return" | ";" => self.instruction(&token, Instruction::Ret)?,
+            "exit" => self.instruction(&token, Instruction::Exit)?,
+            "lores" => self.instruction(&token, Instruction::Low)?,
+            "hires" => self.instruction(&token, Instruction::High)?,
+            "scroll-left" => self.instruction(&token, Instruction::ScrollLeft)?,
+            "scroll-right" => self.instruction(&token, Instruction::ScrollRight)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.instruction(&token, Instruction::ScrollDown(n))?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.instruction(&token, Instruction::ScrollUp(n))?;
+            }
+            "audio" => self.instruction(&token, Instruction::Audio)?,
+            "plane" => {
+                let n = self.next()?;
+                let n = range(&n, self.value(&n)?, 0, 3, "a plane mask")? as u8;
+                self.instruction(&token, Instruction::Plane(n))?;
+            }
+            "bcd" => {
+                let x = self.register_operand()?;
+                self.instruction(&token, Instruction::Bcd(x))?;
+            }
+            "save" | "load" => {
+                let x = self.register_operand()?;
+                let range = match self.peek_is("-") {
+                    true => {
+                        self.next()?;
+                        Some(self.register_operand()?)
+                    }
+                    false => None,
+                };
+                let instruction = match (token.text.as_str(), range) {
+                    ("save", Some(y)) => Instruction::SaveRange(x, y),
+                    ("save", None) => Instruction::Store(x),
+                    (_, Some(y)) => Instruction::LoadRange(x, y),
+                    (_, None) => Instruction::Load(x),
+                };
+                self.instruction(&token, instruction)?;
+            }
+            "saveflags" => {
+                let x = self.register_operand()?;
+                self.instruction(&token, Instruction::StoreFlags(x))?;
+            }
+            "loadflags" => {
+                let x = self.register_operand()?;
+                self.instruction(&token, Instruction::LoadFlags(x))?;
+            }
+            "sprite" => {
+                let x = self.register_operand()?;
+                let y = self.register_operand()?;
+                let n = self.nibble()?;
+                self.instruction(&token, Instruction::Drw { x, y, n })?;
+            }
+            "jump" | "jump0" => {
+                let target = self.next()?;
+                let addr = self.address(&target, Patch::Addr, 0xfff)?;
+                let instruction = match token.text.as_str() {
+                    "jump" => Instruction::Jp(addr),
+                    _ => Instruction::JpV0(addr),
+                };
+                self.instruction(&token, instruction)?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register_operand()?;
+                let instruction = match token.text.as_str() {
+                    "delay" => Instruction::LdDt(x),
+                    "buzzer" => Instruction::LdSt(x),
+                    _ => Instruction::Pitch(x),
+                };
+                self.instruction(&token, instruction)?;
+            }
+            "i" => self.i_statement(&token)?,
+            "if" => {
+                let condition = self.condition()?;
+                let body = self.next()?;
+                match body.text.as_str() {
+                    "then" => self.condition_skip(&token, condition, false)?,
+                    "begin" => {
+                        self.condition_skip(&token, condition, true)?;
+                        let jump = self.here;
+                        self.instruction(&token, Instruction::Jp(0))?;
+                        self.blocks.push((Block::If { jump }, token));
+                    }
+                    _ => return Err(body.error("expected then or begin")),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some((Block::If { jump }, _)) => {
+                    let skip = self.here;
+                    self.instruction(&token, Instruction::Jp(0))?;
+                    self.patch(&token, jump, Patch::Addr, self.here)?;
+                    self.blocks.push((Block::Else { jump: skip }, token));
+                }
+                _ => return Err(token.error("else without if ... begin")),
+            },
+            "end" => match self.blocks.pop() {
+                Some((Block::If { jump } | Block::Else { jump }, _)) => {
+                    self.patch(&token, jump, Patch::Addr, self.here)?;
+                }
+                _ => return Err(token.error("end without if ... begin")),
+            },
+            "loop" => {
+                let start = self.here;
+                self.blocks.push((
+                    Block::Loop {
+                        start,
+                        whiles: Vec::new(),
+                    },
+                    token,
+                ));
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.condition_skip(&token, condition, true)?;
+                let jump = self.here;
+                self.instruction(&token, Instruction::Jp(0))?;
+                let whiles = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find_map(|(block, _)| match block {
+                        Block::Loop { whiles, .. } => Some(whiles),
+                        _ => None,
+                    })
+                    .ok_or_else(|| token.error("while outside of a loop"))?;
+                whiles.push(jump);
+            }
+            "again" => match self.blocks.pop() {
+                Some((Block::Loop { start, whiles }, _)) => {
+                    self.instruction(&token, Instruction::Jp(start))?;
+                    for jump in whiles {
+                        self.patch(&token, jump, Patch::Addr, self.here)?;
+                    }
+                }
+                _ => return Err(token.error("again without loop")),
+            },
+            "native" => return Err(token.error("native machine code calls are not supported")),
+            name if self.macros.contains_key(name) => self.expand(&token)?,
+            _ => match calc::number(&token.text).or_else(|| self.constant(&token.text)) {
+                // Numbers and constants on their own are data
+                Some(value) => {
+                    let byte = range(&token, value, -0x80, 0xff, "a byte")?;
+                    self.emit(&token, byte as u8)?;
+                }
+                // Any other name is a subroutine call
+                None => {
+                    let addr = self.address(&token, Patch::Addr, 0xfff)?;
+                    self.instruction(&token, Instruction::Call(addr))?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Compile a statement starting with register vX, like `vX := 5` or `vX += vY`
+    fn register_statement(&mut self, token: &Token, x: u8) -> Result<(), OctoError> {
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = self.register(&rhs);
+
+        let instruction = match (op.text.as_str(), y) {
+            (":=", Some(y)) => Instruction::LdReg(x, y),
+            (":=", None) => match rhs.text.as_str() {
+                "random" => Instruction::Rnd(x, self.byte()?),
+                "key" => Instruction::LdKey(x),
+                "delay" => Instruction::LdVxDt(x),
+                _ => Instruction::Ld(x, self.byte_value(&rhs)?),
+            },
+            ("+=", Some(y)) => Instruction::AddReg(x, y),
+            ("+=", None) => Instruction::Add(x, self.byte_value(&rhs)?),
+            ("-=", Some(y)) => Instruction::Sub(x, y),
+            ("-=", None) => Instruction::Add(x, self.byte_value(&rhs)?.wrapping_neg()),
+            ("=-", Some(y)) => Instruction::Subn(x, y),
+            ("|=", Some(y)) => Instruction::Or(x, y),
+            ("&=", Some(y)) => Instruction::And(x, y),
+            ("^=", Some(y)) => Instruction::Xor(x, y),
+            (">>=", Some(y)) => Instruction::Shr(x, y),
+            ("<<=", Some(y)) => Instruction::Shl(x, y),
+            (_, None) if ["=-", "|=", "&=", "^=", ">>=", "<<="].contains(&op.text.as_str()) => {
+                return Err(rhs.error("expected a register"))
+            }
+            _ => return Err(op.error(format!("unknown operator {}", op.text))),
+        };
+
+        self.instruction(token, instruction)
+    }
+
+    /// Compile a statement starting with `i`
+    fn i_statement(&mut self, token: &Token) -> Result<(), OctoError> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => {
+                let rhs = self.next()?;
+                match rhs.text.as_str() {
+                    "hex" => {
+                        let x = self.register_operand()?;
+                        self.instruction(token, Instruction::LdFont(x))
+                    }
+                    "bighex" => {
+                        let x = self.register_operand()?;
+                        self.instruction(token, Instruction::LdBigFont(x))
+                    }
+                    "long" => {
+                        let target = self.next()?;
+                        let addr = self.here;
+                        self.instruction(token, Instruction::LdILong)?;
+                        self.emit(token, 0)?;
+                        self.emit(token, 0)?;
+                        let value = self.address_at(&target, addr, Patch::Long, 0xffff)?;
+                        self.patch(&target, addr, Patch::Long, value)
+                    }
+                    _ => {
+                        let addr = self.address(&rhs, Patch::Addr, 0xfff)?;
+                        self.instruction(token, Instruction::LdI(addr))
+                    }
+                }
+            }
+            "+=" => {
+                let x = self.register_operand()?;
+                self.instruction(token, Instruction::AddI(x))
+            }
+            _ => Err(op.error(format!("unknown operator {}", op.text))),
+        }
+    }
+
+    /// Compile a condition like `vX == 5`, `vX != vY`, `vX key` or `vX < vY`
+    fn condition(&mut self) -> Result<Condition, OctoError> {
+        let x = self.register_operand()?;
+        let op = self.next()?;
+
+        let plain = |skip_if_true, skip_if_false| Condition {
+            prelude: Vec::new(),
+            skip_if_true,
+            skip_if_false,
+        };
+
+        Ok(match op.text.as_str() {
+            "key" => plain(Instruction::Skp(x), Instruction::Sknp(x)),
+            "-key" => plain(Instruction::Sknp(x), Instruction::Skp(x)),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let (eq, ne) = match self.register(&rhs) {
+                    Some(y) => (Instruction::SeReg(x, y), Instruction::SneReg(x, y)),
+                    None => {
+                        let nn = self.byte_value(&rhs)?;
+                        (Instruction::Se(x, nn), Instruction::Sne(x, nn))
+                    }
+                };
+                match op.text.as_str() {
+                    "==" => plain(eq, ne),
+                    _ => plain(ne, eq),
+                }
+            }
+            "<" | ">" | "<=" | ">=" => {
+                let rhs = self.next()?;
+                let y = self.register(&rhs);
+                let n = match y {
+                    Some(_) => 0,
+                    None => self.byte_value(&rhs)?,
+                };
+
+                // Subtract into vF so it holds the no borrow flag, either vX >= rhs for < and >=,
+                // or rhs >= vX for > and <=
+                let prelude = match (op.text.as_str(), y) {
+                    ("<" | ">=", Some(y)) => {
+                        vec![Instruction::LdReg(0xf, x), Instruction::Sub(0xf, y)]
+                    }
+                    ("<" | ">=", None) => vec![Instruction::Ld(0xf, n), Instruction::Subn(0xf, x)],
+                    (_, Some(y)) => vec![Instruction::LdReg(0xf, y), Instruction::Sub(0xf, x)],
+                    (_, None) => vec![Instruction::Ld(0xf, n), Instruction::Sub(0xf, x)],
+                };
+                let flag = match op.text.as_str() {
+                    "<" | ">" => 0,
+                    _ => 1,
+                };
+
+                Condition {
+                    prelude,
+                    skip_if_true: Instruction::Se(0xf, flag),
+                    skip_if_false: Instruction::Sne(0xf, flag),
+                }
+            }
+            _ => return Err(op.error(format!("unknown comparison {}", op.text))),
+        })
+    }
+
+    /// Compile a condition followed by the skip taken if the condition is `skip_if` true or false
+    fn condition_skip(
+        &mut self,
+        token: &Token,
+        condition: Condition,
+        skip_if: bool,
+    ) -> Result<(), OctoError> {
+        for instruction in condition.prelude {
+            self.instruction(token, instruction)?;
+        }
+        match skip_if {
+            true => self.instruction(token, condition.skip_if_true),
+            false => self.instruction(token, condition.skip_if_false),
+        }
+    }
+
+    /// Define a macro, `:macro name args { body }`
+    fn define_macro(&mut self) -> Result<(), OctoError> {
+        let name = self.name()?;
+        let mut args = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            args.push(token.text);
+        }
+
+        let body = self.braced(&name)?;
+        if self.macros.contains_key(&name.text) {
+            return Err(name.error(format!("macro {} is already defined", name.text)));
+        }
+        self.macros.insert(name.text, Macro { args, body });
+        Ok(())
+    }
+
+    /// Expand a macro invocation in place, replacing its arguments in the body
+    fn expand(&mut self, token: &Token) -> Result<(), OctoError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(token.error(format!("macro {} expands forever", token.text)));
+        }
+
+        let count = self.macros[&token.text].args.len();
+        let values = (0..count)
+            .map(|_| self.next())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let m = &self.macros[&token.text];
+        let body: Vec<Token> = m
+            .body
+            .iter()
+            .map(|t| match m.args.iter().position(|arg| *arg == t.text) {
+                Some(n) => Token {
+                    text: values[n].text.clone(),
+                    ..t.clone()
+                },
+                None => t.clone(),
+            })
+            .collect();
+
+        for t in body.into_iter().rev() {
+            self.tokens.push_front(t);
+        }
+        Ok(())
+    }
+
+    /// The tokens up to the `}` matching an already consumed `{`
+    fn braced(&mut self, open: &Token) -> Result<Vec<Token>, OctoError> {
+        let mut depth = 0;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self
+                .tokens
+                .pop_front()
+                .ok_or_else(|| open.error("{ is never closed"))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(tokens),
+                "}" => depth -= 1,
+                _ => (),
+            }
+            tokens.push(token);
+        }
+    }
+
+    /// Evaluate a braced `:calc` expression
+    fn calc(&mut self) -> Result<f64, OctoError> {
+        let open = self.expect("{")?;
+        let tokens = self.braced(&open)?;
+        let here = self.here as f64;
+        calc::eval(
+            &tokens,
+            &|name| match name {
+                "HERE" => Some(here),
+                _ => self.constant(name),
+            },
+            &open,
+        )
+    }
+
+    /// Patch an instruction at `addr` with the address of a label
+    fn patch(
+        &mut self,
+        token: &Token,
+        addr: u16,
+        patch: Patch,
+        target: u16,
+    ) -> Result<(), OctoError> {
+        let at = (addr - ROM_START) as usize;
+        match patch {
+            Patch::Addr => {
+                if target > 0xfff {
+                    return Err(
+                        token.error(format!("{:#06x} does not fit in a 12 bit address", target))
+                    );
+                }
+                self.rom[at] = (self.rom[at] & 0xf0) | (target >> 8) as u8;
+                self.rom[at + 1] = target as u8;
+            }
+            Patch::Long => {
+                self.rom[at + 2..at + 4].copy_from_slice(&target.to_be_bytes());
+            }
+            Patch::Unpack(nibble) => {
+                if nibble.is_some() && target > 0xfff {
+                    return Err(
+                        token.error(format!("{:#06x} does not fit in a 12 bit address", target))
+                    );
+                }
+                self.rom[at + 1] = nibble.map_or(0, |n| n << 4) | (target >> 8) as u8;
+                self.rom[at + 3] = target as u8;
+            }
+        }
+        Ok(())
+    }
+
+    /// The address a token refers to, or a fixup for the instruction about to be compiled if it
+    /// is a label that is not defined yet
+    fn address(&mut self, token: &Token, patch: Patch, max: i64) -> Result<u16, OctoError> {
+        self.address_at(token, self.here, patch, max)
+    }
+
+    /// Like [`Compiler::address`], for an instruction at `addr`
+    fn address_at(
+        &mut self,
+        token: &Token,
+        addr: u16,
+        patch: Patch,
+        max: i64,
+    ) -> Result<u16, OctoError> {
+        match calc::number(&token.text).or_else(|| self.constant(&token.text)) {
+            Some(value) => Ok(range(token, value, 0, max, "an address")? as u16),
+            None if self.register(token).is_some() => Err(token.error("expected an address")),
+            None => {
+                self.fixups.push(Fixup {
+                    addr,
+                    patch,
+                    name: token.clone(),
+                });
+                Ok(0)
+            }
+        }
+    }
+
+    /// The value of a constant or label
+    fn constant(&self, name: &str) -> Option<f64> {
+        self.constants
+            .get(name)
+            .copied()
+            .or_else(|| self.labels.get(name).map(|addr| *addr as f64))
+    }
+
+    /// The value of a number literal, constant or label
+    fn value(&self, token: &Token) -> Result<f64, OctoError> {
+        calc::number(&token.text)
+            .or_else(|| self.constant(&token.text))
+            .ok_or_else(|| token.error(format!("undefined name {}", token.text)))
+    }
+
+    /// The byte value of a token, negative values are stored as two's complement
+    fn byte_value(&self, token: &Token) -> Result<u8, OctoError> {
+        Ok(range(token, self.value(token)?, -0x80, 0xff, "a byte")? as u8)
+    }
+
+    /// Parse the next token as a byte
+    fn byte(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        self.byte_value(&token)
+    }
+
+    /// Parse the next token as a nibble
+    fn nibble(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        Ok(range(&token, self.value(&token)?, 0, 0xf, "a nibble")? as u8)
+    }
+
+    /// The register a token names, either `v0`-`vf` or an alias
+    fn register(&self, token: &Token) -> Option<u8> {
+        if let Some(register) = self.aliases.get(&token.text) {
+            return Some(*register);
+        }
+        let digit = token.text.strip_prefix(['v', 'V'])?;
+        (digit.len() == 1)
+            .then(|| u8::from_str_radix(digit, 16).ok())
+            .flatten()
+    }
+
+    /// Parse the next token as a register
+    fn register_operand(&mut self) -> Result<u8, OctoError> {
+        let token = self.next()?;
+        self.register(&token)
+            .ok_or_else(|| token.error(format!("expected a register, found {}", token.text)))
+    }
+
+    /// Parse the next token as a name for a label, constant, alias or macro
+    fn name(&mut self) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        if calc::number(&token.text).is_some() || self.register(&token).is_some() {
+            return Err(token.error(format!("{} is not a valid name", token.text)));
+        }
+        Ok(token)
+    }
+
+    fn define_label(&mut self, name: &Token, addr: u16) -> Result<(), OctoError> {
+        if self.constant(&name.text).is_some() || self.labels.contains_key(&name.text) {
+            return Err(name.error(format!("{} is already defined", name.text)));
+        }
+        self.labels.insert(name.text.clone(), addr);
+        Ok(())
+    }
+
+    fn define_constant(&mut self, name: &Token, value: f64) -> Result<(), OctoError> {
+        if self.labels.contains_key(&name.text) {
+            return Err(name.error(format!("{} is already a label", name.text)));
+        }
+        self.constants.insert(name.text.clone(), value);
+        Ok(())
+    }
+
+    /// Consume the next token
+    fn next(&mut self) -> Result<Token, OctoError> {
+        self.tokens
+            .pop_front()
+            .ok_or_else(|| self.end.error("unexpected end of the program"))
+    }
+
+    /// If the next token is the text
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|t| t.text == text)
+    }
+
+    /// Consume the next token, which must be the text
+    fn expect(&mut self, text: &str) -> Result<Token, OctoError> {
+        let token = self.next()?;
+        match token.text == text {
+            true => Ok(token),
+            false => Err(token.error(format!("expected {}, found {}", text, token.text))),
+        }
+    }
+
+    /// Compile an instruction
+    fn instruction(&mut self, token: &Token, instruction: Instruction) -> Result<(), OctoError> {
+        self.lines.push(SourceLine {
+            addr: self.here,
+            line: token.line,
+        });
+        for byte in instruction.encode().to_be_bytes() {
+            self.emit(token, byte)?;
+        }
+        Ok(())
+    }
+
+    /// Compile a byte at `here`
+    fn emit(&mut self, token: &Token, byte: u8) -> Result<(), OctoError> {
+        let at = (self.here - ROM_START) as usize;
+        if at >= self.rom.len() {
+            self.rom.resize(at + 1, 0);
+        }
+        self.rom[at] = byte;
+        self.here = self
+            .here
+            .checked_add(1)
+            .ok_or_else(|| token.error("the program does not fit in memory"))?;
+        Ok(())
+    }
+}
+
+/// Truncate a value and check that it is in the range `min..=max`
+fn range(token: &Token, value: f64, min: i64, max: i64, what: &str) -> Result<i64, OctoError> {
+    let v = value as i64;
+    if (min..=max).contains(&v) {
+        Ok(v)
+    } else {
+        Err(token.error(format!("{} does not fit in {}", v, what)))
+    }
+}
@@ -0,0 +1,100 @@
+mod calc;
+mod compiler;
+
+use std::fmt;
+
+/// The address roms are loaded at, the first compiled byte is placed here
+pub const ROM_START: u16 = 0x200;
+
+/// An error raised while compiling, with the location it was raised at
+///
+/// * `line`: The line number, starting at 1
+/// * `col`: The column number, starting at 1
+/// * `message`: A description of the error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OctoError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OctoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for OctoError {}
+
+/// The source line an instruction was compiled from
+///
+/// * `addr`: The address of the instruction
+/// * `line`: The line number, starting at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub line: usize,
+}
+
+/// A whitespace separated word of the source and where it starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token {
+    text: String,
+    line: usize,
+    col: usize,
+}
+
+impl Token {
+    /// Create an error at the token
+    pub(crate) fn error(&self, message: impl Into<String>) -> OctoError {
+        OctoError {
+            line: self.line,
+            col: self.col,
+            message: message.into(),
+        }
+    }
+}
+
+/// Split the source into tokens, `#` starts a comment that runs to the end of the line
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (n, line) in source.lines().enumerate() {
+        let mut start = None;
+        for (col, c) in line.chars().chain([' ']).enumerate() {
+            if c == '#' && start.is_none() {
+                break;
+            }
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(col),
+                (true, Some(s)) => {
+                    tokens.push(Token {
+                        text: line.chars().skip(s).take(col - s).collect(),
+                        line: n + 1,
+                        col: s + 1,
+                    });
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Compile an Octo program into a rom that is loaded at [`ROM_START`]
+///
+/// The program starts with a jump to the `main` label. Supports the Octo instructions for CHIP-8,
+/// SUPER-CHIP and XO-CHIP, labels, `:alias`, `:const`, `:calc`, `:byte`, `:org`, `:next`,
+/// `:unpack`, `:call` and `:macro`, as well as the structured `if ... then`,
+/// `if ... begin ... else ... end` and `loop ... while ... again` statements.
+pub fn compile(source: &str) -> Result<Vec<u8>, OctoError> {
+    compile_with_map(source).map(|(rom, _)| rom)
+}
+
+/// Compile like [`compile`], also returning a source map with the line of every instruction in
+/// address order
+pub fn compile_with_map(source: &str) -> Result<(Vec<u8>, Vec<SourceLine>), OctoError> {
+    compiler::Compiler::new(tokenize(source)).compile()
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+/// Compile an Octo source file into a rom
+///
+/// Takes the source path and optionally `-o <rom>`, the path to write the rom to. Defaults to the
+/// source path with the extension `.ch8`.
+fn main() {
+    let mut source = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().expect("-o needs an output path"))),
+            _ => source = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let source = source.expect("No source passed, needs an Octo source path as an argument");
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+
+    let code = std::fs::read_to_string(&source)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", source.display(), err));
+
+    match chip8_octo::compile(&code) {
+        Ok(rom) => std::fs::write(&output, rom)
+            .unwrap_or_else(|err| panic!("Could not write {}: {}", output.display(), err)),
+        Err(err) => {
+            eprintln!("{}:{}", source.display(), err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use chip8_octo::{compile, compile_with_map, SourceLine};
+
+#[test]
+fn instructions() {
+    let rom = compile(
+        "
+        : main
+            clear
+            v0 := 5
+            v1 += v0
+            v2 -= 1
+            i := sprite
+            sprite v0 v1 5
+            i := long sprite
+            save v2 - v4
+            return
+
+        : sprite
+            0xff 0x81 :byte { 2 * 3 + 1 }
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(
+        rom,
+        [
+            0x12, 0x02, // jump main
+            0x00, 0xe0, 0x60, 0x05, 0x81, 0x04, 0x72, 0xff, 0xa2, 0x16, 0xd0, 0x15, 0xf0, 0x00,
+            0x02, 0x16, 0x52, 0x42, 0x00, 0xee, // main
+            0xff, 0x81, 0x08, // sprite, calc is evaluated right to left
+        ]
+    );
+}
+
+#[test]
+fn structured() {
+    let rom = compile(
+        "
+        :alias counter v3
+        :const LIMIT 10
+        : main
+            loop
+                counter += 1
+                if counter == LIMIT then counter := 0
+                if counter < v4 begin
+                    v5 := 1
+                else
+                    v5 := 2
+                end
+                while counter != 3
+            again
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(
+        rom,
+        [
+            0x12, 0x02, // jump main
+            0x73, 0x01, // counter += 1
+            0x43, 0x0a, 0x63, 0x00, // if counter == LIMIT then counter := 0
+            0x8f, 0x30, 0x8f, 0x45, 0x3f, 0x00, 0x12, 0x14, // if counter < v4 begin
+            0x65, 0x01, 0x12, 0x16, // v5 := 1 else
+            0x65, 0x02, // v5 := 2 end
+            0x43, 0x03, 0x12, 0x1c, // while counter != 3
+            0x12, 0x02, // again
+        ]
+    );
+}
+
+#[test]
+fn macros_next_and_unpack() {
+    let rom = compile(
+        "
+        :macro set reg value { reg := value }
+        : main
+            set v2 7
+            :next target
+            v0 := 0
+            :unpack 0xA data
+            :unpack long data
+        : data
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(
+        rom,
+        [0x12, 0x02, 0x62, 0x07, 0x60, 0x00, 0x60, 0xa2, 0x61, 0x0e, 0x60, 0x02, 0x61, 0x0e]
+    );
+    assert_eq!(
+        compile(": main :next a v0 := 1 i := a").unwrap()[4..],
+        [0xa2, 0x03]
+    );
+}
+
+#[test]
+fn errors() {
+    let error = |source: &str| {
+        let err = compile(source).unwrap_err();
+        (err.line, err.col, err.message)
+    };
+
+    assert_eq!(
+        error("clear"),
+        (1, 1, "the program has no main label".to_string())
+    );
+    assert_eq!(
+        error(": main\n  jump nowhere"),
+        (2, 8, "undefined name nowhere".to_string())
+    );
+    assert_eq!(
+        error(": main v0 := 300"),
+        (1, 14, "300 does not fit in a byte".to_string())
+    );
+    assert_eq!(
+        error(": main loop"),
+        (1, 8, "loop is never closed".to_string())
+    );
+    assert_eq!(
+        error(": main jump end\n: end\n: end"),
+        (3, 3, "end is already defined".to_string())
+    );
+    assert_eq!(
+        error(": main\n: x :next x v0 := 1"),
+        (2, 11, "x is already defined".to_string())
+    );
+}
+
+#[test]
+fn source_map() {
+    let (_, lines) = compile_with_map(
+        "
+        :macro twice { v0 += 1 v0 += 1 }
+        : main
+            twice twice
+            if v0 == 4 then clear
+        ",
+    )
+    .unwrap();
+
+    let line = |addr, line| SourceLine { addr, line };
+    assert_eq!(
+        lines,
+        [
+            line(0x202, 2),
+            line(0x204, 2),
+            line(0x206, 2),
+            line(0x208, 2),
+            line(0x20a, 5),
+            line(0x20c, 5),
+        ]
+    );
+}
@@ -0,0 +1,101 @@
+use crate::Chip8;
+
+/// The time it takes to fade the tone in or out, short enough to be inaudible but long enough to
+/// avoid clicks
+const FADE_SECONDS: f32 = 0.005;
+
+/// Renders the sound of a [`Chip8`] into audio samples
+///
+/// The buzzer plays the audio pattern of the emulator while the sound timer is active. Before
+/// XO-CHIP the pattern is a square wave. Starting and stopping fades the tone to avoid clicks.
+///
+/// * `sample_rate`: The output sample rate in Hz
+/// * `active`: If the sound timer is active
+/// * `pattern`: The 1-bit audio pattern to play
+/// * `rate`: The playback rate of the pattern in bits per second
+/// * `position`: The current position in the pattern, in bits
+/// * `gain`: The current gain of the fade, between 0 and 1
+/// * `volume`: The volume, between 0 and 1
+/// * `muted`: If the buzzer is muted
+#[derive(Debug, Clone)]
+pub struct Buzzer {
+    sample_rate: f32,
+    active: bool,
+    pattern: [u8; 16],
+    rate: f32,
+    position: f32,
+    gain: f32,
+    volume: f32,
+    muted: bool,
+}
+
+impl Buzzer {
+    /// Create a silent buzzer for an output at the sample rate
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate: sample_rate as f32,
+            active: false,
+            pattern: [0; 16],
+            rate: 4000.,
+            position: 0.,
+            gain: 0.,
+            volume: 1.,
+            muted: false,
+        }
+    }
+
+    /// Update the tone from the state of the emulator, call after running the emulator
+    pub fn update(&mut self, chip8: &Chip8) {
+        self.active = chip8.is_sound_active();
+        self.pattern = *chip8.audio_pattern();
+        self.rate = chip8.playback_rate();
+    }
+
+    /// Fill a buffer of mono samples between -1 and 1
+    pub fn fill(&mut self, out: &mut [f32]) {
+        let bits = (self.pattern.len() * 8) as f32;
+        let step = self.rate / self.sample_rate;
+        let fade_step = 1. / (FADE_SECONDS * self.sample_rate);
+        let target = if self.active && !self.muted { 1. } else { 0. };
+
+        for sample in out.iter_mut() {
+            self.gain = if self.gain < target {
+                (self.gain + fade_step).min(target)
+            } else {
+                (self.gain - fade_step).max(target)
+            };
+
+            // Keep the position while silent so the tone restarts where it stopped
+            if self.gain == 0. {
+                *sample = 0.;
+                continue;
+            }
+
+            let bit = self.position as usize;
+            let set = self.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+            *sample = if set { 1. } else { -1. } * self.gain * self.volume;
+
+            self.position = (self.position + step) % bits;
+        }
+    }
+
+    /// Set the volume, clamped between 0 and 1
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0., 1.);
+    }
+
+    /// Get the volume, between 0 and 1
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Mute or unmute the buzzer
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Check if the buzzer is muted
+    pub fn muted(&self) -> bool {
+        self.muted
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{Chip8, Chip8Error, StepOutcome, Timing, VIP_INTERPRETER_CYCLES};
+
+/// The instructions per frame frontends run by default, 480 instructions a second
+pub const DEFAULT_IPF: u32 = 8;
+
+/// How a call to [`Chip8::run_frame`] ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameEnd {
+    /// Every instruction of the frame ran and the timers ticked
+    Finished,
+    /// The program exited with `00FD`
+    Exited,
+    /// The instruction at `pc` accessed a watched address, the rest of the frame runs on the next
+    /// call
+    Watchpoint {
+        addr: u16,
+        pc: u16,
+        old: u8,
+        new: u8,
+    },
+    /// The hook of [`Chip8::run_frame_with`] stopped the frame before an instruction, the rest of
+    /// the frame runs on the next call
+    Interrupted,
+}
+
+/// The result of running a frame
+///
+/// * `cycles`: The number of cycles run, including cycles blocked waiting for a key
+/// * `display_changed`: If the display changed since the last frame, so it needs to be redrawn
+/// * `end`: Why the frame ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameResult {
+    pub cycles: u32,
+    pub display_changed: bool,
+    pub end: FrameEnd,
+}
+
+impl Chip8 {
+    /// Run a 60Hz frame of `ipf` instructions and then tick the timers once
+    ///
+    /// Cycles blocked waiting for a key count towards the frame, so time passes at the same rate.
+    /// A frame that ends early on a watchpoint is resumed by the next call, frontends get the same
+    /// timing by calling this 60 times a second. If an instruction fails the rest of the frame is
+    /// left to run.
+    ///
+    /// With [`Timing::CosmacVip`] `ipf` is ignored, the frame runs until the interpreter spent its
+    /// [`VIP_INTERPRETER_CYCLES`] machine cycles, see [`Chip8::vip_cycles`]. Cycles past the end
+    /// of the frame are taken from the next one, so a slow instruction can span several timer
+    /// ticks, and blocking waits for the rest of the frame.
+    pub fn run_frame(&mut self, ipf: u32) -> Result<FrameResult, Chip8Error> {
+        self.run_frame_with(ipf, |_| true)
+    }
+
+    /// Run a frame like [`Chip8::run_frame`], calling `hook` before each cycle
+    ///
+    /// The frame is interrupted if the hook returns false, which debuggers use for breakpoints and
+    /// stepping.
+    pub fn run_frame_with(
+        &mut self,
+        ipf: u32,
+        mut hook: impl FnMut(&Chip8) -> bool,
+    ) -> Result<FrameResult, Chip8Error> {
+        let mut cycles = 0;
+        let budget = match self.timing {
+            Timing::Fixed => ipf,
+            Timing::CosmacVip => VIP_INTERPRETER_CYCLES,
+        };
+
+        let end = loop {
+            if self.frame_cycles >= budget {
+                self.frame_cycles = match self.timing {
+                    Timing::Fixed => 0,
+                    Timing::CosmacVip => self.frame_cycles - budget,
+                };
+                self.decrease_timers();
+                break FrameEnd::Finished;
+            }
+
+            if !hook(self) {
+                break FrameEnd::Interrupted;
+            }
+
+            let cost = match self.timing {
+                Timing::Fixed => 1,
+                Timing::CosmacVip => self.vip_cycles(),
+            };
+            let outcome = self.cycle()?;
+            cycles += 1;
+            self.frame_cycles += match (self.timing, outcome) {
+                (Timing::CosmacVip, StepOutcome::Blocked) => budget - self.frame_cycles,
+                _ => cost,
+            };
+
+            match outcome {
+                StepOutcome::Exited => break FrameEnd::Exited,
+                StepOutcome::Watchpoint { addr, pc, old, new } => {
+                    break FrameEnd::Watchpoint { addr, pc, old, new }
+                }
+                StepOutcome::Executed | StepOutcome::Blocked => (),
+            }
+        };
+
+        Ok(FrameResult {
+            cycles,
+            display_changed: std::mem::take(&mut self.display_changed),
+            end,
+        })
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+
+use crate::Variant;
+
+/// A decoded instruction
+///
+/// `x` and `y` are register numbers between 0x0 and 0xF, addresses are 12 bits wide. Each variant
+/// is documented with its opcode, where `n` is an immediate nibble, `nn` a byte and `nnn` an
+/// address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `00Cn`: Scroll the display down n pixels
+    ScrollDown(u8),
+    /// `00Dn`: Scroll the display up n pixels
+    ScrollUp(u8),
+    /// `00E0`: Clear the display
+    Cls,
+    /// `00EE`: Return from a subroutine
+    Ret,
+    /// `00FB`: Scroll the display right 4 pixels
+    ScrollRight,
+    /// `00FC`: Scroll the display left 4 pixels
+    ScrollLeft,
+    /// `00FD`: Exit the interpreter
+    Exit,
+    /// `00FE`: Switch to low resolution mode
+    Low,
+    /// `00FF`: Switch to high resolution mode
+    High,
+    /// `1nnn`: Jump to nnn
+    Jp(u16),
+    /// `2nnn`: Call the subroutine at nnn
+    Call(u16),
+    /// `3xnn`: Skip next if vX == nn
+    Se(u8, u8),
+    /// `4xnn`: Skip next if vX != nn
+    Sne(u8, u8),
+    /// `5xy0`: Skip next if vX == vY
+    SeReg(u8, u8),
+    /// `5xy2`: Store registers vX..=vY to memory[I..]
+    SaveRange(u8, u8),
+    /// `5xy3`: Load registers vX..=vY from memory[I..]
+    LoadRange(u8, u8),
+    /// `6xnn`: Write nn to vX
+    Ld(u8, u8),
+    /// `7xnn`: Increase vX by nn
+    Add(u8, u8),
+    /// `8xy0`: Write vY to vX
+    LdReg(u8, u8),
+    /// `8xy1`: Or vX with vY
+    Or(u8, u8),
+    /// `8xy2`: And vX with vY
+    And(u8, u8),
+    /// `8xy3`: Xor vX with vY
+    Xor(u8, u8),
+    /// `8xy4`: Add vY to vX, vF is set to the carry
+    AddReg(u8, u8),
+    /// `8xy5`: Subtract vY from vX, vF is set if there was no borrow
+    Sub(u8, u8),
+    /// `8xy6`: Shift right, vF is set to the shifted out bit
+    Shr(u8, u8),
+    /// `8xy7`: Write vY - vX to vX, vF is set if there was no borrow
+    Subn(u8, u8),
+    /// `8xyE`: Shift left, vF is set to the shifted out bit
+    Shl(u8, u8),
+    /// `9xy0`: Skip next if vX != vY
+    SneReg(u8, u8),
+    /// `Annn`: Write nnn to I
+    LdI(u16),
+    /// `Bnnn`: Jump to v0 + nnn
+    JpV0(u16),
+    /// `Cxnn`: Write a random number anded with nn to vX
+    Rnd(u8, u8),
+    /// `Dxyn`: Draw the n rows long sprite at I to position vX, vY
+    Drw { x: u8, y: u8, n: u8 },
+    /// `Ex9E`: Skip next if key vX is pressed
+    Skp(u8),
+    /// `ExA1`: Skip next if key vX is not pressed
+    Sknp(u8),
+    /// `F000 nnnn`: Write the 16 bit address in the following word to I
+    LdILong,
+    /// `Fn01`: Select the bit planes n for drawing
+    Plane(u8),
+    /// `F002`: Load the 16 byte audio pattern from memory[I..]
+    Audio,
+    /// `Fx07`: Write the delay timer to vX
+    LdVxDt(u8),
+    /// `Fx11`: Write the delay timer to vX, an undocumented duplicate of `Fx07` that older roms
+    /// for this emulator may use
+    LdVxDtAlias(u8),
+    /// `Fx0A`: Block until a key is pressed and released and write it to vX
+    LdKey(u8),
+    /// `Fx15`: Write vX to the delay timer
+    LdDt(u8),
+    /// `Fx18`: Write vX to the sound timer
+    LdSt(u8),
+    /// `Fx1E`: Increase I by vX
+    AddI(u8),
+    /// `Fx29`: Point I at the font sprite for digit vX
+    LdFont(u8),
+    /// `Fx30`: Point I at the large font sprite for digit vX
+    LdBigFont(u8),
+    /// `Fx33`: Write the decimal digits of vX to memory[I..I + 3]
+    Bcd(u8),
+    /// `Fx3A`: Set the audio pattern pitch to vX
+    Pitch(u8),
+    /// `Fx55`: Store registers v0..=vX to memory[I..]
+    Store(u8),
+    /// `Fx65`: Load registers v0..=vX from memory[I..]
+    Load(u8),
+    /// `Fx75`: Store registers v0..=vX in the RPL user flags
+    StoreFlags(u8),
+    /// `Fx85`: Load registers v0..=vX from the RPL user flags
+    LoadFlags(u8),
+}
+
+/// An error raised when decoding an opcode that is not a known instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown opcode {:#06x}", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Instruction {
+    /// Decode an opcode
+    ///
+    /// Instructions of every variant are decoded, use [`Instruction::variant`] to check if it is
+    /// supported. `F000` decodes to [`Instruction::LdILong`], its address is the following word.
+    pub fn decode(opcode: u16) -> Result<Self, DecodeError> {
+        use Instruction::*;
+
+        // Split the opcode into different parts for ease of use
+        let a = ((opcode >> 12) & 0x0f) as u8;
+        let x = ((opcode >> 8) & 0x0f) as u8;
+        let y = ((opcode >> 4) & 0x0f) as u8;
+        let n = (opcode & 0x0f) as u8;
+        let nn = (opcode & 0xff) as u8;
+        let nnn = opcode & 0xfff;
+
+        Ok(match (a, x, y, n) {
+            (0x0, 0x0, 0xc, n) => ScrollDown(n),
+            (0x0, 0x0, 0xd, n) => ScrollUp(n),
+            (0x0, 0x0, 0xe, 0x0) => Cls,
+            (0x0, 0x0, 0xe, 0xe) => Ret,
+            (0x0, 0x0, 0xf, 0xb) => ScrollRight,
+            (0x0, 0x0, 0xf, 0xc) => ScrollLeft,
+            (0x0, 0x0, 0xf, 0xd) => Exit,
+            (0x0, 0x0, 0xf, 0xe) => Low,
+            (0x0, 0x0, 0xf, 0xf) => High,
+            (0x1, _, _, _) => Jp(nnn),
+            (0x2, _, _, _) => Call(nnn),
+            (0x3, x, _, _) => Se(x, nn),
+            (0x4, x, _, _) => Sne(x, nn),
+            (0x5, x, y, 0x0) => SeReg(x, y),
+            (0x5, x, y, 0x2) => SaveRange(x, y),
+            (0x5, x, y, 0x3) => LoadRange(x, y),
+            (0x6, x, _, _) => Ld(x, nn),
+            (0x7, x, _, _) => Add(x, nn),
+            (0x8, x, y, 0x0) => LdReg(x, y),
+            (0x8, x, y, 0x1) => Or(x, y),
+            (0x8, x, y, 0x2) => And(x, y),
+            (0x8, x, y, 0x3) => Xor(x, y),
+            (0x8, x, y, 0x4) => AddReg(x, y),
+            (0x8, x, y, 0x5) => Sub(x, y),
+            (0x8, x, y, 0x6) => Shr(x, y),
+            (0x8, x, y, 0x7) => Subn(x, y),
+            (0x8, x, y, 0xe) => Shl(x, y),
+            (0x9, x, y, 0x0) => SneReg(x, y),
+            (0xa, _, _, _) => LdI(nnn),
+            (0xb, _, _, _) => JpV0(nnn),
+            (0xc, x, _, _) => Rnd(x, nn),
+            (0xd, x, y, n) => Drw { x, y, n },
+            (0xe, x, 0x9, 0xe) => Skp(x),
+            (0xe, x, 0xa, 0x1) => Sknp(x),
+            (0xf, 0x0, 0x0, 0x0) => LdILong,
+            (0xf, n, 0x0, 0x1) => Plane(n),
+            (0xf, 0x0, 0x0, 0x2) => Audio,
+            (0xf, x, 0x0, 0x7) => LdVxDt(x),
+            (0xf, x, 0x0, 0xa) => LdKey(x),
+            (0xf, x, 0x1, 0x1) => LdVxDtAlias(x),
+            (0xf, x, 0x1, 0x5) => LdDt(x),
+            (0xf, x, 0x1, 0x8) => LdSt(x),
+            (0xf, x, 0x1, 0xe) => AddI(x),
+            (0xf, x, 0x2, 0x9) => LdFont(x),
+            (0xf, x, 0x3, 0x0) => LdBigFont(x),
+            (0xf, x, 0x3, 0x3) => Bcd(x),
+            (0xf, x, 0x3, 0xa) => Pitch(x),
+            (0xf, x, 0x5, 0x5) => Store(x),
+            (0xf, x, 0x6, 0x5) => Load(x),
+            (0xf, x, 0x7, 0x5) => StoreFlags(x),
+            (0xf, x, 0x8, 0x5) => LoadFlags(x),
+            _ => return Err(DecodeError { opcode }),
+        })
+    }
+
+    /// Encode the instruction into its opcode
+    ///
+    /// Register numbers, nibbles and addresses are masked to their width.
+    pub fn encode(self) -> u16 {
+        use Instruction::*;
+
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16 & 0xf) << 8 | (y as u16 & 0xf) << 4;
+        let xnn = |base: u16, x: u8, nn: u8| base | (x as u16 & 0xf) << 8 | nn as u16;
+        let nnn = |base: u16, nnn: u16| base | nnn & 0xfff;
+
+        match self {
+            ScrollDown(n) => 0x00c0 | n as u16 & 0xf,
+            ScrollUp(n) => 0x00d0 | n as u16 & 0xf,
+            Cls => 0x00e0,
+            Ret => 0x00ee,
+            ScrollRight => 0x00fb,
+            ScrollLeft => 0x00fc,
+            Exit => 0x00fd,
+            Low => 0x00fe,
+            High => 0x00ff,
+            Jp(addr) => nnn(0x1000, addr),
+            Call(addr) => nnn(0x2000, addr),
+            Se(x, nn) => xnn(0x3000, x, nn),
+            Sne(x, nn) => xnn(0x4000, x, nn),
+            SeReg(x, y) => xy(0x5000, x, y),
+            SaveRange(x, y) => xy(0x5002, x, y),
+            LoadRange(x, y) => xy(0x5003, x, y),
+            Ld(x, nn) => xnn(0x6000, x, nn),
+            Add(x, nn) => xnn(0x7000, x, nn),
+            LdReg(x, y) => xy(0x8000, x, y),
+            Or(x, y) => xy(0x8001, x, y),
+            And(x, y) => xy(0x8002, x, y),
+            Xor(x, y) => xy(0x8003, x, y),
+            AddReg(x, y) => xy(0x8004, x, y),
+            Sub(x, y) => xy(0x8005, x, y),
+            Shr(x, y) => xy(0x8006, x, y),
+            Subn(x, y) => xy(0x8007, x, y),
+            Shl(x, y) => xy(0x800e, x, y),
+            SneReg(x, y) => xy(0x9000, x, y),
+            LdI(addr) => nnn(0xa000, addr),
+            JpV0(addr) => nnn(0xb000, addr),
+            Rnd(x, nn) => xnn(0xc000, x, nn),
+            Drw { x, y, n } => xy(0xd000, x, y) | n as u16 & 0xf,
+            Skp(x) => xnn(0xe09e, x, 0),
+            Sknp(x) => xnn(0xe0a1, x, 0),
+            LdILong => 0xf000,
+            Plane(n) => xnn(0xf001, n, 0),
+            Audio => 0xf002,
+            LdVxDt(x) => xnn(0xf007, x, 0),
+            LdKey(x) => xnn(0xf00a, x, 0),
+            LdVxDtAlias(x) => xnn(0xf011, x, 0),
+            LdDt(x) => xnn(0xf015, x, 0),
+            LdSt(x) => xnn(0xf018, x, 0),
+            AddI(x) => xnn(0xf01e, x, 0),
+            LdFont(x) => xnn(0xf029, x, 0),
+            LdBigFont(x) => xnn(0xf030, x, 0),
+            Bcd(x) => xnn(0xf033, x, 0),
+            Pitch(x) => xnn(0xf03a, x, 0),
+            Store(x) => xnn(0xf055, x, 0),
+            Load(x) => xnn(0xf065, x, 0),
+            StoreFlags(x) => xnn(0xf075, x, 0),
+            LoadFlags(x) => xnn(0xf085, x, 0),
+        }
+    }
+
+    /// The first variant that supports the instruction
+    pub fn variant(self) -> Variant {
+        use Instruction::*;
+
+        match self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Low | High | LdBigFont(_)
+            | StoreFlags(_) | LoadFlags(_) => Variant::SuperChip,
+            ScrollUp(_)
+            | SaveRange(_, _)
+            | LoadRange(_, _)
+            | LdILong
+            | Plane(_)
+            | Audio
+            | Pitch(_) => Variant::XoChip,
+            _ => Variant::Chip8,
+        }
+    }
+
+    /// The size of the instruction in bytes, 4 for `F000 nnnn` and 2 otherwise
+    pub fn size(self) -> u16 {
+        match self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+}
+
+/// Formats the instruction as an assembly mnemonic, like `LD V1, 0x2a` or `DRW V0, V1, 5`
+///
+/// The address of [`Instruction::LdILong`] is not part of the instruction, so it is formatted as
+/// `LD I, LONG` without it.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            Jp(addr) => write!(f, "JP {:#05x}", addr),
+            Call(addr) => write!(f, "CALL {:#05x}", addr),
+            Se(x, nn) => write!(f, "SE V{:X}, {:#04x}", x, nn),
+            Sne(x, nn) => write!(f, "SNE V{:X}, {:#04x}", x, nn),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            Ld(x, nn) => write!(f, "LD V{:X}, {:#04x}", x, nn),
+            Add(x, nn) => write!(f, "ADD V{:X}, {:#04x}", x, nn),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(addr) => write!(f, "LD I, {:#05x}", addr),
+            JpV0(addr) => write!(f, "JP V0, {:#05x}", addr),
+            Rnd(x, nn) => write!(f, "RND V{:X}, {:#04x}", x, nn),
+            Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdILong => write!(f, "LD I, LONG"),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdKey(x) => write!(f, "LD V{:X}, K", x),
+            // There is no mnemonic for the duplicate, so it is written as data that assembles to
+            // the same opcode
+            LdVxDtAlias(x) => write!(f, "DW {:#06x}", 0xf011 | (x as u16) << 8),
+            LdDt(x) => write!(f, "LD DT, V{:X}", x),
+            LdSt(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            LdFont(x) => write!(f, "LD F, V{:X}", x),
+            LdBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Store(x) => write!(f, "LD [I], V{:X}", x),
+            Load(x) => write!(f, "LD V{:X}, [I]", x),
+            StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            LoadFlags(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
@@ -1,23 +1,60 @@
 #![allow(dead_code, clippy::identity_op)]
 
+mod audio;
+mod frame;
+mod instruction;
+mod quirks;
+mod rng;
+mod snapshot;
+mod timing;
+mod trace;
+mod watchpoint;
+
 use std::fmt;
 
+pub use audio::Buzzer;
+pub use frame::{FrameEnd, FrameResult, DEFAULT_IPF};
+pub use instruction::{DecodeError, Instruction};
+pub use quirks::{MemoryIncrement, Quirks};
+pub use rng::Rng;
+pub use snapshot::StateError;
+pub use timing::{Timing, VIP_FRAME_CYCLES, VIP_INTERPRETER_CYCLES, VIP_INTERRUPT_CYCLES};
+pub use trace::{TraceFormat, Tracer, BINARY_RECORD_SIZE};
+pub use watchpoint::Access;
+
 /// The Chip8 emulator
 ///
-/// * `mem`: 4KB memory
-/// * `display`: 64x32 binary display
+/// * `mem`: 4KB memory, 64KB for XO-CHIP
+/// * `display`: 64x32 display, 128x64 in high resolution mode, each pixel is a bitmask of the
+///   bit planes that are set
+/// * `hires`: If the display is in high resolution mode
+/// * `planes`: The bit planes selected for drawing with `Fn01`, only plane 1 before XO-CHIP
 /// * `pc`: 16 bit program counter
 /// * `i`: The memory index used for sprites
 /// * `stack`: The stack for subroutines
 /// * `delay_timer`: The delay timer, decreased at 60Hz
-/// * `sound_timer`: The sound timer, like delay timer decreased at 60Hz, *should* cause a sound
-///    but I have not implemented that *yet*
+/// * `sound_timer`: The sound timer, like delay timer decreased at 60Hz, plays a sound while
+///   active
 /// * `vs`: The registers v0-vF
 /// * `key_pressed`: An array of currently pressed keys
 /// * `state`: The state of the emulator, used for blocking key grabs
+/// * `rpl`: The SUPER-CHIP RPL user flags, saved and loaded with `Fx75` and `Fx85`
+/// * `audio_pattern`: The 1-bit audio pattern, a square wave unless loaded with XO-CHIP `F002`
+/// * `pitch`: The XO-CHIP playback pitch of the audio pattern, set with `Fx3A`
+/// * `rng`: The random number generator for `Cxnn`
+/// * `variant`: The instruction set to emulate
+/// * `quirks`: The interpreter behaviour to emulate
+/// * `watchpoints`: The watched memory ranges, not part of save states
+/// * `watch_hit`: The first watchpoint hit by the current instruction
+/// * `timing`: How long instructions take when running frames, not part of save states
+/// * `frame_cycles`: The instructions, or VIP machine cycles, run in the current frame, not part of
+///   save states
+/// * `display_changed`: If the display changed since the last frame
 pub struct Chip8 {
-    mem: [u8; 4096],
-    display: [bool; 64 * 32],
+    mem: Vec<u8>,
+    display: Vec<u8>,
+    hires: bool,
+    planes: u8,
     pc: u16,
     i: u16,
     stack: Vec<u16>,
@@ -26,6 +63,17 @@ pub struct Chip8 {
     vs: [u8; 16],
     key_pressed: [bool; 16],
     state: State,
+    rpl: [u8; 16],
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    rng: Rng,
+    variant: Variant,
+    quirks: Quirks,
+    watchpoints: Vec<watchpoint::Watchpoint>,
+    watch_hit: Option<watchpoint::WatchHit>,
+    timing: Timing,
+    frame_cycles: u32,
+    display_changed: bool,
 }
 
 // Custom debug print for Chip8 because printing the whole memory is not reasonable
@@ -39,6 +87,12 @@ impl fmt::Debug for Chip8 {
             .field("registers", &self.vs)
             .field("pressed", &self.key_pressed)
             .field("state", &self.state)
+            .field("hires", &self.hires)
+            .field("planes", &self.planes)
+            .field("rpl", &self.rpl)
+            .field("pitch", &self.pitch)
+            .field("variant", &self.variant)
+            .field("quirks", &self.quirks)
             .finish()
     }
 }
@@ -47,8 +101,140 @@ impl fmt::Debug for Chip8 {
 pub enum State {
     Default,
     GetKey(u8),
+    /// Waiting for the next timer tick after a draw, with the display wait quirk
+    DisplayWait,
+    /// Exited with the SUPER-CHIP `00FD` instruction
+    Exited,
+}
+
+/// The instruction set to emulate, later variants extend the earlier ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Variant {
+    /// The original CHIP-8 instruction set
+    Chip8,
+    /// SUPER-CHIP 1.1, adds high resolution mode, scrolling and large sprites
+    SuperChip,
+    /// XO-CHIP, adds 64KB memory, a second bit plane and audio patterns
+    XoChip,
+}
+
+impl Variant {
+    /// Look up a variant by name, one of `chip8`, `schip` or `xochip`
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "chip8" => Some(Variant::Chip8),
+            "schip" => Some(Variant::SuperChip),
+            "xochip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The size of the addressable memory
+    pub fn memory_size(self) -> usize {
+        match self {
+            Variant::Chip8 | Variant::SuperChip => 0x1000,
+            Variant::XoChip => 0x10000,
+        }
+    }
+
+    /// The size of the largest rom that fits in memory, roms are loaded at 0x200
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - 0x200
+    }
+
+    /// The quirks used by the variant unless overridden
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::COSMAC_VIP,
+            Variant::SuperChip => Quirks::SCHIP_1_1,
+            Variant::XoChip => Quirks::XO_CHIP,
+        }
+    }
 }
 
+/// The width of the display in low resolution mode
+pub const LORES_WIDTH: usize = 64;
+/// The height of the display in low resolution mode
+pub const LORES_HEIGHT: usize = 32;
+/// The width of the display in SUPER-CHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+/// The height of the display in SUPER-CHIP high resolution mode
+pub const HIRES_HEIGHT: usize = 64;
+
+/// The number of XO-CHIP bit planes
+pub const PLANES: usize = 2;
+
+/// The pitch of the XO-CHIP audio pattern that plays back at 4000Hz
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// The audio pattern played until an XO-CHIP program loads its own, a 500Hz square wave at the
+/// default pitch
+pub const SQUARE_WAVE: [u8; 16] = [0xF0; 16];
+
+/// The maximum depth of the subroutine stack
+pub const STACK_SIZE: usize = 16;
+
+/// The outcome of a successful clock cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed
+    Executed,
+    /// No instruction was executed since the emulator is blocking on a key press or the display
+    Blocked,
+    /// The program has exited with `00FD`
+    Exited,
+    /// The instruction at `pc` was executed and accessed the watched address `addr`, changing it
+    /// from `old` to `new`, which are the same for reads
+    Watchpoint {
+        addr: u16,
+        pc: u16,
+        old: u8,
+        new: u8,
+    },
+}
+
+/// An error raised while loading a rom or executing an instruction
+///
+/// When an instruction fails the program counter is left pointing at it and nothing else is
+/// changed, so executing it again fails the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The rom of `size` bytes does not fit in memory, which has room for `max` bytes
+    RomTooLarge { size: usize, max: usize },
+    /// The opcode at `pc` is not a known instruction
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// Returned from a subroutine with an empty stack
+    StackUnderflow,
+    /// Called a subroutine with a full stack
+    StackOverflow,
+    /// Accessed memory outside of the address space
+    MemoryOutOfBounds { addr: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "the rom is {} bytes but at most {} bytes fit", size, max)
+            }
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:#06x} at {:#06x}", opcode, pc)
+            }
+            Chip8Error::StackUnderflow => {
+                write!(f, "returned from a subroutine with an empty stack")
+            }
+            Chip8Error::StackOverflow => {
+                write!(f, "exceeded the maximum stack depth of {}", STACK_SIZE)
+            }
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:#06x}", addr)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
 /// A commonly used font for CHIP-8
 static FONT: [u8; 5 * 16] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -69,125 +255,267 @@ static FONT: [u8; 5 * 16] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// The large SUPER-CHIP font, stored after [`FONT`]
+static BIG_FONT: [u8; 10 * 16] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 impl Chip8 {
     /// Read a byte
-    fn read8(&self, addr: u16) -> u8 {
-        self.mem[addr as usize]
+    fn read8(&self, addr: usize) -> Result<u8, Chip8Error> {
+        self.mem
+            .get(addr)
+            .copied()
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr })
     }
 
     /// Read a word
-    fn read16(&self, addr: u16) -> u16 {
-        u16::from_be_bytes([self.read8(addr), self.read8(addr + 1)])
+    fn read16(&self, addr: usize) -> Result<u16, Chip8Error> {
+        Ok(u16::from_be_bytes([
+            self.read8(addr)?,
+            self.read8(addr + 1)?,
+        ]))
+    }
+
+    /// Read a byte of data, unlike instruction fetches these trigger read watchpoints
+    fn load8(&mut self, addr: usize) -> Result<u8, Chip8Error> {
+        let v = self.read8(addr)?;
+        self.watch(addr, v, v, false);
+        Ok(v)
+    }
+
+    /// Read a word of data
+    fn load16(&mut self, addr: usize) -> Result<u16, Chip8Error> {
+        Ok(u16::from_be_bytes([
+            self.load8(addr)?,
+            self.load8(addr + 1)?,
+        ]))
+    }
+
+    /// Write a byte, triggering write watchpoints
+    fn write8(&mut self, addr: usize, v: u8) -> Result<(), Chip8Error> {
+        let cell = self
+            .mem
+            .get_mut(addr)
+            .ok_or(Chip8Error::MemoryOutOfBounds { addr })?;
+        let old = std::mem::replace(cell, v);
+        self.watch(addr, old, v, true);
+        Ok(())
+    }
+
+    /// Check that the memory range `addr..addr + len` is addressable, an empty range always is
+    fn check_range(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        if len > 0 && addr + len > self.mem.len() {
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: addr.max(self.mem.len()),
+            })
+        } else {
+            Ok(())
+        }
     }
 
     /// Fetch a byte and increment the program counter
-    fn fetch8(&mut self) -> u8 {
-        let r = self.read8(self.pc);
-        self.pc += 1;
-        r
+    fn fetch8(&mut self) -> Result<u8, Chip8Error> {
+        let r = self.read8(self.pc as usize)?;
+        self.pc = self.pc.wrapping_add(1);
+        Ok(r)
     }
 
     /// Fetch a word and increment the program counter
-    fn fetch16(&mut self) -> u16 {
-        let r = self.read16(self.pc);
-        self.pc += 2;
-        r
+    fn fetch16(&mut self) -> Result<u16, Chip8Error> {
+        let r = self.read16(self.pc as usize)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(r)
+    }
+
+    /// Skip the next instruction, XO-CHIP skips both words of `F000 NNNN`
+    fn skip(&mut self) {
+        let size = match self.read16(self.pc as usize).map(Instruction::decode) {
+            Ok(Ok(instruction)) if instruction.variant() <= self.variant => instruction.size(),
+            _ => 2,
+        };
+        self.pc = self.pc.wrapping_add(size);
     }
 
-    /// Perform a clock cycle, unless blocking GetKey
-    pub fn cycle(&mut self) {
-        // Get key is blocking
-        if let State::GetKey(_) = self.state {
-            return;
+    /// Perform a clock cycle, unless blocking on GetKey or the display wait
+    ///
+    /// If the instruction fails the program counter is reset to the faulting instruction.
+    pub fn cycle(&mut self) -> Result<StepOutcome, Chip8Error> {
+        match self.state {
+            // Get key and the display wait are blocking
+            State::GetKey(_) | State::DisplayWait => return Ok(StepOutcome::Blocked),
+            State::Exited => return Ok(StepOutcome::Exited),
+            State::Default => (),
         };
 
-        // Fetch the opcode, split into different parts for ease of use
-        let abcd = self.fetch16();
-        let a = ((abcd >> 12) & 0x0f) as u8;
-        let b = ((abcd >> 8) & 0x0f) as u8;
-        let c = ((abcd >> 4) & 0x0f) as u8;
-        let d = ((abcd >> 0) & 0x0f) as u8;
-        let cd = ((abcd >> 0) & 0xff) as u8;
-        let bcd = (abcd >> 0) & 0xfff;
-
-        match (a, b, c, d) {
-            // Clear the display
-            (0x0, 0x0, 0xe, 0x0) => {
+        let pc = self.pc;
+        if let Err(err) = self.execute() {
+            self.pc = pc;
+            self.watch_hit = None;
+            return Err(err);
+        }
+
+        if let Some(hit) = self.watch_hit.take() {
+            return Ok(StepOutcome::Watchpoint {
+                addr: hit.addr,
+                pc,
+                old: hit.old,
+                new: hit.new,
+            });
+        }
+
+        match self.state {
+            State::Exited => Ok(StepOutcome::Exited),
+            _ => Ok(StepOutcome::Executed),
+        }
+    }
+
+    /// Fetch, decode and execute a single instruction
+    fn execute(&mut self) -> Result<(), Chip8Error> {
+        let pc = self.pc;
+        let opcode = self.fetch16()?;
+        let instruction = Instruction::decode(opcode)
+            .ok()
+            .filter(|instruction| instruction.variant() <= self.variant)
+            .ok_or(Chip8Error::UnknownOpcode { pc, opcode })?;
+
+        match instruction {
+            Instruction::ScrollDown(n) => {
+                self.scroll(0, n as isize);
+            }
+
+            Instruction::ScrollUp(n) => {
+                self.scroll(0, -(n as isize));
+            }
+
+            Instruction::Cls => {
                 self.clear_display();
             }
 
-            // Return from a subroutine
-            (0x0, 0x0, 0xe, 0xe) => {
-                self.pc = self.stack.pop().unwrap();
+            Instruction::Ret => {
+                self.pc = self.stack.pop().ok_or(Chip8Error::StackUnderflow)?;
             }
 
-            // Jump
-            (0x1, _, _, _) => {
-                self.pc = bcd;
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
             }
 
-            // Call a subroutine, jumps and push to stack
-            (0x2, _, _, _) => {
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+            }
+
+            Instruction::Exit => {
+                self.state = State::Exited;
+            }
+
+            Instruction::Low => {
+                self.set_hires(false);
+            }
+
+            Instruction::High => {
+                self.set_hires(true);
+            }
+
+            Instruction::Jp(addr) => {
+                self.pc = addr;
+            }
+
+            // Push the return address to the stack and jump
+            Instruction::Call(addr) => {
+                if self.stack.len() >= STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow);
+                }
                 self.stack.push(self.pc);
-                self.pc = bcd;
+                self.pc = addr;
             }
 
-            // Skip next if vX == NN
-            (0x3, x, _, _) => {
-                if self.vr(x) == cd {
-                    self.pc += 2;
+            Instruction::Se(x, nn) => {
+                if self.vr(x) == nn {
+                    self.skip();
                 };
             }
 
-            // Skip next if vX != NN
-            (0x4, x, _, _) => {
-                if self.vr(x) != cd {
-                    self.pc += 2;
+            Instruction::Sne(x, nn) => {
+                if self.vr(x) != nn {
+                    self.skip();
                 }
             }
 
-            // Skip next if vX == vY
-            (0x5, x, y, 0) => {
+            Instruction::SeReg(x, y) => {
                 if self.vr(x) == self.vr(y) {
-                    self.pc += 2;
+                    self.skip();
+                }
+            }
+
+            // Stored in reverse order if X > Y
+            Instruction::SaveRange(x, y) => {
+                let registers = Self::register_range(x, y);
+                self.check_range(self.i as usize, registers.len())?;
+                for (offset, r) in registers.into_iter().enumerate() {
+                    self.write8(self.i as usize + offset, self.vr(r))?;
+                }
+            }
+
+            // Loaded in reverse order if X > Y
+            Instruction::LoadRange(x, y) => {
+                let registers = Self::register_range(x, y);
+                self.check_range(self.i as usize, registers.len())?;
+                for (offset, r) in registers.into_iter().enumerate() {
+                    let v = self.load8(self.i as usize + offset)?;
+                    self.vw(r, v);
                 }
             }
 
-            // Write NN to vX
-            (0x6, x, _, _) => {
-                self.vw(x, cd);
+            Instruction::Ld(x, nn) => {
+                self.vw(x, nn);
             }
 
-            // Increase vX by NN
-            (0x7, x, _, _) => {
-                self.vw(x, self.vr(x).wrapping_add(cd));
+            Instruction::Add(x, nn) => {
+                self.vw(x, self.vr(x).wrapping_add(nn));
             }
 
-            // Write vY to vX
-            (0x8, x, y, 0x0) => {
+            Instruction::LdReg(x, y) => {
                 self.vw(x, self.vr(y));
             }
 
-            // Or vX with vY
-            (0x8, x, y, 0x1) => {
+            Instruction::Or(x, y) => {
                 self.vw(x, self.vr(x) | self.vr(y));
-                self.vw(0xf, 0x0); // CHIP-8 quirk
+                if self.quirks.vf_reset {
+                    self.vw(0xf, 0x0);
+                }
             }
 
-            // And vX with vY
-            (0x8, x, y, 0x2) => {
+            Instruction::And(x, y) => {
                 self.vw(x, self.vr(x) & self.vr(y));
-                self.vw(0xf, 0x0); // CHIP-8 quirk
+                if self.quirks.vf_reset {
+                    self.vw(0xf, 0x0);
+                }
             }
 
-            // Xor vX with vY
-            (0x8, x, y, 0x3) => {
+            Instruction::Xor(x, y) => {
                 self.vw(x, self.vr(x) ^ self.vr(y));
-                self.vw(0xf, 0x0); // CHIP-8 quirk
+                if self.quirks.vf_reset {
+                    self.vw(0xf, 0x0);
+                }
             }
 
             // Add vY to vX, set overflow in flag register
-            (0x8, x, y, 0x4) => {
+            Instruction::AddReg(x, y) => {
                 let (res, of) = self.vr(x).overflowing_add(self.vr(y));
                 self.vw(x, res);
                 self.vw(0xf, if of { 1 } else { 0 });
@@ -195,7 +523,7 @@ impl Chip8 {
 
             // Subtract vX by vY, set flag to the borrow bit, set if subtraction did not require a
             // borrow
-            (0x8, x, y, 0x5) => {
+            Instruction::Sub(x, y) => {
                 let unborrowed = self.vr(x) >= self.vr(y);
                 let res = self.vr(x).wrapping_sub(self.vr(y));
                 self.vw(x, res);
@@ -203,172 +531,291 @@ impl Chip8 {
             }
 
             // Shift vY to the right and store in vX, save shifted bit in flag
-            (0x8, x, y, 0x6) => {
-                self.vw(x, self.vr(y)); // CHIP-8 quirk
+            Instruction::Shr(x, y) => {
+                if self.quirks.shift_vy {
+                    self.vw(x, self.vr(y));
+                }
                 let vx = self.vr(x);
                 self.vw(x, vx >> 1);
                 self.vw(0xf, vx & 0b1);
             }
 
             // Subtract vY by vX and store in vX, set flag to borrow bit
-            (0x8, x, y, 0x7) => {
+            Instruction::Subn(x, y) => {
                 let unborrowed = self.vr(y) >= self.vr(x);
                 let (res, _) = self.vr(y).overflowing_sub(self.vr(x));
                 self.vw(x, res);
                 self.vw(0xf, if unborrowed { 1 } else { 0 });
             }
 
-            // Shift vY to the left and store in vY, save shifted bit in flag
-            (0x8, x, y, 0xe) => {
-                self.vw(x, self.vr(y)); // CHIP-8 quirk
+            // Shift vY to the left and store in vX, save shifted bit in flag
+            Instruction::Shl(x, y) => {
+                if self.quirks.shift_vy {
+                    self.vw(x, self.vr(y));
+                }
                 let vx = self.vr(x);
                 self.vw(x, vx << 1);
                 self.vw(0xf, if vx & 0x80 != 0 { 1 } else { 0 });
             }
 
-            // Skip if vX !- vY
-            (0x9, x, y, 0x0) => {
+            Instruction::SneReg(x, y) => {
                 if self.vr(x) != self.vr(y) {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
-            // Write NNN to I
-            (0xa, _, _, _) => {
-                self.i = bcd;
+            Instruction::LdI(addr) => {
+                self.i = addr;
             }
 
-            // Jump to v0 + NNN
-            (0xb, _, _, _) => {
-                self.pc = self.vr(0x0) as u16 + bcd;
+            // Jump to v0 + NNN, or vX + XNN with the jump quirk
+            Instruction::JpV0(addr) => {
+                let offset = if self.quirks.jump_vx {
+                    self.vr((addr >> 8) as u8)
+                } else {
+                    self.vr(0x0)
+                };
+                self.pc = offset as u16 + addr;
             }
 
-            // Get a random number anded with NN
-            (0xc, x, _, _) => {
-                let random = rand::random::<u8>();
-                self.vw(x, random & cd);
+            Instruction::Rnd(x, nn) => {
+                let random = self.rng.next_u8();
+                self.vw(x, random & nn);
             }
 
-            // Draw sprite in memory at position I offset by vX and vY, N rows long
-            (0xd, x, y, n) => {
-                self.draw(self.vr(x), self.vr(y), n);
+            Instruction::Drw { x, y, n } => {
+                self.draw(self.vr(x), self.vr(y), n)?;
+                if self.quirks.display_wait {
+                    self.state = State::DisplayWait;
+                }
             }
 
-            // Skip if key vX is pressed
-            (0xe, x, 0x9, 0xe) => {
-                if self.key_pressed[self.vr(x) as usize] {
-                    self.pc += 2;
+            Instruction::Skp(x) => {
+                if self.key_pressed[(self.vr(x) & 0xf) as usize] {
+                    self.skip();
                 }
             }
 
-            // Skip if key vX is not pressed
-            (0xe, x, 0xa, 0x1) => {
-                if !self.key_pressed[self.vr(x) as usize] {
-                    self.pc += 2;
+            Instruction::Sknp(x) => {
+                if !self.key_pressed[(self.vr(x) & 0xf) as usize] {
+                    self.skip();
                 }
             }
 
-            // Write delay timer to vX
-            (0xf, x, 0x0, 0x7) => {
-                self.vw(x, self.delay_timer);
+            // The address is stored in the word after the instruction
+            Instruction::LdILong => {
+                self.i = self.fetch16()?;
             }
 
-            // Block get key to vX
-            (0xf, x, 0x0, 0xa) => {
-                self.state = State::GetKey(x);
+            Instruction::Plane(n) => {
+                self.planes = n & 0b11;
             }
 
-            // Write delay timer to vX
-            (0xf, x, 0x1, 0x1) => {
+            Instruction::Audio => {
+                self.check_range(self.i as usize, self.audio_pattern.len())?;
+                for offset in 0..self.audio_pattern.len() {
+                    self.audio_pattern[offset] = self.load8(self.i as usize + offset)?;
+                }
+            }
+
+            Instruction::LdVxDt(x) | Instruction::LdVxDtAlias(x) => {
                 self.vw(x, self.delay_timer);
             }
 
-            // Write vX to delay timer
-            (0xf, x, 0x1, 0x5) => {
+            // Block until a key is pressed and released
+            Instruction::LdKey(x) => {
+                self.state = State::GetKey(x);
+            }
+
+            Instruction::LdDt(x) => {
                 self.delay_timer = self.vr(x);
             }
 
-            // Write vX to sound timer
-            (0xf, x, 0x1, 0x8) => {
+            Instruction::LdSt(x) => {
                 self.sound_timer = self.vr(x);
             }
 
-            // Increment I by vX
-            (0xf, x, 0x1, 0xe) => {
+            Instruction::AddI(x) => {
                 self.i = self.i.wrapping_add(self.vr(x) as u16);
             }
 
-            // Get sprite for character X, stored in the beginning of memory
-            (0xf, x, 0x2, 0x9) => {
+            // The font is stored in the beginning of memory
+            Instruction::LdFont(x) => {
                 self.i = 5 * self.vr(x) as u16;
             }
 
+            // The large font is stored after the small font
+            Instruction::LdBigFont(x) => {
+                self.i = FONT.len() as u16 + 10 * (self.vr(x) & 0xf) as u16;
+            }
+
             // Write decimal digits to memory at I, memory[I] = 100's digit, memory[I+1] = 10's
             // digit, memory[I+2] = 1's digit
-            (0xf, x, 0x3, 0x3) => {
+            Instruction::Bcd(x) => {
                 let mut curr = self.vr(x);
+                self.check_range(self.i as usize, 3)?;
 
                 for i in 0..3 {
-                    self.mem[self.i as usize + 2 - i] = curr % 10;
+                    self.write8(self.i as usize + 2 - i, curr % 10)?;
                     curr /= 10;
                 }
             }
 
-            // Store registers v0..=vX to memory[I..]
-            (0xf, x, 0x5, 0x5) => {
+            Instruction::Pitch(x) => {
+                self.pitch = self.vr(x);
+            }
+
+            Instruction::Store(x) => {
+                self.check_range(self.i as usize, x as usize + 1)?;
                 for i in 0x0..=x {
-                    self.mem[self.i as usize + i as usize] = self.vs[i as usize];
+                    self.write8(self.i as usize + i as usize, self.vs[i as usize])?;
                 }
-                self.i += 1; // CHIP-8 quirk
+                self.increment_i_after_memory(x);
             }
 
-            // Load registers v0..=vX from memory[I..]
-            (0xf, x, 0x6, 0x5) => {
+            Instruction::Load(x) => {
+                self.check_range(self.i as usize, x as usize + 1)?;
                 for i in 0x0..=x {
-                    self.vs[i as usize] = self.mem[self.i as usize + i as usize];
+                    self.vs[i as usize] = self.load8(self.i as usize + i as usize)?;
                 }
-                self.i += 1; // CHIP-8 quirk
+                self.increment_i_after_memory(x);
+            }
+
+            Instruction::StoreFlags(x) => {
+                self.rpl[..=x as usize].copy_from_slice(&self.vs[..=x as usize]);
             }
-            _ => panic!("Unimplemented instruction {:#06x}", abcd),
+
+            Instruction::LoadFlags(x) => {
+                self.vs[..=x as usize].copy_from_slice(&self.rpl[..=x as usize]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Change I after storing or loading registers v0..=vX, depending on the memory quirk
+    fn increment_i_after_memory(&mut self, x: u8) {
+        self.i = match self.quirks.memory {
+            MemoryIncrement::Unchanged => self.i,
+            MemoryIncrement::X => self.i.wrapping_add(x as u16),
+            MemoryIncrement::XPlusOne => self.i.wrapping_add(x as u16 + 1),
+        };
+    }
+
+    /// The registers vX..=vY, in reverse order if X > Y
+    fn register_range(x: u8, y: u8) -> Vec<u8> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
         }
     }
 
-    /// Clear the display
+    /// Clear the selected bit planes of the display
     fn clear_display(&mut self) {
-        self.display.fill(false);
+        let planes = self.planes;
+        self.display.iter_mut().for_each(|p| *p &= !planes);
+        self.display_changed = true;
     }
 
-    /// Draw sprite instruction
-    fn draw(&mut self, mut x_offset: u8, mut y_offset: u8, height: u8) {
-        self.vw(0xf, 0);
+    /// Switch between low and high resolution, clears the display
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.display = vec![0; self.width() * self.height()];
+        self.display_changed = true;
+    }
 
-        x_offset %= 64;
-        y_offset %= 32;
+    /// Scroll the selected bit planes of the display by dx pixels right and dy pixels down
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let planes = self.planes;
+        let old = self.display.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let src = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    old[(src_y * width + src_x) as usize]
+                } else {
+                    0
+                };
 
-        for dy in 0..height {
-            let y = y_offset as usize + dy as usize;
+                let disp_i = (y * width + x) as usize;
+                self.display[disp_i] = (old[disp_i] & !planes) | (src & planes);
+            }
+        }
+        self.display_changed = true;
+    }
+
+    /// Draw sprite instruction, SUPER-CHIP draws a 16x16 sprite if the height is 0
+    ///
+    /// With several bit planes selected the sprite data for each plane follows the previous one.
+    fn draw(&mut self, x_offset: u8, y_offset: u8, height: u8) -> Result<(), Chip8Error> {
+        let (sprite_width, sprite_height) = if height == 0 && self.variant >= Variant::SuperChip {
+            (16, 16)
+        } else {
+            (8, height as usize)
+        };
+        let row_bytes = sprite_width / 8;
+        let sprite_bytes = sprite_height * row_bytes;
 
-            for dx in 0..8 {
-                let x = x_offset as usize + dx as usize;
+        self.check_range(
+            self.i as usize,
+            sprite_bytes * self.planes.count_ones() as usize,
+        )?;
+        self.vw(0xf, 0);
 
-                if 64 <= x || 32 <= y {
-                    continue;
-                };
+        let (width, height) = (self.width(), self.height());
+        let x_offset = x_offset as usize % width;
+        let y_offset = y_offset as usize % height;
+        let mut sprite_addr = self.i as usize;
 
-                let tile = self.mem[self.i as usize + dy as usize];
-                let pixel = (tile << dx) & 0b10000000 != 0;
+        for plane in 0..PLANES {
+            let plane_bit = 1 << plane;
+            if self.planes & plane_bit == 0 {
+                continue;
+            }
 
-                if pixel {
-                    let disp_i = y * 64 + x;
+            for dy in 0..sprite_height {
+                let row_addr = sprite_addr + dy * row_bytes;
+                let row = if row_bytes == 2 {
+                    self.load16(row_addr)?
+                } else {
+                    (self.load8(row_addr)? as u16) << 8
+                };
 
-                    if self.display[disp_i] {
-                        self.vw(0xf, 1)
+                for dx in 0..sprite_width {
+                    let mut x = x_offset + dx;
+                    let mut y = y_offset + dy;
+
+                    if self.quirks.clipping {
+                        if width <= x || height <= y {
+                            continue;
+                        };
+                    } else {
+                        x %= width;
+                        y %= height;
                     }
 
-                    self.display[disp_i] = !self.display[disp_i];
+                    let pixel = (row << dx) & 0x8000 != 0;
+
+                    if pixel {
+                        let disp_i = y * width + x;
+
+                        if self.display[disp_i] & plane_bit != 0 {
+                            self.vw(0xf, 1)
+                        }
+
+                        self.display[disp_i] ^= plane_bit;
+                        self.display_changed = true;
+                    }
                 }
             }
+
+            sprite_addr += sprite_bytes;
         }
+
+        Ok(())
     }
 
     /// Read register vX
@@ -381,16 +828,53 @@ impl Chip8 {
         self.vs[x as usize] = v;
     }
 
-    /// Create a new emulator, initialise with ROM (which is not really a ROM)
-    pub fn new(rom: Vec<u8>) -> Self {
-        let mut mem = [0; 4096];
+    /// Create a new emulator with the default quirks, initialise with ROM (which is not really a
+    /// ROM)
+    ///
+    /// Fails if the rom does not fit in memory.
+    pub fn new(rom: Vec<u8>) -> Result<Self, Chip8Error> {
+        Self::builder(rom).build()
+    }
+
+    /// Create a builder for an emulator with non-default options
+    pub fn builder(rom: Vec<u8>) -> Chip8Builder {
+        Chip8Builder {
+            rom,
+            variant: Variant::Chip8,
+            quirks: None,
+            seed: None,
+            timing: Timing::Fixed,
+        }
+    }
+
+    /// Create a new emulator from the options in a builder
+    fn from_builder(builder: Chip8Builder) -> Result<Self, Chip8Error> {
+        let Chip8Builder {
+            rom,
+            variant,
+            quirks,
+            seed,
+            timing,
+        } = builder;
+
+        if rom.len() > variant.max_rom_size() {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max: variant.max_rom_size(),
+            });
+        }
+
+        let mut mem = vec![0; variant.memory_size()];
 
         mem[..FONT.len()].copy_from_slice(&FONT);
+        mem[FONT.len()..FONT.len() + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         mem[0x200..0x200 + rom.len()].copy_from_slice(&rom);
 
-        Self {
+        Ok(Self {
             mem,
-            display: [false; 64 * 32],
+            display: vec![0; LORES_WIDTH * LORES_HEIGHT],
+            hires: false,
+            planes: 0b01,
             pc: 0x200,
             i: 0,
             stack: Vec::new(),
@@ -399,28 +883,46 @@ impl Chip8 {
             vs: [0; 16],
             key_pressed: [false; 16],
             state: State::Default,
-        }
+            rpl: [0; 16],
+            audio_pattern: SQUARE_WAVE,
+            pitch: DEFAULT_PITCH,
+            rng: Rng::new(seed.unwrap_or_else(rand::random)),
+            variant,
+            quirks: quirks.unwrap_or(variant.quirks()),
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            timing,
+            frame_cycles: 0,
+            display_changed: false,
+        })
     }
 
-    /// Send a key down event
+    /// Send a key down event, keys above 0xF are ignored
     pub fn down(&mut self, v: u8) {
-        if !self.key_pressed[v as usize] {
+        if let Some(pressed) = self.key_pressed.get_mut(v as usize) {
+            *pressed = true;
+        }
+    }
+
+    /// Send a key up event, releasing a pressed key finishes a blocking key grab like on the
+    /// COSMAC VIP
+    pub fn up(&mut self, v: u8) {
+        if self.key_pressed.get(v as usize) == Some(&true) {
             if let State::GetKey(x) = self.state {
                 self.vw(x, v);
                 self.state = State::Default;
             };
 
-            self.key_pressed[v as usize] = true;
+            self.key_pressed[v as usize] = false;
         }
     }
 
-    /// Send a key up event
-    pub fn up(&mut self, v: u8) {
-        self.key_pressed[v as usize] = false;
-    }
-
-    /// Tick the delay and sound timer, call 60 times a second
+    /// Tick the delay and sound timer and end a display wait, call 60 times a second
     pub fn decrease_timers(&mut self) {
+        if let State::DisplayWait = self.state {
+            self.state = State::Default;
+        }
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -430,8 +932,178 @@ impl Chip8 {
         }
     }
 
-    /// Get the display, indexed [y * 64 + 32]
-    pub fn display(&self) -> &[bool; 64 * 32] {
+    /// Get the display, indexed [y * width + x]
+    ///
+    /// Each pixel is a bitmask of the bit planes that are set, so a value between 0 and 3 that can
+    /// be used as an index into a 4 color palette. Pixels are only ever 0 or 1 before XO-CHIP.
+    pub fn display(&self) -> &[u8] {
         &self.display
     }
+
+    /// Get the width and height of the display in high or low resolution
+    fn resolution(hires: bool) -> (usize, usize) {
+        if hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (LORES_WIDTH, LORES_HEIGHT)
+        }
+    }
+
+    /// Get the width of the display in the current resolution
+    pub fn width(&self) -> usize {
+        Self::resolution(self.hires).0
+    }
+
+    /// Get the height of the display in the current resolution
+    pub fn height(&self) -> usize {
+        Self::resolution(self.hires).1
+    }
+
+    /// Check if the sound timer is active and a sound should be playing
+    pub fn is_sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Get the 1-bit audio pattern, played back most significant bit first
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// Get the XO-CHIP audio pattern pitch
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Get the rate in bits per second to play back the audio pattern at, given by the pitch
+    pub fn playback_rate(&self) -> f32 {
+        4000. * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.)
+    }
+
+    /// Get the instruction set being emulated
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// Get the quirks being emulated
+    pub fn quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    /// Get how long instructions take when running frames
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Get the program counter
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Get the memory index register I
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    /// Get the registers v0-vF
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.vs
+    }
+
+    /// Get the return addresses on the subroutine stack, the innermost last
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Get the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Get the sound timer
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Get the whole memory, including the fonts before the rom
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Get the state of the emulator
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Set the program counter
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Set the memory index register I
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+
+    /// Set a register vX, `x` is masked to 0-F
+    pub fn set_register(&mut self, x: u8, v: u8) {
+        self.vw(x & 0xF, v);
+    }
+
+    /// Set the delay timer
+    pub fn set_delay_timer(&mut self, v: u8) {
+        self.delay_timer = v;
+    }
+
+    /// Set the sound timer
+    pub fn set_sound_timer(&mut self, v: u8) {
+        self.sound_timer = v;
+    }
+
+    /// Get the whole memory mutably, writes do not trigger watchpoints
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+}
+
+/// Builder for a [`Chip8`] with non-default options
+#[derive(Debug)]
+pub struct Chip8Builder {
+    rom: Vec<u8>,
+    variant: Variant,
+    quirks: Option<Quirks>,
+    seed: Option<u64>,
+    timing: Timing,
+}
+
+impl Chip8Builder {
+    /// Set the instruction set to emulate, also selects the quirks of the variant unless quirks
+    /// are set explicitly
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    /// Set the quirks to emulate, see [`Quirks`] for the available presets
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Seed the random number generator to make `Cxnn` reproducible, a random seed is used
+    /// otherwise
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Set how long instructions take when running frames, see [`Timing`]
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Create the emulator, fails if the rom does not fit in memory
+    pub fn build(self) -> Result<Chip8, Chip8Error> {
+        Chip8::from_builder(self)
+    }
 }
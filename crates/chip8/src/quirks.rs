@@ -0,0 +1,95 @@
+/// How `Fx55` and `Fx65` change the I register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    /// I is left unchanged
+    Unchanged,
+    /// I is increased by X
+    X,
+    /// I is increased by X + 1, pointing past the last register
+    XPlusOne,
+}
+
+/// Behavioural differences between CHIP-8 interpreters
+///
+/// * `vf_reset`: `8xy1`, `8xy2` and `8xy3` reset vF to 0
+/// * `shift_vy`: `8xy6` and `8xyE` shift vY into vX instead of shifting vX in place
+/// * `memory`: How `Fx55` and `Fx65` change I
+/// * `jump_vx`: `Bxnn` jumps to vX + xnn instead of v0 + xnn
+/// * `clipping`: Sprites are clipped at the edges of the screen instead of wrapping around
+/// * `display_wait`: `Dxyn` blocks until the next timer tick after drawing, like the VIP waiting for
+///   the vertical blank, which limits games to a sprite per frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub vf_reset: bool,
+    pub shift_vy: bool,
+    pub memory: MemoryIncrement,
+    pub jump_vx: bool,
+    pub clipping: bool,
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter on the COSMAC VIP
+    pub const COSMAC_VIP: Quirks = Quirks {
+        vf_reset: true,
+        shift_vy: true,
+        memory: MemoryIncrement::XPlusOne,
+        jump_vx: false,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// The COSMAC VIP interpreter including its wait for the display after drawing
+    pub const COSMAC_VIP_DISPLAY_WAIT: Quirks = Quirks {
+        display_wait: true,
+        ..Quirks::COSMAC_VIP
+    };
+
+    /// CHIP-48 on the HP-48 calculators
+    pub const CHIP_48: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: false,
+        memory: MemoryIncrement::X,
+        jump_vx: true,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// SUPER-CHIP 1.1 on the HP-48 calculators
+    pub const SCHIP_1_1: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: false,
+        memory: MemoryIncrement::Unchanged,
+        jump_vx: true,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// XO-CHIP as implemented by Octo
+    pub const XO_CHIP: Quirks = Quirks {
+        vf_reset: false,
+        shift_vy: true,
+        memory: MemoryIncrement::XPlusOne,
+        jump_vx: false,
+        clipping: false,
+        display_wait: false,
+    };
+
+    /// Look up a preset by name, one of `vip`, `vip-wait`, `chip48`, `schip` or `xochip`
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::COSMAC_VIP),
+            "vip-wait" => Some(Quirks::COSMAC_VIP_DISPLAY_WAIT),
+            "chip48" => Some(Quirks::CHIP_48),
+            "schip" => Some(Quirks::SCHIP_1_1),
+            "xochip" => Some(Quirks::XO_CHIP),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::COSMAC_VIP
+    }
+}
@@ -0,0 +1,40 @@
+/// The seedable random number generator used by `Cxnn`
+///
+/// A xorshift64* generator, small enough to store its whole state in save states so replays are
+/// reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator from a seed, equal seeds give equal sequences
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed with splitmix64 so similar seeds give different sequences, xorshift
+        // also needs a non-zero state
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        Self { state: z.max(1) }
+    }
+
+    /// Restore a generator from its state, `None` if the state is invalid
+    pub fn from_state(state: u64) -> Option<Self> {
+        (state != 0).then_some(Self { state })
+    }
+
+    /// Get the internal state of the generator
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Get the next random byte
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
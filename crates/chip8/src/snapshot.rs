@@ -0,0 +1,277 @@
+use std::fmt;
+
+use crate::{Chip8, MemoryIncrement, Quirks, Rng, State, Variant, STACK_SIZE};
+
+/// The magic bytes every save state starts with
+const MAGIC: &[u8; 4] = b"C8SS";
+
+/// The current version of the save state format, version 2 added the random number generator and
+/// version 3 the display wait quirk
+const VERSION: u16 = 3;
+
+/// An error raised when loading a save state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic bytes
+    BadMagic,
+    /// The save state was written by an unsupported version of the format
+    UnsupportedVersion(u16),
+    /// The data ended before the save state was complete
+    Truncated,
+    /// A field of the save state holds an invalid value
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Reads the fields of a save state in order
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Read the next n bytes
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Read the next n bytes into an array
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self
+            .bytes(N)?
+            .try_into()
+            .expect("Read the wrong number of bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+}
+
+impl Chip8 {
+    /// Serialize the complete machine into a versioned binary save state
+    ///
+    /// The state starts with the magic bytes `C8SS` and a big endian version, followed by the
+    /// variant, quirks, memory, display, registers, timers, keys, state and random number generator
+    /// of the emulator.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.mem.len() + self.display.len() + 128);
+
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_be_bytes());
+
+        out.push(match self.variant {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2,
+        });
+        out.push(self.quirks.vf_reset as u8);
+        out.push(self.quirks.shift_vy as u8);
+        out.push(match self.quirks.memory {
+            MemoryIncrement::Unchanged => 0,
+            MemoryIncrement::X => 1,
+            MemoryIncrement::XPlusOne => 2,
+        });
+        out.push(self.quirks.jump_vx as u8);
+        out.push(self.quirks.clipping as u8);
+        out.push(self.quirks.display_wait as u8);
+
+        out.extend_from_slice(&(self.mem.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.mem);
+
+        out.push(self.hires as u8);
+        out.push(self.planes);
+        out.extend_from_slice(&(self.display.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.display);
+
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.extend_from_slice(&self.i.to_be_bytes());
+        out.push(self.stack.len() as u8);
+        for addr in &self.stack {
+            out.extend_from_slice(&addr.to_be_bytes());
+        }
+
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.extend_from_slice(&self.vs);
+        out.extend(self.key_pressed.iter().map(|pressed| *pressed as u8));
+
+        match self.state {
+            State::Default => out.extend_from_slice(&[0, 0]),
+            State::GetKey(x) => out.extend_from_slice(&[1, x]),
+            State::Exited => out.extend_from_slice(&[2, 0]),
+            State::DisplayWait => out.extend_from_slice(&[3, 0]),
+        }
+
+        out.extend_from_slice(&self.rpl);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(self.pitch);
+        out.extend_from_slice(&self.rng.state().to_be_bytes());
+
+        out
+    }
+
+    /// Restore the complete machine from a save state made by [`Chip8::save_state`]
+    ///
+    /// The emulator is left unchanged if the save state is invalid. Version 1 save states keep the
+    /// current random number generator and versions before 3 the current display wait quirk. A
+    /// loaded state starts a new frame.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader { data };
+
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let variant = match r.u8()? {
+            0 => Variant::Chip8,
+            1 => Variant::SuperChip,
+            2 => Variant::XoChip,
+            _ => return Err(StateError::Invalid("variant")),
+        };
+        let quirks = Quirks {
+            vf_reset: r.bool()?,
+            shift_vy: r.bool()?,
+            memory: match r.u8()? {
+                0 => MemoryIncrement::Unchanged,
+                1 => MemoryIncrement::X,
+                2 => MemoryIncrement::XPlusOne,
+                _ => return Err(StateError::Invalid("memory quirk")),
+            },
+            jump_vx: r.bool()?,
+            clipping: r.bool()?,
+            display_wait: if version >= 3 {
+                r.bool()?
+            } else {
+                self.quirks.display_wait
+            },
+        };
+
+        let mem_len = r.u32()? as usize;
+        if mem_len != variant.memory_size() {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let mem = r.bytes(mem_len)?.to_vec();
+
+        let hires = r.bool()?;
+        let planes = r.u8()?;
+        if planes > 0b11 {
+            return Err(StateError::Invalid("plane selection"));
+        }
+        let display_len = r.u32()? as usize;
+        let (width, height) = Self::resolution(hires);
+        if display_len != width * height {
+            return Err(StateError::Invalid("display size"));
+        }
+        let display = r.bytes(display_len)?.to_vec();
+        if display.iter().any(|p| *p > 0b11) {
+            return Err(StateError::Invalid("display"));
+        }
+
+        let pc = r.u16()?;
+        let i = r.u16()?;
+        let stack_len = r.u8()? as usize;
+        if stack_len > STACK_SIZE {
+            return Err(StateError::Invalid("stack depth"));
+        }
+        let stack = (0..stack_len)
+            .map(|_| r.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let vs = r.array()?;
+        let mut key_pressed = [false; 16];
+        for pressed in key_pressed.iter_mut() {
+            *pressed = r.bool()?;
+        }
+
+        let state = match (r.u8()?, r.u8()?) {
+            (0, _) => State::Default,
+            (1, x) if x < 16 => State::GetKey(x),
+            (2, _) => State::Exited,
+            (3, _) => State::DisplayWait,
+            _ => return Err(StateError::Invalid("state")),
+        };
+
+        let rpl = r.array()?;
+        let audio_pattern = r.array()?;
+        let pitch = r.u8()?;
+        let rng = if version >= 2 {
+            Some(Rng::from_state(r.u64()?).ok_or(StateError::Invalid("random number generator"))?)
+        } else {
+            None
+        };
+
+        if !r.data.is_empty() {
+            return Err(StateError::Invalid("length"));
+        }
+
+        self.mem = mem;
+        self.display = display;
+        self.hires = hires;
+        self.planes = planes;
+        self.pc = pc;
+        self.i = i;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.vs = vs;
+        self.key_pressed = key_pressed;
+        self.state = state;
+        self.rpl = rpl;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        if let Some(rng) = rng {
+            self.rng = rng;
+        }
+        self.variant = variant;
+        self.quirks = quirks;
+        self.frame_cycles = 0;
+        self.display_changed = true;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{Chip8, Instruction, LORES_HEIGHT, LORES_WIDTH};
+
+/// How long instructions take when running frames with [`Chip8::run_frame`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// Every instruction takes as long, frames run a fixed number of instructions
+    #[default]
+    Fixed,
+    /// Instructions take as many machine cycles as in the COSMAC VIP interpreter, frames end once
+    /// the cycles the VIP has between two display interrupts are spent
+    CosmacVip,
+}
+
+impl Timing {
+    /// Look up a timing by name, `fixed` or `vip`
+    pub fn from_name(name: &str) -> Option<Timing> {
+        match name {
+            "fixed" => Some(Timing::Fixed),
+            "vip" => Some(Timing::CosmacVip),
+            _ => None,
+        }
+    }
+}
+
+/// The CDP1802 machine cycles in a 60Hz frame of the VIP, 8 clock cycles each at 1.7609MHz
+pub const VIP_FRAME_CYCLES: u32 = 3668;
+
+/// The machine cycles of a frame spent in the display interrupt, which keeps the processor busy
+/// while the CDP1861 shows the 128 visible lines
+pub const VIP_INTERRUPT_CYCLES: u32 = 1832;
+
+/// The machine cycles left for the interpreter in a frame
+pub const VIP_INTERPRETER_CYCLES: u32 = VIP_FRAME_CYCLES - VIP_INTERRUPT_CYCLES;
+
+/// The machine cycles the interpreter spends fetching and decoding every instruction
+const FETCH_CYCLES: u32 = 68;
+
+/// The extra machine cycles of a skip that is taken
+const SKIP_CYCLES: u32 = 4;
+
+impl Chip8 {
+    /// The machine cycles the instruction at the program counter takes in the VIP interpreter
+    ///
+    /// Every instruction costs the 68 cycles of fetching and decoding it plus the cycles of its
+    /// routine in the interpreter as analysed by Laurence Scotford, listed in the match below and
+    /// checked by the `vip_instruction_costs` test. The costs depend on the machine state for
+    /// skips, `Bnnn` crossing a page, the digits of `Fx33` and the number of registers of `Fx55`
+    /// and `Fx65`.
+    ///
+    /// The known deviations from the VIP are:
+    ///
+    /// * `Dxyn` is modelled per row as 22 cycles, 4 per bit the row is shifted and 12 per byte
+    ///   written, plus 26 for the setup. The real routine also varies with collisions and the
+    ///   sprite bytes, so draws can be off by a few cycles per row.
+    /// * `Fx0A` costs 19 cycles when it starts waiting. While it blocks, [`Chip8::run_frame`]
+    ///   charges the rest of the frame for each blocked cycle instead of following the keypad scan
+    ///   and debounce loop of the VIP, so a waiting program spends whole frames in it.
+    /// * Instructions the VIP does not have cost as much as a jump, 12 cycles.
+    /// * The display interrupt always takes [`VIP_INTERRUPT_CYCLES`], so instructions are never
+    ///   delayed within a frame by the interrupt arriving mid routine.
+    pub fn vip_cycles(&self) -> u32 {
+        use Instruction::*;
+
+        let Ok(Ok(instruction)) = self.read16(self.pc as usize).map(Instruction::decode) else {
+            return FETCH_CYCLES;
+        };
+        let v = |x: u8| self.vs[x as usize];
+        let skip = |taken: bool| if taken { SKIP_CYCLES } else { 0 };
+        let pressed = |x: u8| self.key_pressed[(v(x) & 0xf) as usize];
+
+        let cycles = match instruction {
+            Cls => 3078,
+            Ret => 10,
+            Jp(_) => 12,
+            Call(_) => 26,
+            Se(x, nn) => 10 + skip(v(x) == nn),
+            Sne(x, nn) => 10 + skip(v(x) != nn),
+            SeReg(x, y) => 14 + skip(v(x) == v(y)),
+            SneReg(x, y) => 14 + skip(v(x) != v(y)),
+            Ld(..) => 6,
+            Add(..) => 10,
+            LdReg(..) => 12,
+            Or(..) | And(..) | Xor(..) | AddReg(..) | Sub(..) | Shr(..) | Subn(..) | Shl(..) => 44,
+            LdI(_) => 12,
+            JpV0(addr) => {
+                22 + if (addr & 0xff) + v(0) as u16 > 0xff {
+                    2
+                } else {
+                    0
+                }
+            }
+            Rnd(..) => 36,
+            Drw { x, y, n } => draw_cycles(v(x), v(y), n),
+            Skp(x) => 14 + skip(pressed(x)),
+            Sknp(x) => 14 + skip(!pressed(x)),
+            LdVxDt(_) => 10,
+            LdKey(_) => 19,
+            LdDt(_) | LdSt(_) => 10,
+            AddI(_) => 16,
+            LdFont(_) => 16,
+            Bcd(x) => {
+                let digits = v(x) / 100 + v(x) / 10 % 10 + v(x) % 10;
+                80 + 16 * digits as u32
+            }
+            Store(x) | Load(x) => 14 + 14 * (x as u32 + 1),
+            _ => 12,
+        };
+
+        FETCH_CYCLES + cycles
+    }
+}
+
+/// The machine cycles of drawing an n rows long sprite at x, y in the VIP interpreter
+///
+/// Rows below the bottom of the display are not drawn, and the second byte of a shifted row is
+/// not written past the right edge.
+fn draw_cycles(x: u8, y: u8, n: u8) -> u32 {
+    let (x, y) = (x as usize % LORES_WIDTH, y as usize % LORES_HEIGHT);
+    let rows = (n as usize).min(LORES_HEIGHT - y) as u32;
+    let shift = (x % 8) as u32;
+    let bytes = if shift > 0 && x < LORES_WIDTH - 8 {
+        2
+    } else {
+        1
+    };
+
+    26 + rows * (22 + 4 * shift + 12 * bytes)
+}
@@ -0,0 +1,100 @@
+use std::io::{self, Write};
+
+use crate::{Chip8, Instruction, State};
+
+/// The size of a record in the binary trace format
+pub const BINARY_RECORD_SIZE: usize = 24;
+
+/// The format of the records written by a [`Tracer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per instruction, in hex
+    ///
+    /// `PC=0200 OP=6005 I=0000 V=00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00 DT=00 ST=00 LD V0, 0x05`
+    Text,
+    /// Fixed size records of [`BINARY_RECORD_SIZE`] bytes, the program counter, opcode and I as big
+    /// endian words followed by v0-vF, the delay timer and the sound timer
+    Binary,
+}
+
+impl TraceFormat {
+    /// Look up a format by name, `text` or `binary`
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "binary" => Some(TraceFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// Writes a record of the machine state before each instruction, to compare runs between emulators
+///
+/// Tracing is opt in, the emulator itself does no tracing work. Call [`Tracer::trace`] before
+/// each [`Chip8::cycle`].
+///
+/// * `writer`: Where the records are written
+/// * `format`: The format of the records
+pub struct Tracer<W: Write> {
+    writer: W,
+    format: TraceFormat,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(writer: W, format: TraceFormat) -> Self {
+        Self { writer, format }
+    }
+
+    /// Write a record of the instruction the next cycle executes, cycles that do not execute an
+    /// instruction, like while waiting for a key, are not recorded
+    pub fn trace(&mut self, chip8: &Chip8) -> io::Result<()> {
+        if !matches!(chip8.state, State::Default) {
+            return Ok(());
+        }
+
+        let pc = chip8.pc as usize;
+        let opcode = chip8
+            .mem
+            .get(pc..pc + 2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]));
+
+        match self.format {
+            TraceFormat::Text => {
+                let opcode_text = opcode.map_or("????".to_string(), |op| format!("{:04X}", op));
+                let mnemonic = opcode
+                    .and_then(|op| Instruction::decode(op).ok())
+                    .filter(|instruction| instruction.variant() <= chip8.variant)
+                    .map_or("??".to_string(), |instruction| instruction.to_string());
+                let registers: Vec<String> =
+                    chip8.vs.iter().map(|v| format!("{:02X}", v)).collect();
+
+                writeln!(
+                    self.writer,
+                    "PC={:04X} OP={} I={:04X} V={} DT={:02X} ST={:02X} {}",
+                    chip8.pc,
+                    opcode_text,
+                    chip8.i,
+                    registers.join(","),
+                    chip8.delay_timer,
+                    chip8.sound_timer,
+                    mnemonic
+                )
+            }
+            TraceFormat::Binary => {
+                let mut record = [0; BINARY_RECORD_SIZE];
+                record[0..2].copy_from_slice(&chip8.pc.to_be_bytes());
+                record[2..4].copy_from_slice(&opcode.unwrap_or(0).to_be_bytes());
+                record[4..6].copy_from_slice(&chip8.i.to_be_bytes());
+                record[6..22].copy_from_slice(&chip8.vs);
+                record[22] = chip8.delay_timer;
+                record[23] = chip8.sound_timer;
+                self.writer.write_all(&record)
+            }
+        }
+    }
+
+    /// Flush the records written so far
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,84 @@
+use std::ops::RangeInclusive;
+
+use crate::Chip8;
+
+/// The memory accesses a watchpoint triggers on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Reads of sprite and register data, instruction fetches are not watched
+    Read,
+    /// Writes from any instruction
+    Write,
+    /// Both reads and writes
+    ReadWrite,
+}
+
+impl Access {
+    fn matches(self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::ReadWrite => true,
+        }
+    }
+}
+
+/// A watched range of memory
+///
+/// * `range`: The watched addresses
+/// * `access`: The accesses that trigger the watchpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Watchpoint {
+    range: RangeInclusive<u16>,
+    access: Access,
+}
+
+/// The first watchpoint hit by the instruction being executed
+///
+/// * `addr`: The accessed address
+/// * `old`: The value before the access
+/// * `new`: The value after the access, the same as `old` for reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct WatchHit {
+    pub(crate) addr: u16,
+    pub(crate) old: u8,
+    pub(crate) new: u8,
+}
+
+impl Chip8 {
+    /// Watch a range of memory, a [`crate::StepOutcome::Watchpoint`] is returned by the cycle that
+    /// accesses it
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, access: Access) {
+        self.watchpoints.push(Watchpoint { range, access });
+    }
+
+    /// Remove the watchpoints over exactly the range
+    pub fn remove_watchpoint(&mut self, range: RangeInclusive<u16>) {
+        self.watchpoints
+            .retain(|watchpoint| watchpoint.range != range);
+    }
+
+    /// Remove all watchpoints
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Record an access to memory if it hits a watchpoint, only the first hit of an instruction is
+    /// kept
+    pub(crate) fn watch(&mut self, addr: usize, old: u8, new: u8, write: bool) {
+        if self.watch_hit.is_some() || self.watchpoints.is_empty() {
+            return;
+        }
+
+        let Ok(addr) = u16::try_from(addr) else {
+            return;
+        };
+        let hit = self
+            .watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.range.contains(&addr) && watchpoint.access.matches(write));
+        if hit {
+            self.watch_hit = Some(WatchHit { addr, old, new });
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Differential tests of the emulator against the reference executor
+//!
+//! Random programs run on random machine states in both, one step at a time, and the first
+//! difference in their state is reported.
+
+mod reference;
+
+use chip8::{Chip8, Instruction, MemoryIncrement, Quirks, State, Variant};
+use proptest::prelude::*;
+use reference::Reference;
+
+/// Opcodes to generate as a base opcode and the mask of its random bits, covering every
+/// instruction with some jumps and indexes biased towards the program and data, and small
+/// immediates to make equal values likely
+const TEMPLATES: [(u16, u16); 57] = [
+    (0x00c0, 0x000f),
+    (0x00d0, 0x000f),
+    (0x00e0, 0x0000),
+    (0x00ee, 0x0000),
+    (0x00fb, 0x0000),
+    (0x00fc, 0x0000),
+    (0x00fd, 0x0000),
+    (0x00fe, 0x0000),
+    (0x00ff, 0x0000),
+    (0x1000, 0x0fff),
+    (0x1200, 0x007f),
+    (0x2000, 0x0fff),
+    (0x2200, 0x007f),
+    (0x3000, 0x0fff),
+    (0x4000, 0x0fff),
+    (0x5000, 0x0ff0),
+    (0x5002, 0x0ff0),
+    (0x5003, 0x0ff0),
+    (0x6000, 0x0fff),
+    (0x6000, 0x0f03),
+    (0x7000, 0x0fff),
+    (0x7000, 0x0f03),
+    (0x8000, 0x0ff0),
+    (0x8001, 0x0ff0),
+    (0x8002, 0x0ff0),
+    (0x8003, 0x0ff0),
+    (0x8004, 0x0ff0),
+    (0x8005, 0x0ff0),
+    (0x8006, 0x0ff0),
+    (0x8007, 0x0ff0),
+    (0x800e, 0x0ff0),
+    (0x9000, 0x0ff0),
+    (0xa000, 0x0fff),
+    (0xa800, 0x00ff),
+    (0xb000, 0x0fff),
+    (0xb200, 0x007f),
+    (0xc000, 0x0fff),
+    (0xd000, 0x0fff),
+    (0xe09e, 0x0f00),
+    (0xe0a1, 0x0f00),
+    (0xf000, 0x0000),
+    (0xf001, 0x0f00),
+    (0xf002, 0x0000),
+    (0xf007, 0x0f00),
+    (0xf00a, 0x0f00),
+    (0xf011, 0x0f00),
+    (0xf015, 0x0f00),
+    (0xf018, 0x0f00),
+    (0xf01e, 0x0f00),
+    (0xf029, 0x0f00),
+    (0xf030, 0x0f00),
+    (0xf033, 0x0f00),
+    (0xf03a, 0x0f00),
+    (0xf055, 0x0f00),
+    (0xf065, 0x0f00),
+    (0xf075, 0x0f00),
+    (0xf085, 0x0f00),
+];
+
+/// Where the random data is placed in memory
+const DATA: usize = 0x800;
+
+/// Something to do to both machines
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Step,
+    Down(u8),
+    Up(u8),
+    Timers,
+}
+
+/// The random machine state to start from
+///
+/// * `data`: Random bytes placed at [`DATA`]
+#[derive(Debug, Clone)]
+struct Machine {
+    variant: Variant,
+    quirks: Quirks,
+    seed: u64,
+    registers: [u8; 16],
+    i: u16,
+    delay: u8,
+    sound: u8,
+    data: Vec<u8>,
+}
+
+fn opcode() -> impl Strategy<Value = u16> {
+    prop_oneof![
+        4 => (0..TEMPLATES.len(), any::<u16>())
+            .prop_map(|(template, bits)| TEMPLATES[template].0 | bits & TEMPLATES[template].1),
+        1 => any::<u16>(),
+    ]
+}
+
+/// A register value, often at the edges of the range to make carries and equal values likely
+fn register() -> impl Strategy<Value = u8> {
+    prop_oneof![any::<u8>(), 0u8..4, 0xfcu8..=0xff]
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    prop_oneof![
+        12 => Just(Action::Step),
+        1 => (0u8..16).prop_map(Action::Down),
+        1 => (0u8..16).prop_map(Action::Up),
+        1 => Just(Action::Timers),
+    ]
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    let memory = prop_oneof![
+        Just(MemoryIncrement::Unchanged),
+        Just(MemoryIncrement::X),
+        Just(MemoryIncrement::XPlusOne),
+    ];
+
+    (any::<[bool; 5]>(), memory).prop_map(
+        |([vf_reset, shift_vy, jump_vx, clipping, display_wait], memory)| Quirks {
+            vf_reset,
+            shift_vy,
+            memory,
+            jump_vx,
+            clipping,
+            display_wait,
+        },
+    )
+}
+
+fn machine() -> impl Strategy<Value = Machine> {
+    let variant = prop_oneof![
+        Just(Variant::Chip8),
+        Just(Variant::SuperChip),
+        Just(Variant::XoChip),
+    ];
+    let i = prop_oneof![any::<u16>(), DATA as u16..DATA as u16 + 0x100];
+
+    (
+        variant,
+        quirks(),
+        any::<u64>(),
+        prop::array::uniform16(register()),
+        i,
+        any::<(u8, u8)>(),
+        prop::collection::vec(any::<u8>(), 0x100),
+    )
+        .prop_map(
+            |(variant, quirks, seed, registers, i, (delay, sound), data)| Machine {
+                variant,
+                quirks,
+                seed,
+                registers,
+                i,
+                delay,
+                sound,
+                data,
+            },
+        )
+}
+
+/// Describe the first difference between the reference and the emulator
+fn divergence(reference: &Reference, chip8: &Chip8) -> Option<String> {
+    let differ = |what: &str, expected: &dyn std::fmt::Debug, actual: &dyn std::fmt::Debug| {
+        Some(format!(
+            "{} differs, reference {:x?} but emulator {:x?}",
+            what, expected, actual
+        ))
+    };
+
+    if reference.pc != chip8.pc() {
+        return differ("PC", &reference.pc, &chip8.pc());
+    }
+    if reference.i != chip8.i() {
+        return differ("I", &reference.i, &chip8.i());
+    }
+    if let Some(x) = (0..16).find(|x| reference.v[*x] != chip8.registers()[*x]) {
+        return differ(&format!("V{:X}", x), &reference.v[x], &chip8.registers()[x]);
+    }
+    if reference.stack != chip8.stack() {
+        return differ("The stack", &reference.stack, &chip8.stack());
+    }
+    if (reference.delay, reference.sound) != (chip8.delay_timer(), chip8.sound_timer()) {
+        return differ(
+            "The timers",
+            &(reference.delay, reference.sound),
+            &(chip8.delay_timer(), chip8.sound_timer()),
+        );
+    }
+
+    let state = match chip8.state() {
+        State::Default => (None, false, false),
+        State::GetKey(x) => (Some(*x), false, false),
+        State::DisplayWait => (None, true, false),
+        State::Exited => (None, false, true),
+    };
+    let expected = (reference.waiting, reference.display_wait, reference.exited);
+    if expected != state {
+        return differ("The state", &expected, chip8.state());
+    }
+
+    if let Some(addr) = (0..reference.mem.len()).find(|a| reference.mem[*a] != chip8.memory()[*a]) {
+        return differ(
+            &format!("Memory at {:#06x}", addr),
+            &reference.mem[addr],
+            &chip8.memory()[addr],
+        );
+    }
+
+    if (reference.width(), reference.height()) != (chip8.width(), chip8.height()) {
+        return differ(
+            "The resolution",
+            &(reference.width(), reference.height()),
+            &(chip8.width(), chip8.height()),
+        );
+    }
+    if let Some(p) =
+        (0..reference.display.len()).find(|p| reference.display[*p] != chip8.display()[*p])
+    {
+        let (x, y) = (p % reference.width(), p / reference.width());
+        return differ(
+            &format!("The pixel at {}, {}", x, y),
+            &reference.display[p],
+            &chip8.display()[p],
+        );
+    }
+
+    if reference.audio != *chip8.audio_pattern() {
+        return differ("The audio pattern", &reference.audio, chip8.audio_pattern());
+    }
+    if reference.pitch != chip8.pitch() {
+        return differ("The pitch", &reference.pitch, &chip8.pitch());
+    }
+
+    None
+}
+
+/// Describe the instruction at the program counter of the reference
+fn describe(reference: &Reference) -> String {
+    let pc = reference.pc as usize;
+    match reference.mem.get(pc..pc + 2) {
+        Some(&[a, b]) => {
+            let opcode = u16::from_be_bytes([a, b]);
+            match Instruction::decode(opcode) {
+                Ok(instruction) => format!("{:#06x}: {:04X} {:?}", pc, opcode, instruction),
+                Err(_) => format!("{:#06x}: {:04X}", pc, opcode),
+            }
+        }
+        _ => format!("{:#06x}: outside of memory", pc),
+    }
+}
+
+proptest! {
+    #[test]
+    fn matches_reference(
+        machine in machine(),
+        program in prop::collection::vec(opcode(), 1..64),
+        actions in prop::collection::vec(action(), 1..256),
+    ) {
+        let rom = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        let mut chip8 = Chip8::builder(rom)
+            .variant(machine.variant)
+            .quirks(machine.quirks)
+            .seed(machine.seed)
+            .build()
+            .unwrap();
+        for (x, v) in machine.registers.iter().enumerate() {
+            chip8.set_register(x as u8, *v);
+        }
+        chip8.set_i(machine.i);
+        chip8.set_delay_timer(machine.delay);
+        chip8.set_sound_timer(machine.sound);
+        chip8.memory_mut()[DATA..DATA + machine.data.len()].copy_from_slice(&machine.data);
+
+        let mut reference = Reference::new(&chip8, machine.seed);
+        prop_assert_eq!(divergence(&reference, &chip8), None, "before the first action");
+
+        for (n, action) in actions.into_iter().enumerate() {
+            let instruction = describe(&reference);
+            let mut failed = false;
+            match action {
+                Action::Step => {
+                    let expected = reference.step();
+                    prop_assert_eq!(
+                        expected,
+                        chip8.cycle(),
+                        "action {} returned differently at {}",
+                        n,
+                        instruction
+                    );
+                    failed = expected.is_err();
+                }
+                Action::Down(key) => {
+                    reference.down(key);
+                    chip8.down(key);
+                }
+                Action::Up(key) => {
+                    reference.up(key);
+                    chip8.up(key);
+                }
+                Action::Timers => {
+                    reference.decrease_timers();
+                    chip8.decrease_timers();
+                }
+            }
+
+            if let Some(difference) = divergence(&reference, &chip8) {
+                prop_assert!(
+                    false,
+                    "action {} {:?} diverged at {}: {}",
+                    n,
+                    action,
+                    instruction,
+                    difference
+                );
+            }
+
+            // Both keep failing the same way after an error
+            if failed {
+                break;
+            }
+        }
+    }
+}
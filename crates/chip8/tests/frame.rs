@@ -0,0 +1,214 @@
+//! Tests of running the emulator a frame at a time
+
+use chip8::{Access, Chip8, Chip8Error, FrameEnd, FrameResult, Quirks, State, Timing, Variant};
+
+fn finished(cycles: u32, display_changed: bool) -> FrameResult {
+    FrameResult {
+        cycles,
+        display_changed,
+        end: FrameEnd::Finished,
+    }
+}
+
+/// Build an emulator with the VIP timing
+fn vip(rom: Vec<u8>) -> Chip8 {
+    Chip8::builder(rom)
+        .timing(Timing::CosmacVip)
+        .build()
+        .unwrap()
+}
+
+/// The VIP machine cycles of the first instruction of the rom with v0 and v1 set
+fn vip_cycles(rom: Vec<u8>, v0: u8, v1: u8) -> u32 {
+    let mut chip8 = vip(rom);
+    chip8.set_register(0, v0);
+    chip8.set_register(1, v1);
+    chip8.vip_cycles()
+}
+
+#[test]
+fn timers_tick_once_per_frame() {
+    // JP 0x200
+    let mut chip8 = Chip8::new(vec![0x12, 0x00]).unwrap();
+    chip8.set_delay_timer(10);
+    chip8.set_sound_timer(10);
+
+    assert_eq!(chip8.run_frame(8), Ok(finished(8, false)));
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (9, 9));
+
+    assert_eq!(chip8.run_frame(20), Ok(finished(20, false)));
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (8, 8));
+}
+
+#[test]
+fn display_changed() {
+    // LD F, V0; DRW V0, V0, 5; JP 0x204
+    let mut chip8 = Chip8::new(vec![0xf0, 0x29, 0xd0, 0x05, 0x12, 0x04]).unwrap();
+    assert_eq!(chip8.run_frame(8), Ok(finished(8, true)));
+    assert_eq!(chip8.run_frame(8), Ok(finished(8, false)));
+
+    // Loading a state redraws it
+    let state = chip8.save_state();
+    chip8.load_state(&state).unwrap();
+    assert_eq!(chip8.run_frame(8), Ok(finished(8, true)));
+}
+
+#[test]
+fn blocked_cycles_count() {
+    // LD V0, K
+    let mut chip8 = Chip8::new(vec![0xf0, 0x0a]).unwrap();
+    chip8.set_delay_timer(2);
+
+    assert_eq!(chip8.run_frame(8), Ok(finished(8, false)));
+    assert!(matches!(chip8.state(), State::GetKey(0)));
+    assert_eq!(chip8.delay_timer(), 1);
+}
+
+#[test]
+fn watchpoints_resume_the_frame() {
+    // LD I, 0x300; LD [I], V0; JP 0x204
+    let mut chip8 = Chip8::new(vec![0xa3, 0x00, 0xf0, 0x55, 0x12, 0x04]).unwrap();
+    chip8.add_watchpoint(0x300..=0x300, Access::Write);
+    chip8.set_delay_timer(5);
+
+    let frame = chip8.run_frame(8).unwrap();
+    assert_eq!(frame.cycles, 2);
+    assert_eq!(
+        frame.end,
+        FrameEnd::Watchpoint {
+            addr: 0x300,
+            pc: 0x202,
+            old: 0,
+            new: 0
+        }
+    );
+    assert_eq!(chip8.delay_timer(), 5);
+
+    assert_eq!(chip8.run_frame(8), Ok(finished(6, false)));
+    assert_eq!(chip8.delay_timer(), 4);
+}
+
+#[test]
+fn hooks_interrupt_the_frame() {
+    // JP 0x200
+    let mut chip8 = Chip8::new(vec![0x12, 0x00]).unwrap();
+    chip8.set_delay_timer(5);
+
+    let mut calls = 0;
+    let frame = chip8
+        .run_frame_with(8, |_| {
+            calls += 1;
+            calls <= 3
+        })
+        .unwrap();
+    assert_eq!((frame.cycles, frame.end), (3, FrameEnd::Interrupted));
+    assert_eq!(chip8.delay_timer(), 5);
+
+    assert_eq!(chip8.run_frame(8), Ok(finished(5, false)));
+    assert_eq!(chip8.delay_timer(), 4);
+}
+
+#[test]
+fn exits_and_errors_end_the_frame() {
+    // EXIT
+    let mut chip8 = Chip8::builder(vec![0x00, 0xfd])
+        .variant(Variant::SuperChip)
+        .build()
+        .unwrap();
+    let frame = chip8.run_frame(8).unwrap();
+    assert_eq!((frame.cycles, frame.end), (1, FrameEnd::Exited));
+
+    // LD V0, 0; an unknown opcode
+    let mut chip8 = Chip8::new(vec![0x60, 0x00, 0xff, 0xff]).unwrap();
+    chip8.set_delay_timer(5);
+    assert_eq!(
+        chip8.run_frame(8),
+        Err(Chip8Error::UnknownOpcode {
+            opcode: 0xffff,
+            pc: 0x202
+        })
+    );
+    assert_eq!(chip8.delay_timer(), 5);
+}
+
+#[test]
+fn vip_instruction_costs() {
+    // JP 0x200
+    assert_eq!(vip_cycles(vec![0x12, 0x00], 0, 0), 80);
+
+    // SE V0, 0 costs more when it skips
+    assert_eq!(vip_cycles(vec![0x30, 0x00], 0, 0), 82);
+    assert_eq!(vip_cycles(vec![0x30, 0x00], 1, 0), 78);
+
+    // CLS, and JP V0, 0x2ff costs more when it crosses a page
+    assert_eq!(vip_cycles(vec![0x00, 0xe0], 0, 0), 3146);
+    assert_eq!(vip_cycles(vec![0xb2, 0xff], 0, 0), 90);
+    assert_eq!(vip_cycles(vec![0xb2, 0xff], 1, 0), 92);
+
+    // LD V0, K when it starts waiting, HIGH is not a VIP instruction
+    assert_eq!(vip_cycles(vec![0xf0, 0x0a], 0, 0), 87);
+    assert_eq!(vip_cycles(vec![0x00, 0xff], 0, 0), 80);
+
+    // LD B, V0 depends on the digits, LD [I], V2 on the number of registers
+    assert_eq!(vip_cycles(vec![0xf0, 0x33], 199, 0), 452);
+    assert_eq!(vip_cycles(vec![0xf0, 0x33], 0, 0), 148);
+    assert_eq!(vip_cycles(vec![0xf2, 0x55], 0, 0), 124);
+
+    // DRW V0, V1, 5 depends on the position, shifted sprites write two bytes and rows below the
+    // display are not drawn
+    let draw = vec![0xd0, 0x15];
+    assert_eq!(vip_cycles(draw.clone(), 0, 0), 264);
+    assert_eq!(vip_cycles(draw.clone(), 3, 0), 384);
+    assert_eq!(vip_cycles(draw.clone(), 59, 0), 324);
+    assert_eq!(vip_cycles(draw, 0, 30), 162);
+}
+
+#[test]
+fn vip_blocked_keys_take_the_frame() {
+    // LD V0, K; JP 0x202
+    let mut chip8 = vip(vec![0xf0, 0x0a, 0x12, 0x02]);
+
+    // LD V0, K costs 87 cycles and the blocked cycle after it the rest of the frame
+    assert_eq!(chip8.run_frame(8), Ok(finished(2, false)));
+    assert!(matches!(chip8.state(), State::GetKey(0)));
+
+    // Every further check takes a whole frame
+    assert_eq!(chip8.run_frame(8), Ok(finished(1, false)));
+    assert_eq!(chip8.run_frame(8), Ok(finished(1, false)));
+
+    chip8.down(5);
+    chip8.up(5);
+    assert_eq!(chip8.registers()[0], 5);
+    assert_eq!(chip8.run_frame(8), Ok(finished(23, false)));
+}
+
+#[test]
+fn vip_timing_ignores_ipf() {
+    // JP 0x200 takes 80 of the 1836 cycles of a frame
+    let mut chip8 = vip(vec![0x12, 0x00]);
+    chip8.set_delay_timer(5);
+    assert_eq!(chip8.run_frame(8), Ok(finished(23, false)));
+    assert_eq!(chip8.run_frame(100), Ok(finished(23, false)));
+    assert_eq!(chip8.delay_timer(), 3);
+}
+
+#[test]
+fn vip_cycles_carry_over() {
+    // CLS takes most of two frames; JP 0x202
+    let mut chip8 = vip(vec![0x00, 0xe0, 0x12, 0x02]);
+    assert_eq!(chip8.run_frame(8), Ok(finished(1, true)));
+    assert_eq!(chip8.run_frame(8), Ok(finished(7, false)));
+}
+
+#[test]
+fn vip_blocking_ends_the_frame() {
+    // LD I, 0; DRW V0, V0, 5 waits for the display; JP 0x204
+    let mut chip8 = Chip8::builder(vec![0xa0, 0x00, 0xd0, 0x05, 0x12, 0x04])
+        .quirks(Quirks::COSMAC_VIP_DISPLAY_WAIT)
+        .timing(Timing::CosmacVip)
+        .build()
+        .unwrap();
+    assert_eq!(chip8.run_frame(8), Ok(finished(3, true)));
+    assert!(matches!(chip8.state(), State::Default));
+    assert_eq!(chip8.run_frame(8), Ok(finished(23, false)));
+}
@@ -0,0 +1,539 @@
+use chip8::{Chip8, Chip8Error, MemoryIncrement, Quirks, State, StepOutcome, Variant};
+
+/// Build an emulator with the opcodes as its rom
+fn build(variant: Variant, quirks: Quirks, opcodes: &[u16]) -> Chip8 {
+    let rom = opcodes.iter().flat_map(|op| op.to_be_bytes()).collect();
+    Chip8::builder(rom)
+        .variant(variant)
+        .quirks(quirks)
+        .seed(0)
+        .build()
+        .unwrap()
+}
+
+/// Execute until the program counter leaves the first `len` opcodes of the rom
+fn finish(chip8: &mut Chip8, len: usize) {
+    let end = 0x200 + 2 * len as u16;
+    for _ in 0..1000 {
+        if !(0x200..end).contains(&chip8.pc()) {
+            return;
+        }
+        chip8.cycle().unwrap();
+    }
+    panic!("the rom did not finish");
+}
+
+/// Execute the opcodes until the program counter leaves them
+fn run(variant: Variant, quirks: Quirks, opcodes: &[u16]) -> Chip8 {
+    let mut chip8 = build(variant, quirks, opcodes);
+    finish(&mut chip8, opcodes.len());
+    chip8
+}
+
+/// Execute the opcodes as CHIP-8 with the COSMAC VIP quirks
+fn vip(opcodes: &[u16]) -> Chip8 {
+    run(Variant::Chip8, Quirks::COSMAC_VIP, opcodes)
+}
+
+/// Execute the opcodes as SUPER-CHIP
+fn schip(opcodes: &[u16]) -> Chip8 {
+    run(Variant::SuperChip, Quirks::SCHIP_1_1, opcodes)
+}
+
+/// Execute the opcodes as XO-CHIP
+fn xochip(opcodes: &[u16]) -> Chip8 {
+    run(Variant::XoChip, Quirks::XO_CHIP, opcodes)
+}
+
+/// Execute the setup and then check if the last opcode skipped the following instruction
+fn skips(setup: &[u16], opcode: u16) -> bool {
+    let chip8 = vip(&[setup, &[opcode]].concat());
+    let next = 0x200 + 2 * (setup.len() as u16 + 1);
+    match chip8.pc() - next {
+        0 => false,
+        2 => true,
+        _ => panic!("{:04X} jumped to {:#06x}", opcode, chip8.pc()),
+    }
+}
+
+fn pixel(chip8: &Chip8, x: usize, y: usize) -> u8 {
+    chip8.display()[y * chip8.width() + x]
+}
+
+/// The coordinates of the pixels that are set, row by row
+fn lit(chip8: &Chip8) -> Vec<(usize, usize)> {
+    (0..chip8.display().len())
+        .filter(|i| chip8.display()[*i] != 0)
+        .map(|i| (i % chip8.width(), i / chip8.width()))
+        .collect()
+}
+
+#[test]
+fn jump_and_call() {
+    assert_eq!(vip(&[0x1234]).pc(), 0x234);
+
+    // CALL 0x206 and RET
+    let mut chip8 = build(
+        Variant::Chip8,
+        Quirks::COSMAC_VIP,
+        &[0x2206, 0x0000, 0x0000, 0x00ee],
+    );
+    chip8.cycle().unwrap();
+    assert_eq!((chip8.pc(), chip8.stack()), (0x206, &[0x202][..]));
+    chip8.cycle().unwrap();
+    assert_eq!((chip8.pc(), chip8.stack()), (0x202, &[][..]));
+
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP, &[0x00ee]);
+    assert_eq!(chip8.cycle(), Err(Chip8Error::StackUnderflow));
+    assert_eq!(chip8.pc(), 0x200);
+
+    // Calling itself overflows the stack
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP, &[0x2200]);
+    for _ in 0..16 {
+        chip8.cycle().unwrap();
+    }
+    assert_eq!(chip8.cycle(), Err(Chip8Error::StackOverflow));
+
+    // BNNN jumps to v0 + nnn, or vX + xnn with the jump quirk
+    assert_eq!(vip(&[0x6004, 0x6302, 0xb310]).pc(), 0x314);
+    let jump_vx = Quirks {
+        jump_vx: true,
+        ..Quirks::COSMAC_VIP
+    };
+    let chip8 = run(Variant::Chip8, jump_vx, &[0x6004, 0x6302, 0xb310]);
+    assert_eq!(chip8.pc(), 0x312);
+}
+
+#[test]
+fn exit_and_unknown() {
+    let mut chip8 = build(Variant::SuperChip, Quirks::SCHIP_1_1, &[0x00fd]);
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Exited));
+    assert!(matches!(chip8.state(), State::Exited));
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Exited));
+
+    // Instructions of later variants are unknown
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP, &[0x00fd]);
+    assert_eq!(
+        chip8.cycle(),
+        Err(Chip8Error::UnknownOpcode {
+            pc: 0x200,
+            opcode: 0x00fd
+        })
+    );
+
+    let mut chip8 = build(Variant::XoChip, Quirks::XO_CHIP, &[0x6000, 0xffff]);
+    chip8.cycle().unwrap();
+    assert_eq!(
+        chip8.cycle(),
+        Err(Chip8Error::UnknownOpcode {
+            pc: 0x202,
+            opcode: 0xffff
+        })
+    );
+    assert_eq!(chip8.pc(), 0x202);
+}
+
+#[test]
+fn skip() {
+    // v0 = 1, v1 = 1, v2 = 2
+    let setup = [0x6001, 0x6101, 0x6202];
+
+    assert!(skips(&setup, 0x3001));
+    assert!(!skips(&setup, 0x3002));
+    assert!(skips(&setup, 0x4002));
+    assert!(!skips(&setup, 0x4001));
+    assert!(skips(&setup, 0x5010));
+    assert!(!skips(&setup, 0x5020));
+    assert!(skips(&setup, 0x9020));
+    assert!(!skips(&setup, 0x9010));
+
+    // No keys are pressed
+    assert!(!skips(&setup, 0xe09e));
+    assert!(skips(&setup, 0xe0a1));
+
+    let mut chip8 = build(
+        Variant::Chip8,
+        Quirks::COSMAC_VIP,
+        &[0x6001, 0xe09e, 0x0000, 0xe0a1],
+    );
+    chip8.down(0x1);
+    finish(&mut chip8, 4);
+    assert_eq!(chip8.pc(), 0x208);
+
+    // XO-CHIP skips both words of F000 NNNN
+    assert_eq!(xochip(&[0x3000, 0xf000, 0x1234]).pc(), 0x206);
+}
+
+#[test]
+fn load_and_add() {
+    let chip8 = vip(&[0x6a2a, 0x8ba0, 0x6fff, 0x7a01, 0x7bff]);
+    let vs = chip8.registers();
+    assert_eq!((vs[0xa], vs[0xb]), (0x2b, 0x29));
+    // 7xnn does not set the carry flag
+    assert_eq!(vs[0xf], 0xff);
+}
+
+#[test]
+fn logic() {
+    let setup = [0x600c, 0x610a, 0x6f07];
+    let keep_vf = Quirks {
+        vf_reset: false,
+        ..Quirks::COSMAC_VIP
+    };
+
+    for (op, result) in [(0x8011, 0x0e), (0x8012, 0x08), (0x8013, 0x06)] {
+        let opcodes = [&setup[..], &[op]].concat();
+
+        let chip8 = vip(&opcodes);
+        assert_eq!(chip8.registers()[0x0], result, "{:04X}", op);
+        assert_eq!(chip8.registers()[0xf], 0, "{:04X}", op);
+
+        let chip8 = run(Variant::Chip8, keep_vf, &opcodes);
+        assert_eq!(chip8.registers()[0x0], result, "{:04X}", op);
+        assert_eq!(chip8.registers()[0xf], 7, "{:04X}", op);
+    }
+}
+
+#[test]
+fn arithmetic() {
+    let result = |opcodes: &[u16], x: usize| {
+        let chip8 = vip(opcodes);
+        (chip8.registers()[x], chip8.registers()[0xf])
+    };
+
+    // 8xy4 sets vF to the carry
+    assert_eq!(result(&[0x60f0, 0x6120, 0x8014], 0x0), (0x10, 1));
+    assert_eq!(result(&[0x60f0, 0x610f, 0x6f05, 0x8014], 0x0), (0xff, 0));
+    // The flag is written last, replacing the sum if vF is the destination
+    assert_eq!(result(&[0x6fff, 0x6102, 0x8f14], 0xf), (1, 1));
+    assert_eq!(result(&[0x6f01, 0x6102, 0x8f14], 0xf), (0, 0));
+
+    // 8xy5 sets vF if there was no borrow
+    assert_eq!(result(&[0x6005, 0x6103, 0x8015], 0x0), (0x02, 1));
+    assert_eq!(result(&[0x6003, 0x6105, 0x8015], 0x0), (0xfe, 0));
+    assert_eq!(result(&[0x6005, 0x6105, 0x8015], 0x0), (0x00, 1));
+    assert_eq!(result(&[0x6f05, 0x6103, 0x8f15], 0xf), (1, 1));
+    assert_eq!(result(&[0x6f03, 0x6105, 0x8f15], 0xf), (0, 0));
+
+    // 8xy7 subtracts vX from vY
+    assert_eq!(result(&[0x6003, 0x6105, 0x8017], 0x0), (0x02, 1));
+    assert_eq!(result(&[0x6005, 0x6103, 0x8017], 0x0), (0xfe, 0));
+    assert_eq!(result(&[0x6f03, 0x6105, 0x8f17], 0xf), (1, 1));
+}
+
+#[test]
+fn shift() {
+    let in_place = Quirks {
+        shift_vy: false,
+        ..Quirks::COSMAC_VIP
+    };
+    let result = |quirks, opcodes: &[u16], x: usize| {
+        let chip8 = run(Variant::Chip8, quirks, opcodes);
+        (chip8.registers()[x], chip8.registers()[0xf])
+    };
+
+    // The COSMAC VIP shifts vY into vX
+    let vip = Quirks::COSMAC_VIP;
+    assert_eq!(result(vip, &[0x6002, 0x6105, 0x8016], 0x0), (0x02, 1));
+    assert_eq!(result(vip, &[0x6002, 0x6181, 0x801e], 0x0), (0x02, 1));
+    assert_eq!(result(in_place, &[0x6005, 0x6102, 0x8016], 0x0), (0x02, 1));
+    assert_eq!(result(in_place, &[0x6040, 0x6181, 0x801e], 0x0), (0x80, 0));
+
+    // The shifted out bit replaces the result if vF is the destination
+    assert_eq!(result(in_place, &[0x6f02, 0x8ff6], 0xf), (0, 0));
+    assert_eq!(result(in_place, &[0x6f81, 0x8ffe], 0xf), (1, 1));
+}
+
+#[test]
+fn random() {
+    let random = |opcodes: &[u16]| vip(opcodes).registers()[0x0];
+
+    // The seed makes it reproducible
+    assert_eq!(random(&[0xc0ff]), random(&[0xc0ff]));
+    assert_eq!(random(&[0xc000]), 0);
+    assert!(random(&[0xc00f]) <= 0x0f);
+}
+
+#[test]
+fn index() {
+    assert_eq!(vip(&[0xa123]).i(), 0x123);
+    assert_eq!(vip(&[0xa300, 0x6010, 0xf01e]).i(), 0x310);
+    assert_eq!(xochip(&[0xf000, 0x1234]).i(), 0x1234);
+
+    // The small font starts at 0 and the large font follows it
+    assert_eq!(vip(&[0x600a, 0xf029]).i(), 50);
+    assert_eq!(schip(&[0x6001, 0xf030]).i(), 90);
+}
+
+#[test]
+fn bcd() {
+    let digits = |v: u16| {
+        let chip8 = vip(&[0xa300, 0x6000 | v, 0xf033]);
+        assert_eq!(chip8.i(), 0x300);
+        chip8.memory()[0x300..0x303].to_vec()
+    };
+
+    assert_eq!(digits(255), [2, 5, 5]);
+    assert_eq!(digits(107), [1, 0, 7]);
+    assert_eq!(digits(0), [0, 0, 0]);
+
+    // Nothing is written if the digits do not fit in memory
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP, &[0xaffe, 0xf033]);
+    chip8.cycle().unwrap();
+    assert_eq!(
+        chip8.cycle(),
+        Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })
+    );
+    assert_eq!(chip8.memory()[0xffe..], [0, 0]);
+}
+
+#[test]
+fn store_and_load() {
+    let opcodes = [
+        0xa300, 0x6001, 0x6102, 0x6203, 0xf255, // Store v0..=v2
+        0xa300, 0x6000, 0x6100, 0x6200, 0xf165, // Load v0..=v1
+    ];
+
+    for (memory, i) in [
+        (MemoryIncrement::XPlusOne, 0x302),
+        (MemoryIncrement::X, 0x301),
+        (MemoryIncrement::Unchanged, 0x300),
+    ] {
+        let quirks = Quirks {
+            memory,
+            ..Quirks::COSMAC_VIP
+        };
+        let chip8 = run(Variant::Chip8, quirks, &opcodes);
+        assert_eq!(chip8.memory()[0x300..0x304], [1, 2, 3, 0]);
+        assert_eq!(chip8.registers()[..3], [1, 2, 0]);
+        assert_eq!(chip8.i(), i, "{:?}", memory);
+    }
+
+    // 5xy2 and 5xy3 work in reverse if X > Y and leave I unchanged
+    let chip8 = xochip(&[
+        0xa300, 0x6101, 0x6202, 0x6303, 0x5132, 0xa310, 0x5312, // Store
+        0xa310, 0x6100, 0x5123, // Load v1..=v2 in order
+    ]);
+    assert_eq!(chip8.memory()[0x300..0x303], [1, 2, 3]);
+    assert_eq!(chip8.memory()[0x310..0x313], [3, 2, 1]);
+    assert_eq!(chip8.registers()[1..4], [3, 2, 3]);
+    assert_eq!(chip8.i(), 0x310);
+}
+
+#[test]
+fn flags() {
+    let chip8 = schip(&[
+        0x6001, 0x6102, 0x6203, 0xf275, // Store v0..=v2
+        0x6000, 0x6100, 0x6200, 0xf185, // Load v0..=v1
+    ]);
+    assert_eq!(chip8.registers()[..3], [1, 2, 0]);
+}
+
+#[test]
+fn timers() {
+    let mut chip8 = vip(&[0x603c, 0xf015, 0x6105, 0xf118, 0xf207]);
+    assert_eq!(chip8.registers()[0x2], 0x3c);
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (0x3c, 5));
+    assert!(chip8.is_sound_active());
+
+    for _ in 0..5 {
+        chip8.decrease_timers();
+    }
+    assert_eq!((chip8.delay_timer(), chip8.sound_timer()), (0x37, 0));
+    assert!(!chip8.is_sound_active());
+
+    // Fx11 is a duplicate of Fx07
+    let chip8 = vip(&[0x603c, 0xf015, 0xf311]);
+    assert_eq!(chip8.registers()[0x3], 0x3c);
+}
+
+#[test]
+fn audio() {
+    let chip8 = xochip(&[0xa000, 0xf002, 0x6070, 0xf03a]);
+    assert_eq!(chip8.audio_pattern()[..], chip8.memory()[..16]);
+    assert_eq!(chip8.pitch(), 0x70);
+}
+
+#[test]
+fn get_key() {
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP, &[0xf30a, 0x6001]);
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Executed));
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Blocked));
+
+    // Blocks until the key is released
+    chip8.down(0x7);
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Blocked));
+    assert!(matches!(chip8.state(), State::GetKey(0x3)));
+    chip8.up(0x8);
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Blocked));
+
+    chip8.up(0x7);
+    assert!(matches!(chip8.state(), State::Default));
+    assert_eq!(chip8.registers()[0x3], 0x7);
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Executed));
+    assert_eq!(chip8.pc(), 0x204);
+}
+
+#[test]
+fn draw() {
+    // The font sprite for 0 at 1, 2
+    let mut chip8 = build(
+        Variant::Chip8,
+        Quirks::COSMAC_VIP,
+        &[0x6001, 0x6102, 0xa000, 0xd015, 0xd015],
+    );
+    finish(&mut chip8, 4);
+    assert_eq!(lit(&chip8).len(), 14);
+    assert_eq!((pixel(&chip8, 1, 2), pixel(&chip8, 2, 3)), (1, 0));
+    assert_eq!(chip8.registers()[0xf], 0);
+
+    // Drawing it again erases it and sets the collision flag
+    chip8.cycle().unwrap();
+    assert!(lit(&chip8).is_empty());
+    assert_eq!(chip8.registers()[0xf], 1);
+
+    // The start position wraps around even with clipping
+    let chip8 = vip(&[0x6042, 0x6121, 0xa000, 0xd015]);
+    assert_eq!(lit(&chip8)[0], (2, 1));
+
+    let clear = vip(&[0xa000, 0xd015, 0x00e0]);
+    assert!(lit(&clear).is_empty());
+}
+
+#[test]
+fn display_wait() {
+    // The font sprite for 0, then LD V0, 1
+    let opcodes = [0xa000, 0xd015, 0x6001];
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP_DISPLAY_WAIT, &opcodes);
+    for _ in 0..2 {
+        assert_eq!(chip8.cycle(), Ok(StepOutcome::Executed));
+    }
+    assert_eq!(lit(&chip8).len(), 14);
+
+    // The draw blocks until the timers tick
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Blocked));
+    assert!(matches!(chip8.state(), State::DisplayWait));
+    chip8.decrease_timers();
+    assert_eq!(chip8.cycle(), Ok(StepOutcome::Executed));
+    assert_eq!(chip8.registers()[0], 1);
+
+    // Without the quirk draws do not wait
+    let mut chip8 = build(Variant::Chip8, Quirks::COSMAC_VIP, &opcodes);
+    for _ in 0..3 {
+        assert_eq!(chip8.cycle(), Ok(StepOutcome::Executed));
+    }
+}
+
+#[test]
+fn clipping() {
+    // The font sprite for 0 at the bottom right corner
+    let opcodes = [0x603e, 0x611e, 0xa000, 0xd015];
+
+    let clipped = vip(&opcodes);
+    assert_eq!(lit(&clipped), [(62, 30), (63, 30), (62, 31)]);
+
+    let wrapping = Quirks {
+        clipping: false,
+        ..Quirks::COSMAC_VIP
+    };
+    let wrapped = run(Variant::Chip8, wrapping, &opcodes);
+    assert_eq!(
+        lit(&wrapped),
+        [
+            (1, 0),
+            (62, 0),
+            (1, 1),
+            (62, 1),
+            (0, 2),
+            (1, 2),
+            (62, 2),
+            (63, 2),
+            (0, 30),
+            (1, 30),
+            (62, 30),
+            (63, 30),
+            (1, 31),
+            (62, 31),
+        ]
+    );
+}
+
+#[test]
+fn large_sprites() {
+    let mut chip8 = build(
+        Variant::SuperChip,
+        Quirks::SCHIP_1_1,
+        &[0x00ff, 0xa300, 0xd010],
+    );
+    chip8.memory_mut()[0x300..0x320].fill(0xff);
+    finish(&mut chip8, 3);
+    assert_eq!((chip8.width(), chip8.height()), (128, 64));
+    assert_eq!(lit(&chip8).len(), 16 * 16);
+
+    // CHIP-8 draws nothing with a height of 0
+    assert!(lit(&vip(&[0xa000, 0xd010])).is_empty());
+}
+
+#[test]
+fn resolution() {
+    let drawn = [0xa000, 0xd015];
+
+    let hires = schip(&[&[0x00ff][..], &drawn].concat());
+    assert_eq!((hires.width(), hires.height()), (128, 64));
+    assert_eq!(lit(&hires).len(), 14);
+
+    // Switching resolution clears the display
+    let lores = schip(&[&[0x00ff][..], &drawn, &[0x00fe]].concat());
+    assert_eq!((lores.width(), lores.height()), (64, 32));
+    assert!(lit(&lores).is_empty());
+}
+
+#[test]
+fn scroll() {
+    // The font sprite for 0 at 16, 16 in high resolution, XO-CHIP adds scrolling up
+    let setup = [0x00ff, 0x6010, 0x6110, 0xa000, 0xd015];
+    let drawn = lit(&xochip(&setup));
+    let scrolled = |op, dx: isize, dy: isize| {
+        let expected: Vec<_> = drawn
+            .iter()
+            .map(|&(x, y)| ((x as isize + dx) as usize, (y as isize + dy) as usize))
+            .collect();
+        assert_eq!(
+            lit(&xochip(&[&setup[..], &[op]].concat())),
+            expected,
+            "{:04X}",
+            op
+        );
+    };
+
+    scrolled(0x00c2, 0, 2);
+    scrolled(0x00d3, 0, -3);
+    scrolled(0x00fb, 4, 0);
+    scrolled(0x00fc, -4, 0);
+
+    // Pixels scrolled off the screen are lost
+    let chip8 = xochip(&[0x00ff, 0xa000, 0xd015, 0x00fc]);
+    assert_eq!(lit(&chip8).len(), 0);
+}
+
+#[test]
+fn planes() {
+    // The second plane only
+    let chip8 = xochip(&[0xf201, 0xa000, 0xd015]);
+    assert_eq!(pixel(&chip8, 0, 0), 2);
+
+    // Both planes, the sprite for the second plane follows the first, the font sprites 0 and 1
+    let chip8 = xochip(&[0xf301, 0xa000, 0xd015]);
+    assert_eq!(pixel(&chip8, 0, 0), 1);
+    assert_eq!(pixel(&chip8, 2, 0), 3);
+    assert_eq!(pixel(&chip8, 2, 1), 2);
+
+    // No planes
+    assert!(lit(&xochip(&[0xf001, 0xa000, 0xd015])).is_empty());
+
+    // Clearing only clears the selected planes
+    let chip8 = xochip(&[0xf301, 0xa000, 0xd015, 0xf201, 0x00e0]);
+    assert_eq!(pixel(&chip8, 0, 0), 1);
+    assert_eq!(pixel(&chip8, 2, 0), 1);
+    assert_eq!(pixel(&chip8, 2, 1), 0);
+}
@@ -0,0 +1,420 @@
+//! A deliberately simple reference executor for differential testing
+//!
+//! It shares only the instruction decoding and random number generator with the emulator, every
+//! instruction is written out from its description in the documentation of [`Instruction`] and
+//! [`Quirks`], favouring obviousness over speed.
+
+use chip8::{
+    Chip8, Chip8Error, Instruction, MemoryIncrement, Quirks, Rng, StepOutcome, Variant,
+    HIRES_HEIGHT, HIRES_WIDTH, LORES_HEIGHT, LORES_WIDTH, STACK_SIZE,
+};
+
+/// The reference machine, every field is public so the harness can compare them
+///
+/// * `waiting`: The register to store the key in while `Fx0A` is blocking
+/// * `display_wait`: Set by `Dxyn` with the display wait quirk, blocks until the timers tick
+/// * `exited`: Set by `00FD`
+pub struct Reference {
+    pub mem: Vec<u8>,
+    pub display: Vec<u8>,
+    pub hires: bool,
+    pub planes: u8,
+    pub pc: u16,
+    pub i: u16,
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    pub v: [u8; 16],
+    pub keys: [bool; 16],
+    pub waiting: Option<u8>,
+    pub display_wait: bool,
+    pub exited: bool,
+    pub rpl: [u8; 16],
+    pub audio: [u8; 16],
+    pub pitch: u8,
+    pub rng: Rng,
+    pub variant: Variant,
+    pub quirks: Quirks,
+}
+
+impl Reference {
+    /// Copy the state of a freshly built emulator, which was seeded with `seed` and has no keys
+    /// pressed
+    pub fn new(chip8: &Chip8, seed: u64) -> Self {
+        Self {
+            mem: chip8.memory().to_vec(),
+            display: chip8.display().to_vec(),
+            hires: chip8.width() == HIRES_WIDTH,
+            planes: 0b01,
+            pc: chip8.pc(),
+            i: chip8.i(),
+            stack: chip8.stack().to_vec(),
+            delay: chip8.delay_timer(),
+            sound: chip8.sound_timer(),
+            v: *chip8.registers(),
+            keys: [false; 16],
+            waiting: None,
+            display_wait: false,
+            exited: false,
+            rpl: [0; 16],
+            audio: *chip8.audio_pattern(),
+            pitch: chip8.pitch(),
+            rng: Rng::new(seed),
+            variant: chip8.variant(),
+            quirks: *chip8.quirks(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            LORES_WIDTH
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            LORES_HEIGHT
+        }
+    }
+
+    pub fn down(&mut self, key: u8) {
+        self.keys[key as usize] = true;
+    }
+
+    /// Releasing a pressed key finishes a blocking `Fx0A`
+    pub fn up(&mut self, key: u8) {
+        if self.keys[key as usize] {
+            if let Some(x) = self.waiting.take() {
+                self.v[x as usize] = key;
+            }
+            self.keys[key as usize] = false;
+        }
+    }
+
+    pub fn decrease_timers(&mut self) {
+        self.display_wait = false;
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    /// Execute one instruction, the program counter is restored if it fails
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.waiting.is_some() || self.display_wait {
+            return Ok(StepOutcome::Blocked);
+        }
+
+        let pc = self.pc;
+        match self.execute() {
+            Ok(()) if self.exited => Ok(StepOutcome::Exited),
+            Ok(()) => Ok(StepOutcome::Executed),
+            Err(err) => {
+                self.pc = pc;
+                Err(err)
+            }
+        }
+    }
+
+    /// Fail with the first address of `addr..addr + len` outside of memory
+    fn bounds(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        match (addr..addr + len).find(|addr| *addr >= self.mem.len()) {
+            Some(addr) => Err(Chip8Error::MemoryOutOfBounds { addr }),
+            None => Ok(()),
+        }
+    }
+
+    fn word(&self, addr: u16) -> Result<u16, Chip8Error> {
+        self.bounds(addr as usize, 2)?;
+        Ok(u16::from_be_bytes([
+            self.mem[addr as usize],
+            self.mem[addr as usize + 1],
+        ]))
+    }
+
+    /// Skip the next instruction, which is 4 bytes long for XO-CHIP `F000 nnnn`
+    fn skip(&mut self) {
+        let long = self.variant == Variant::XoChip
+            && self.mem.get(self.pc as usize..self.pc as usize + 2) == Some(&[0xf0, 0x00]);
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+    }
+
+    /// The registers vX..=vY, counting down if X > Y
+    fn range(x: u8, y: u8) -> Vec<usize> {
+        if x <= y {
+            (x as usize..=y as usize).collect()
+        } else {
+            (y as usize..=x as usize).rev().collect()
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), Chip8Error> {
+        use Instruction::*;
+
+        let pc = self.pc;
+        let opcode = self.word(pc)?;
+        self.pc = pc.wrapping_add(2);
+
+        let instruction = match Instruction::decode(opcode) {
+            Ok(instruction) if instruction.variant() <= self.variant => instruction,
+            _ => return Err(Chip8Error::UnknownOpcode { pc, opcode }),
+        };
+
+        let v = &mut self.v;
+        match instruction {
+            Cls => {
+                for pixel in &mut self.display {
+                    *pixel &= !self.planes;
+                }
+            }
+            Ret => match self.stack.pop() {
+                Some(addr) => self.pc = addr,
+                None => return Err(Chip8Error::StackUnderflow),
+            },
+            ScrollDown(n) => self.scroll(0, n as isize),
+            ScrollUp(n) => self.scroll(0, -(n as isize)),
+            ScrollRight => self.scroll(4, 0),
+            ScrollLeft => self.scroll(-4, 0),
+            Exit => self.exited = true,
+            Low | High => {
+                self.hires = instruction == High;
+                self.display = vec![0; self.width() * self.height()];
+            }
+            Jp(addr) => self.pc = addr,
+            Call(addr) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow);
+                }
+                self.stack.push(self.pc);
+                self.pc = addr;
+            }
+            Se(x, nn) => {
+                if v[x as usize] == nn {
+                    self.skip();
+                }
+            }
+            Sne(x, nn) => {
+                if v[x as usize] != nn {
+                    self.skip();
+                }
+            }
+            SeReg(x, y) => {
+                if v[x as usize] == v[y as usize] {
+                    self.skip();
+                }
+            }
+            SneReg(x, y) => {
+                if v[x as usize] != v[y as usize] {
+                    self.skip();
+                }
+            }
+            SaveRange(x, y) => {
+                let registers = Self::range(x, y);
+                self.bounds(self.i as usize, registers.len())?;
+                for (offset, r) in registers.into_iter().enumerate() {
+                    self.mem[self.i as usize + offset] = self.v[r];
+                }
+            }
+            LoadRange(x, y) => {
+                let registers = Self::range(x, y);
+                self.bounds(self.i as usize, registers.len())?;
+                for (offset, r) in registers.into_iter().enumerate() {
+                    self.v[r] = self.mem[self.i as usize + offset];
+                }
+            }
+            Ld(x, nn) => v[x as usize] = nn,
+            Add(x, nn) => v[x as usize] = v[x as usize].wrapping_add(nn),
+            LdReg(x, y) => v[x as usize] = v[y as usize],
+            Or(x, y) | And(x, y) | Xor(x, y) => {
+                let (a, b) = (v[x as usize], v[y as usize]);
+                v[x as usize] = match instruction {
+                    Or(..) => a | b,
+                    And(..) => a & b,
+                    _ => a ^ b,
+                };
+                if self.quirks.vf_reset {
+                    v[0xf] = 0;
+                }
+            }
+            // The flag is always written after the result
+            AddReg(x, y) => {
+                let sum = v[x as usize] as u16 + v[y as usize] as u16;
+                v[x as usize] = sum as u8;
+                v[0xf] = (sum > 0xff) as u8;
+            }
+            Sub(x, y) => {
+                let (a, b) = (v[x as usize], v[y as usize]);
+                v[x as usize] = a.wrapping_sub(b);
+                v[0xf] = (a >= b) as u8;
+            }
+            Subn(x, y) => {
+                let (a, b) = (v[x as usize], v[y as usize]);
+                v[x as usize] = b.wrapping_sub(a);
+                v[0xf] = (b >= a) as u8;
+            }
+            Shr(x, y) => {
+                let source = if self.quirks.shift_vy {
+                    v[y as usize]
+                } else {
+                    v[x as usize]
+                };
+                v[x as usize] = source >> 1;
+                v[0xf] = source & 1;
+            }
+            Shl(x, y) => {
+                let source = if self.quirks.shift_vy {
+                    v[y as usize]
+                } else {
+                    v[x as usize]
+                };
+                v[x as usize] = source << 1;
+                v[0xf] = source >> 7;
+            }
+            LdI(addr) => self.i = addr,
+            JpV0(addr) => {
+                let register = if self.quirks.jump_vx { addr >> 8 } else { 0 };
+                self.pc = addr + v[register as usize] as u16;
+            }
+            Rnd(x, nn) => v[x as usize] = self.rng.next_u8() & nn,
+            Drw { x, y, n } => {
+                let (x, y) = (v[x as usize], v[y as usize]);
+                self.draw(x, y, n)?;
+                self.display_wait = self.quirks.display_wait;
+            }
+            Skp(x) => {
+                if self.keys[(v[x as usize] & 0xf) as usize] {
+                    self.skip();
+                }
+            }
+            Sknp(x) => {
+                if !self.keys[(v[x as usize] & 0xf) as usize] {
+                    self.skip();
+                }
+            }
+            LdILong => {
+                self.i = self.word(self.pc)?;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            Plane(n) => self.planes = n & 0b11,
+            Audio => {
+                let i = self.i as usize;
+                self.bounds(i, 16)?;
+                self.audio.copy_from_slice(&self.mem[i..i + 16]);
+            }
+            LdVxDt(x) | LdVxDtAlias(x) => v[x as usize] = self.delay,
+            LdKey(x) => self.waiting = Some(x),
+            LdDt(x) => self.delay = v[x as usize],
+            LdSt(x) => self.sound = v[x as usize],
+            AddI(x) => self.i = self.i.wrapping_add(v[x as usize] as u16),
+            LdFont(x) => self.i = 5 * v[x as usize] as u16,
+            LdBigFont(x) => self.i = 5 * 16 + 10 * (v[x as usize] & 0xf) as u16,
+            Bcd(x) => {
+                let i = self.i as usize;
+                self.bounds(i, 3)?;
+                let value = self.v[x as usize];
+                self.mem[i] = value / 100;
+                self.mem[i + 1] = value / 10 % 10;
+                self.mem[i + 2] = value % 10;
+            }
+            Pitch(x) => self.pitch = v[x as usize],
+            Store(x) | Load(x) => {
+                let (i, count) = (self.i as usize, x as usize + 1);
+                self.bounds(i, count)?;
+                if let Store(_) = instruction {
+                    self.mem[i..i + count].copy_from_slice(&self.v[..count]);
+                } else {
+                    self.v[..count].copy_from_slice(&self.mem[i..i + count]);
+                }
+                self.i = match self.quirks.memory {
+                    MemoryIncrement::Unchanged => self.i,
+                    MemoryIncrement::X => self.i.wrapping_add(x as u16),
+                    MemoryIncrement::XPlusOne => self.i.wrapping_add(count as u16),
+                };
+            }
+            StoreFlags(x) => {
+                let count = x as usize + 1;
+                self.rpl[..count].copy_from_slice(&self.v[..count]);
+            }
+            LoadFlags(x) => {
+                let count = x as usize + 1;
+                self.v[..count].copy_from_slice(&self.rpl[..count]);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the selected planes of every pixel by dx, dy, filling in with blank pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let old = self.display.clone();
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let moved = if from_x < 0 || from_x >= width || from_y < 0 || from_y >= height {
+                    0
+                } else {
+                    old[(from_y * width + from_x) as usize]
+                };
+
+                let pixel = &mut self.display[(y * width + x) as usize];
+                *pixel = (*pixel & !self.planes) | (moved & self.planes);
+            }
+        }
+    }
+
+    /// Xor a sprite onto the selected planes, the sprite data of each plane follows the previous
+    fn draw(&mut self, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+        let (width, height) = (self.width(), self.height());
+        let (sprite_width, sprite_height) = if n == 0 && self.variant >= Variant::SuperChip {
+            (16, 16)
+        } else {
+            (8, n as usize)
+        };
+        let sprite_bytes = sprite_width / 8 * sprite_height;
+        let planes: Vec<u8> = [0b01, 0b10]
+            .into_iter()
+            .filter(|plane| self.planes & plane != 0)
+            .collect();
+        self.bounds(self.i as usize, sprite_bytes * planes.len())?;
+
+        let (x, y) = (x as usize % width, y as usize % height);
+        self.v[0xf] = 0;
+
+        for (index, plane) in planes.into_iter().enumerate() {
+            let sprite = self.i as usize + index * sprite_bytes;
+
+            for row in 0..sprite_height {
+                for column in 0..sprite_width {
+                    let byte = self.mem[sprite + row * sprite_width / 8 + column / 8];
+                    if byte & (0x80 >> (column % 8)) == 0 {
+                        continue;
+                    }
+
+                    let (mut px, mut py) = (x + column, y + row);
+                    if px >= width || py >= height {
+                        if self.quirks.clipping {
+                            continue;
+                        }
+                        px %= width;
+                        py %= height;
+                    }
+
+                    let pixel = &mut self.display[py * width + px];
+                    if *pixel & plane != 0 {
+                        self.v[0xf] = 1;
+                    }
+                    *pixel ^= plane;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
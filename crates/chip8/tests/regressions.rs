@@ -0,0 +1,74 @@
+//! Inputs that used to panic or misbehave, found by fuzzing and differential testing
+
+use chip8::{Chip8, Chip8Error, Quirks, State, StepOutcome, Variant};
+
+#[test]
+fn oversized_roms() {
+    assert_eq!(
+        Chip8::new(vec![0; 3585]).unwrap_err(),
+        Chip8Error::RomTooLarge {
+            size: 3585,
+            max: 3584
+        }
+    );
+
+    for variant in [Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+        let max = variant.max_rom_size();
+        let too_large = Chip8::builder(vec![0; max + 1]).variant(variant).build();
+        assert!(matches!(too_large, Err(Chip8Error::RomTooLarge { .. })));
+
+        // A rom filling the memory fits
+        let chip8 = Chip8::builder(vec![0xff; max])
+            .variant(variant)
+            .build()
+            .unwrap();
+        assert_eq!(chip8.memory().len(), 0x200 + max);
+        assert_eq!(chip8.memory()[0x200..], vec![0xff; max]);
+    }
+}
+
+#[test]
+fn keys_out_of_range() {
+    // LD V0, K
+    let mut chip8 = Chip8::new(vec![0xf0, 0x0a]).unwrap();
+    chip8.cycle().unwrap();
+
+    for key in [0x10, 0x80, 0xff] {
+        chip8.down(key);
+        chip8.up(key);
+    }
+    assert!(matches!(chip8.state(), State::GetKey(0)));
+}
+
+#[test]
+fn errors_repeat() {
+    // The pc falls off the end of memory in the middle of an instruction
+    let mut chip8 = Chip8::new(vec![0x1f, 0xff]).unwrap();
+    chip8.cycle().unwrap();
+    let err = Chip8Error::MemoryOutOfBounds { addr: 0x1000 };
+    assert_eq!(chip8.cycle(), Err(err));
+    assert_eq!(chip8.cycle(), Err(err));
+    assert_eq!(chip8.pc(), 0xfff);
+
+    // A sprite partly outside of memory leaves vF unchanged
+    let mut chip8 = Chip8::new(vec![0x6f, 0x07, 0xaf, 0xff, 0xd0, 0x05]).unwrap();
+    chip8.cycle().unwrap();
+    chip8.cycle().unwrap();
+    let err = Chip8Error::MemoryOutOfBounds { addr: 0x1000 };
+    assert_eq!(chip8.cycle(), Err(err));
+    assert_eq!(chip8.cycle(), Err(err));
+    assert_eq!((chip8.pc(), chip8.registers()[0xf]), (0x204, 7));
+}
+
+#[test]
+fn empty_sprite_out_of_bounds() {
+    // LD I, 0xFFF; ADD I, V0 with v0 = 0xFF; DRW V0, V0, 0 draws nothing on CHIP-8
+    let mut chip8 = Chip8::builder(vec![0xaf, 0xff, 0x60, 0xff, 0xf0, 0x1e, 0xd0, 0x00])
+        .quirks(Quirks::COSMAC_VIP)
+        .build()
+        .unwrap();
+    for _ in 0..4 {
+        assert_eq!(chip8.cycle(), Ok(StepOutcome::Executed));
+    }
+    assert_eq!(chip8.i(), 0x10fe);
+}
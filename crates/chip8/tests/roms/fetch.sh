@@ -0,0 +1,37 @@
+#!/bin/sh
+# Download the roms of Timendus's chip8-test-suite next to this script
+#
+# The roms are downloaded from the suite commit in SUITE_COMMIT and checked against SHA256SUMS, so
+# the golden bitmaps always match the same roms. Without a SUITE_COMMIT the current commit of the
+# suite is pinned, commit both files with the golden bitmaps blessed from those roms.
+set -eu
+
+repo="https://github.com/Timendus/chip8-test-suite"
+roms="1-chip8-logo.ch8 2-ibm-logo.ch8 3-corax+.ch8 4-flags.ch8 5-quirks.ch8 6-keypad.ch8"
+dir="$(dirname "$0")"
+
+if [ -f "$dir/SUITE_COMMIT" ]; then
+    commit="$(cat "$dir/SUITE_COMMIT")"
+else
+    commit="$(git ls-remote "$repo" refs/heads/main | cut -f1)"
+    if [ -z "$commit" ]; then
+        echo "Could not find the latest commit of $repo" >&2
+        exit 1
+    fi
+    echo "$commit" > "$dir/SUITE_COMMIT"
+    echo "Pinned the suite to $commit"
+fi
+
+for rom in $roms; do
+    curl -fsSL -o "$dir/$rom" "https://raw.githubusercontent.com/Timendus/chip8-test-suite/$commit/bin/$rom"
+    echo "Downloaded $rom"
+done
+
+cd "$dir"
+if [ -f SHA256SUMS ]; then
+    sha256sum -c SHA256SUMS
+else
+    # shellcheck disable=SC2086
+    sha256sum $roms > SHA256SUMS
+    echo "Wrote the checksums of the roms to SHA256SUMS"
+fi
@@ -0,0 +1,223 @@
+//! Golden image tests against Timendus's chip8-test-suite
+//!
+//! Each test rom runs for a fixed number of frames with [`Chip8::run_frame`] at the default
+//! instructions per frame, the same timing as the frontends, and the display is compared to a
+//! golden bitmap in `tests/golden`. The roms are not checked in, so the tests are ignored by
+//! default and read the roms from `tests/roms` once `tests/roms/fetch.sh` downloaded them. Missing
+//! roms and golden bitmaps fail the tests.
+//!
+//! Run with `CHIP8_BLESS=1` to write the golden bitmaps from the current output instead.
+
+use std::path::{Path, PathBuf};
+
+use chip8::{Chip8, FrameEnd, Variant, DEFAULT_IPF};
+
+/// The address the suite reads to select a test without the menu
+const TEST_SELECT: usize = 0x1ff;
+
+/// A test rom and how to run it
+///
+/// * `rom`: The file name of the rom in `tests/roms`
+/// * `golden`: The file name of the golden bitmap in `tests/golden`
+/// * `variant`: The variant to run the rom as, which also selects the quirks
+/// * `frames`: The number of frames to run
+/// * `select`: The test to select by writing to [`TEST_SELECT`] before starting
+/// * `keys`: Keys held down, the key, the frame it is pressed and the frame it is released
+struct Case {
+    rom: &'static str,
+    golden: &'static str,
+    variant: Variant,
+    frames: u32,
+    select: Option<u8>,
+    keys: &'static [(u8, u32, u32)],
+}
+
+impl Case {
+    const fn new(rom: &'static str, golden: &'static str, variant: Variant, frames: u32) -> Self {
+        Self {
+            rom,
+            golden,
+            variant,
+            frames,
+            select: None,
+            keys: &[],
+        }
+    }
+}
+
+fn dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+/// Run a rom and draw the final display as ASCII art
+fn run(case: &Case) -> String {
+    let path = dir("roms").join(case.rom);
+    let rom = std::fs::read(&path).unwrap_or_else(|err| {
+        panic!(
+            "Could not read {}, download the roms with tests/roms/fetch.sh: {}",
+            path.display(),
+            err
+        )
+    });
+
+    let mut chip8 = Chip8::builder(rom)
+        .variant(case.variant)
+        .seed(0)
+        .build()
+        .unwrap();
+    if let Some(select) = case.select {
+        chip8.memory_mut()[TEST_SELECT] = select;
+    }
+
+    for frame in 0..case.frames {
+        for &(key, down, up) in case.keys {
+            if frame == down {
+                chip8.down(key);
+            }
+            if frame == up {
+                chip8.up(key);
+            }
+        }
+
+        match chip8.run_frame(DEFAULT_IPF) {
+            Ok(result) if result.end == FrameEnd::Exited => break,
+            Ok(_) => (),
+            Err(err) => panic!("{} failed in frame {}: {}", case.rom, frame, err),
+        }
+    }
+
+    let mut art = String::new();
+    for row in chip8.display().chunks(chip8.width()) {
+        art.extend(row.iter().map(|pixel| match pixel {
+            0 => '.',
+            1 => '#',
+            2 => '+',
+            _ => '@',
+        }));
+        art.push('\n');
+    }
+    art
+}
+
+/// Describe the rows that differ between the golden and actual display
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    let mut report = String::new();
+    if expected.len() != actual.len()
+        || expected.first().map(|l| l.len()) != actual.first().map(|l| l.len())
+    {
+        report += &format!(
+            "the resolution differs, expected {}x{} but got {}x{}\n",
+            expected.first().map_or(0, |l| l.len()),
+            expected.len(),
+            actual.first().map_or(0, |l| l.len()),
+            actual.len()
+        );
+    }
+
+    let mut pixels = 0;
+    report += "row  expected | actual\n";
+    for row in 0..expected.len().max(actual.len()) {
+        let e = expected.get(row).copied().unwrap_or_default();
+        let a = actual.get(row).copied().unwrap_or_default();
+        let differing =
+            e.chars().zip(a.chars()).filter(|(e, a)| e != a).count() + e.len().abs_diff(a.len());
+        pixels += differing;
+
+        let marker = if differing > 0 { '>' } else { ' ' };
+        report += &format!("{}{:3} {} | {}\n", marker, row, e, a);
+    }
+
+    format!("{} pixels differ, rows marked with >\n{}", pixels, report)
+}
+
+/// Run every case and compare them to their golden bitmaps, or write the golden bitmaps when
+/// blessing
+fn check(cases: &[Case]) {
+    let bless = std::env::var_os("CHIP8_BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for case in cases {
+        let actual = run(case);
+        let golden = dir("golden").join(case.golden);
+
+        if bless {
+            std::fs::create_dir_all(dir("golden")).unwrap();
+            std::fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+
+        match std::fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => (),
+            Ok(expected) => failures.push(format!(
+                "{} does not match {}\n{}",
+                case.rom,
+                golden.display(),
+                diff(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!(
+                "{} has no golden bitmap at {}, run with CHIP8_BLESS=1 to create it\n{}",
+                case.rom,
+                golden.display(),
+                actual
+            )),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+#[ignore = "needs the suite roms, download them with tests/roms/fetch.sh"]
+fn logos() {
+    check(&[
+        Case::new("1-chip8-logo.ch8", "1-chip8-logo.txt", Variant::Chip8, 125),
+        Case::new("2-ibm-logo.ch8", "2-ibm-logo.txt", Variant::Chip8, 125),
+    ]);
+}
+
+#[test]
+#[ignore = "needs the suite roms, download them with tests/roms/fetch.sh"]
+fn opcodes() {
+    check(&[
+        Case::new("3-corax+.ch8", "3-corax+.txt", Variant::Chip8, 250),
+        Case::new("4-flags.ch8", "4-flags.txt", Variant::Chip8, 500),
+    ]);
+}
+
+#[test]
+#[ignore = "needs the suite roms, download them with tests/roms/fetch.sh"]
+fn quirks() {
+    let quirks = |golden, variant, select| Case {
+        select: Some(select),
+        ..Case::new("5-quirks.ch8", golden, variant, 2_500)
+    };
+
+    check(&[
+        quirks("5-quirks-chip8.txt", Variant::Chip8, 1),
+        quirks("5-quirks-schip.txt", Variant::SuperChip, 2),
+        quirks("5-quirks-xochip.txt", Variant::XoChip, 3),
+    ]);
+}
+
+#[test]
+#[ignore = "needs the suite roms, download them with tests/roms/fetch.sh"]
+fn keypad() {
+    let keypad = |golden, select, keys| Case {
+        select: Some(select),
+        keys,
+        ..Case::new("6-keypad.ch8", golden, Variant::Chip8, 500)
+    };
+
+    check(&[
+        // Ex9E and ExA1 highlight the keys that are held
+        keypad("6-keypad-ex9e.txt", 1, &[(0x5, 20, 400), (0xA, 20, 400)]),
+        keypad("6-keypad-exa1.txt", 2, &[(0x5, 20, 400), (0xA, 20, 400)]),
+        // Fx0A waits for a key to be pressed and released
+        keypad("6-keypad-fx0a.txt", 3, &[(0x5, 60, 70)]),
+    ]);
+}
@@ -0,0 +1,51 @@
+use chip8::{Chip8, StepOutcome, TraceFormat, Tracer, Variant, BINARY_RECORD_SIZE};
+
+/// Trace a rom until it exits
+fn trace(rom: &[u8], format: TraceFormat) -> Vec<u8> {
+    let mut chip8 = Chip8::builder(rom.to_vec())
+        .variant(Variant::SuperChip)
+        .build()
+        .unwrap();
+    let mut out = Vec::new();
+    let mut tracer = Tracer::new(&mut out, format);
+
+    loop {
+        tracer.trace(&chip8).unwrap();
+        if chip8.cycle().unwrap() == StepOutcome::Exited {
+            break;
+        }
+    }
+    // Nothing is traced once the emulator has stopped executing
+    tracer.trace(&chip8).unwrap();
+
+    out
+}
+
+#[test]
+fn formats() {
+    let rom = [
+        0x60, 0x2a, // LD V0, 0x2a
+        0xa3, 0x00, // LD I, 0x300
+        0xf0, 0x15, // LD DT, V0
+        0x00, 0xfd, // EXIT
+    ];
+
+    let text = String::from_utf8(trace(&rom, TraceFormat::Text)).unwrap();
+    let zeros = "00,".repeat(14);
+    assert_eq!(
+        text,
+        format!(
+            "PC=0200 OP=602A I=0000 V=00,{zeros}00 DT=00 ST=00 LD V0, 0x2a\n\
+             PC=0202 OP=A300 I=0000 V=2A,{zeros}00 DT=00 ST=00 LD I, 0x300\n\
+             PC=0204 OP=F015 I=0300 V=2A,{zeros}00 DT=00 ST=00 LD DT, V0\n\
+             PC=0206 OP=00FD I=0300 V=2A,{zeros}00 DT=2A ST=00 EXIT\n"
+        )
+    );
+
+    let binary = trace(&rom, TraceFormat::Binary);
+    assert_eq!(binary.len(), 4 * BINARY_RECORD_SIZE);
+    let last = &binary[3 * BINARY_RECORD_SIZE..];
+    assert_eq!(last[..6], [0x02, 0x06, 0x00, 0xfd, 0x03, 0x00]);
+    assert_eq!(last[6], 0x2a);
+    assert_eq!(last[22..], [0x2a, 0x00]);
+}
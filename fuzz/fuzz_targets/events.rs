@@ -0,0 +1,47 @@
+#![no_main]
+
+use chip8_fuzz::{build, frame, timing, variant};
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+
+/// Something to do to the emulator, keys are not limited to 0-F
+#[derive(Debug, Arbitrary)]
+enum Event {
+    Down(u8),
+    Up(u8),
+    Frames(u8),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    variant: u8,
+    timing: u8,
+    seed: u64,
+    rom: Vec<u8>,
+    events: Vec<Event>,
+}
+
+fuzz_target!(|input: Input| {
+    let Some(mut chip8) = build(
+        input.rom,
+        variant(input.variant),
+        timing(input.timing),
+        input.seed,
+    ) else {
+        return;
+    };
+
+    for event in input.events {
+        match event {
+            Event::Down(key) => chip8.down(key),
+            Event::Up(key) => chip8.up(key),
+            Event::Frames(frames) => {
+                for _ in 0..frames {
+                    if !frame(&mut chip8) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use chip8_fuzz::{build, frame, timing, variant};
+use libfuzzer_sys::fuzz_target;
+
+/// The number of frames to run each rom for
+const FRAMES: usize = 1_250;
+
+// The first byte selects the variant and timing and the rest is the rom
+fuzz_target!(|data: &[u8]| {
+    let Some((&first, rom)) = data.split_first() else {
+        return;
+    };
+    let Some(mut chip8) = build(rom.to_vec(), variant(first), timing(first / 3), 0) else {
+        return;
+    };
+
+    for _ in 0..FRAMES {
+        if !frame(&mut chip8) {
+            break;
+        }
+    }
+});
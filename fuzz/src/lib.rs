@@ -0,0 +1,47 @@
+use chip8::{Chip8, FrameEnd, Timing, Variant, DEFAULT_IPF};
+
+/// Pick a variant from a byte
+pub fn variant(byte: u8) -> Variant {
+    [Variant::Chip8, Variant::SuperChip, Variant::XoChip][byte as usize % 3]
+}
+
+/// Pick a timing from a byte
+pub fn timing(byte: u8) -> Timing {
+    [Timing::Fixed, Timing::CosmacVip][byte as usize % 2]
+}
+
+/// Build an emulator, a rom that does not fit has to be rejected instead of panicking
+pub fn build(rom: Vec<u8>, variant: Variant, timing: Timing, seed: u64) -> Option<Chip8> {
+    let size = rom.len();
+    match Chip8::builder(rom)
+        .variant(variant)
+        .timing(timing)
+        .seed(seed)
+        .build()
+    {
+        Ok(chip8) => {
+            assert!(size <= variant.max_rom_size());
+            Some(chip8)
+        }
+        Err(err) => {
+            assert!(size > variant.max_rom_size(), "{}", err);
+            None
+        }
+    }
+}
+
+/// Run a frame like the frontends do, `false` once the emulator can not continue
+///
+/// A failing instruction must leave the emulator unchanged, so retrying the frame fails the same
+/// way.
+pub fn frame(chip8: &mut Chip8) -> bool {
+    match chip8.run_frame(DEFAULT_IPF) {
+        Ok(frame) => frame.end != FrameEnd::Exited,
+        Err(err) => {
+            let pc = chip8.pc();
+            assert_eq!(chip8.run_frame(DEFAULT_IPF), Err(err));
+            assert_eq!(chip8.pc(), pc);
+            false
+        }
+    }
+}
@@ -5,6 +5,7 @@ let
   ld_library_path = pkgs.lib.makeLibraryPath (
     with pkgs;
     [
+      alsa-lib
       libxkbcommon
       wayland
       vulkan-loader
@@ -18,6 +19,7 @@ in
 pkgs.mkShell {
   packages = with pkgs; [
     pkg-config
+    alsa-lib
     gtk3
     wrapGAppsHook
   ];